@@ -7,14 +7,19 @@ use axum::{
 use entity::prelude::*;
 use ethers::utils::hex;
 use merkletree::{
+    absence_proof::LeafProof,
     account_with_balance::AccountWithBalance,
-    merkle_tree::{generate_proof_of_absense, generate_proof_of_inclusion},
+    backend::{BackendKind, BackendProof},
+    merkle_tree::Error,
 };
 use sea_orm::{error::DbErr, ActiveModelTrait, DatabaseConnection};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use services::accounts::queries::get_accounts_by_proposal_id;
-use services::proposals::queries::{get_proposal_with_accounts, insert_proposal, list_proposals};
+use services::proposals::queries::{
+    get_proposal_tree, get_proposal_with_accounts, insert_proposal, list_proposals,
+};
+use services::utils::errors::ServiceError;
 use services::utils::pagination::Pagination;
 
 use crate::AppState;
@@ -58,6 +63,8 @@ pub struct NewProposal {
     pub author: String,
     pub block_number: i64,
     pub ipfs_hash: String,
+    #[serde(default)]
+    pub backend: BackendKind,
     pub accounts: Vec<NewAccount>,
 }
 
@@ -100,62 +107,75 @@ pub async fn get_proof_of_inclusion(
     Path(proposal_id): Path<i32>,
     Json(account): Json<AccountWithBalance>,
 ) -> (StatusCode, Json<Value>) {
-    let accounts = get_accounts_by_proposal_id(&state.conn, proposal_id)
+    let res = get_proposal_tree(&state.conn, proposal_id)
         .await
-        .unwrap();
-    let accounts_with_balance: Vec<AccountWithBalance> = accounts
-        .iter()
-        .map(|account| {
-            AccountWithBalance::new(
-                account.address.clone().as_str(),
-                account.balance.clone().as_str(),
-            )
-        })
-        .collect();
-
-    let res = generate_proof_of_inclusion(&accounts_with_balance, account);
+        .and_then(|tree| Ok(tree.generate_proof_of_inclusion(&account)?));
 
     match res {
-        Ok(proof) => (StatusCode::OK, Json(json!({"proof": hex::encode(proof)}))),
+        Ok(proof) => (StatusCode::OK, Json(json!({"proof": proof_to_json(proof)}))),
         Err(e) => (
-            StatusCode::BAD_REQUEST,
+            service_error_status(&e),
             Json(json!({"error": format!("{}", e)})),
         ),
     }
 }
 
+fn leaf_proof_to_json(leaf: &Option<LeafProof>) -> Value {
+    match leaf {
+        Some(leaf) => json!({
+            "account": leaf.account,
+            "index": leaf.index,
+            "proof": hex::encode(&leaf.proof),
+        }),
+        None => Value::Null,
+    }
+}
+
+/// Accounts missing from the tree are not found, other tree errors come from
+/// invalid proposal data and database errors are internal
+fn service_error_status(error: &ServiceError) -> StatusCode {
+    match error {
+        ServiceError::MerkleTree(Error::AccountNotFoundError) => StatusCode::NOT_FOUND,
+        ServiceError::MerkleTree(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        ServiceError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Hashes of alphabetic tree proofs are hex encoded, the other backends
+/// are returned tagged with their type
+fn proof_to_json(proof: BackendProof) -> Value {
+    match proof {
+        BackendProof::AlphabeticInclusion {
+            index,
+            leaf_count,
+            proof,
+        } => json!({
+            "index": index,
+            "leaf_count": leaf_count,
+            "proof": hex::encode(proof),
+        }),
+        BackendProof::AlphabeticAbsence(proof) => json!({
+            "left": leaf_proof_to_json(&proof.left),
+            "right": leaf_proof_to_json(&proof.right),
+            "leaf_count": proof.leaf_count,
+        }),
+        proof => json!(proof),
+    }
+}
+
 pub async fn get_proof_of_absense(
     state: State<AppState>,
     Path(proposal_id): Path<i32>,
     Json(account): Json<AccountWithBalance>,
 ) -> (StatusCode, Json<Value>) {
-    let accounts = get_accounts_by_proposal_id(&state.conn, proposal_id)
+    let res = get_proposal_tree(&state.conn, proposal_id)
         .await
-        .unwrap();
-    let accounts_with_balance: Vec<AccountWithBalance> = accounts
-        .iter()
-        .map(|account| {
-            AccountWithBalance::new(
-                account.address.clone().as_str(),
-                account.balance.clone().as_str(),
-            )
-        })
-        .collect();
-
-    let res = generate_proof_of_absense(&accounts_with_balance, account);
+        .and_then(|tree| Ok(tree.generate_proof_of_absense(&account)?));
 
     match res {
-        Ok(proof) => {
-            let hex_proof = match proof {
-                (Some(left), Some(right)) => (hex::encode(left), hex::encode(right)),
-                (Some(left), None) => (hex::encode(left), "".to_string()),
-                (None, Some(right)) => ("".to_string(), hex::encode(right)),
-                (None, None) => ("".to_string(), "".to_string()),
-            };
-            (StatusCode::OK, Json(json!({"proof": hex_proof })))
-        }
+        Ok(proof) => (StatusCode::OK, Json(json!({"proof": proof_to_json(proof)}))),
         Err(e) => (
-            StatusCode::BAD_REQUEST,
+            service_error_status(&e),
             Json(json!({"error": format!("{}", e)})),
         ),
     }
@@ -16,6 +16,8 @@ pub struct Model {
     #[sea_orm(column_type = "Text")]
     #[serde(skip_deserializing)]
     pub root_hash: String,
+    #[sea_orm(column_type = "Text")]
+    pub backend: String,
     #[serde(skip_deserializing)]
     pub created_at: Option<DateTime>,
     #[sea_orm(ignore)]
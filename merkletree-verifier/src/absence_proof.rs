@@ -0,0 +1,213 @@
+use alloc::vec::Vec;
+
+use crate::account_with_balance::{AccountWithBalance, Address, LeafEncoding};
+use crate::verification::{verify_leaf_inclusion, Error, HashMode};
+
+/// Leaf of the tree with its position and inclusion proof
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeafProof {
+    pub account: AccountWithBalance,
+    pub index: usize,
+    pub proof: Vec<u8>,
+}
+
+impl LeafProof {
+    pub fn verify(
+        &self,
+        root: [u8; 32],
+        leaf_count: usize,
+        hash_mode: HashMode,
+        leaf_encoding: LeafEncoding,
+    ) -> Result<bool, Error> {
+        verify_leaf_inclusion(
+            hash_mode,
+            root,
+            self.account.leaf_hash(leaf_encoding),
+            &self.proof,
+            self.index,
+            leaf_count,
+        )
+    }
+}
+
+/// Proof that an account is not a leaf of the tree: the leaves on both sides
+/// of where it would be are consecutive. An account sorting before the first
+/// or after the last leaf only has one neighbour.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AbsenceProof {
+    pub left: Option<LeafProof>,
+    pub right: Option<LeafProof>,
+    pub leaf_count: usize,
+    pub hash_mode: HashMode,
+    pub leaf_encoding: LeafEncoding,
+}
+
+impl AbsenceProof {
+    pub fn verify(&self, root: [u8; 32], account: &AccountWithBalance) -> Result<bool, Error> {
+        self.verify_gap(root, |leaf| leaf < account, |leaf| account < leaf)
+    }
+
+    /// Checks that no leaf of the tree has the given address, whatever its
+    /// balance. Only holds for trees ordered by address.
+    pub fn verify_address(&self, root: [u8; 32], address: Address) -> Result<bool, Error> {
+        self.verify_gap(
+            root,
+            |leaf| leaf.address < address,
+            |leaf| address < leaf.address,
+        )
+    }
+
+    fn verify_gap(
+        &self,
+        root: [u8; 32],
+        sorts_before: impl Fn(&AccountWithBalance) -> bool,
+        sorts_after: impl Fn(&AccountWithBalance) -> bool,
+    ) -> Result<bool, Error> {
+        if self.leaf_count == 0 {
+            return Err(Error::EmptyAccountsListError);
+        }
+        // Commutative proofs don't pin the leaf index, so adjacency can't be checked
+        if self.hash_mode == HashMode::Commutative {
+            return Err(Error::OrderedHashingRequiredError);
+        }
+        for neighbour in [&self.left, &self.right].into_iter().flatten() {
+            if !neighbour.verify(root, self.leaf_count, self.hash_mode, self.leaf_encoding)? {
+                return Ok(false);
+            }
+        }
+
+        Ok(is_gap(
+            self.left.as_ref().map(|x| (x.index, &x.account)),
+            self.right.as_ref().map(|x| (x.index, &x.account)),
+            self.leaf_count,
+            sorts_before,
+            sorts_after,
+        ))
+    }
+}
+
+/// Whether proven neighbours at the given indices surround a gap of the sorted
+/// leaves: they are consecutive, or the first or last leaf, and sort around
+/// the missing value
+pub fn is_gap<A>(
+    left: Option<(usize, &A)>,
+    right: Option<(usize, &A)>,
+    leaf_count: usize,
+    sorts_before: impl Fn(&A) -> bool,
+    sorts_after: impl Fn(&A) -> bool,
+) -> bool {
+    match (left, right) {
+        (Some((left_index, left)), Some((right_index, right))) => {
+            left_index.checked_add(1) == Some(right_index)
+                && sorts_before(left)
+                && sorts_after(right)
+        }
+        (Some((left_index, left)), None) => {
+            left_index.checked_add(1) == Some(leaf_count) && sorts_before(left)
+        }
+        (None, Some((right_index, right))) => right_index == 0 && sorts_after(right),
+        (None, None) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use merkletree::absence_proof as full;
+    use merkletree::alphabetic_merkle_tree::{AlphabeticMerkleTree, OrderingMode};
+
+    use crate::test_utils::{accounts, full_options, to_account, to_full_account, tree_options};
+
+    fn to_leaf_proof(proof: &full::LeafProof) -> LeafProof {
+        LeafProof {
+            account: to_account(&proof.account),
+            index: proof.index,
+            proof: proof.proof.clone(),
+        }
+    }
+
+    fn to_absence_proof(proof: &full::AbsenceProof, options_index: usize) -> AbsenceProof {
+        let options = tree_options()[options_index];
+        AbsenceProof {
+            left: proof.left.as_ref().map(to_leaf_proof),
+            right: proof.right.as_ref().map(to_leaf_proof),
+            leaf_count: proof.leaf_count,
+            hash_mode: options.hash_mode,
+            leaf_encoding: options.leaf_encoding,
+        }
+    }
+
+    #[test]
+    fn test_verify_merkletree_absence_proofs() {
+        let accounts = accounts(60);
+        let (present, absent) = accounts.split_at(40);
+        let full_present: Vec<_> = present.iter().map(to_full_account).collect();
+        // Absence proofs need ordered hashing
+        for options_index in [0, 1] {
+            let options = full_options(tree_options()[options_index]);
+            let tree = AlphabeticMerkleTree::with_options(&full_present, options).unwrap();
+            for account in absent {
+                let full_proof = tree
+                    .generate_proof_of_absense(&to_full_account(account))
+                    .unwrap();
+                let proof = to_absence_proof(&full_proof, options_index);
+                assert!(proof.verify(tree.root(), account).unwrap());
+                // Neighbours of another gap don't prove the account absent
+                assert!(!proof.verify(tree.root(), &present[0]).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn test_verify_merkletree_address_absence_proofs() {
+        let accounts = accounts(30);
+        let (present, absent) = accounts.split_at(20);
+        let full_present: Vec<_> = present.iter().map(to_full_account).collect();
+        let options = merkletree::alphabetic_merkle_tree::TreeOptions {
+            ordering: OrderingMode::Address,
+            ..Default::default()
+        };
+        let tree = AlphabeticMerkleTree::with_options(&full_present, options).unwrap();
+        for account in absent {
+            let full_proof = tree
+                .generate_proof_of_absense(&to_full_account(account))
+                .unwrap();
+            let proof = to_absence_proof(&full_proof, 0);
+            assert!(proof.verify_address(tree.root(), account.address).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_reject_commutative_and_non_adjacent_proofs() {
+        let accounts = accounts(10);
+        let full_accounts: Vec<_> = accounts.iter().map(to_full_account).collect();
+        let tree = AlphabeticMerkleTree::new(&full_accounts).unwrap();
+        let sorted: Vec<_> = tree.accounts().iter().map(to_account).collect();
+        let leaf_proof = |index: usize| LeafProof {
+            account: sorted[index],
+            index,
+            proof: tree
+                .generate_proof_of_inclusion(&tree.accounts()[index])
+                .unwrap(),
+        };
+
+        // Leaves 2 and 4 are both proven, but leaf 3 sits between them
+        let mut between = sorted[3];
+        between.balance[31] ^= 1;
+        let mut proof = AbsenceProof {
+            left: Some(leaf_proof(2)),
+            right: Some(leaf_proof(4)),
+            leaf_count: sorted.len(),
+            hash_mode: HashMode::Ordered,
+            leaf_encoding: LeafEncoding::Packed,
+        };
+        assert!(!proof.verify(tree.root(), &between).unwrap());
+
+        proof.hash_mode = HashMode::Commutative;
+        assert_eq!(
+            proof.verify(tree.root(), &between),
+            Err(Error::OrderedHashingRequiredError)
+        );
+    }
+}
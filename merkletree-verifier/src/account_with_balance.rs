@@ -0,0 +1,147 @@
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+
+use tiny_keccak::{Hasher, Keccak};
+
+pub type Address = [u8; 20];
+
+/// Same layout as the sort key of `merkletree`: the address, the balance
+/// without leading zero bytes padded on the right, and the length of the
+/// balance
+pub type SortKey = [u8; 53];
+
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Keccak::v256();
+    let mut output = [0u8; 32];
+    hasher.update(data);
+    hasher.finalize(&mut output);
+    output
+}
+
+/// How an account is turned into a leaf hash
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LeafEncoding {
+    /// `keccak256(encode_packed(address, balance))`
+    #[default]
+    Packed,
+    /// `keccak256(keccak256(abi.encode(address, uint256)))`
+    Standard,
+}
+
+/// Account with its balance as a big-endian `uint256`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct AccountWithBalance {
+    pub address: Address,
+    pub balance: [u8; 32],
+}
+
+impl AccountWithBalance {
+    pub fn new(address: Address, balance: u128) -> Self {
+        let mut balance_bytes = [0u8; 32];
+        balance_bytes[16..].copy_from_slice(&balance.to_be_bytes());
+        Self {
+            address,
+            balance: balance_bytes,
+        }
+    }
+
+    /// Balance without leading zero bytes, as `encode_packed` writes it
+    fn trimmed_balance(&self) -> &[u8] {
+        let zeros = self.balance.iter().take_while(|x| **x == 0).count();
+        &self.balance[zeros..]
+    }
+
+    pub fn packed(&self) -> Vec<u8> {
+        let mut packed = Vec::with_capacity(52);
+        packed.extend_from_slice(&self.address);
+        packed.extend_from_slice(self.trimmed_balance());
+        packed
+    }
+
+    pub fn abi_encoded(&self) -> [u8; 64] {
+        let mut encoded = [0u8; 64];
+        encoded[12..32].copy_from_slice(&self.address);
+        encoded[32..].copy_from_slice(&self.balance);
+        encoded
+    }
+
+    /// Key with the same order as `packed()`, built without allocating
+    pub fn sort_key(&self) -> SortKey {
+        let balance = self.trimmed_balance();
+        let mut key = [0u8; 53];
+        key[..20].copy_from_slice(&self.address);
+        key[20..20 + balance.len()].copy_from_slice(balance);
+        key[52] = balance.len() as u8;
+        key
+    }
+
+    pub fn leaf_hash(&self, leaf_encoding: LeafEncoding) -> [u8; 32] {
+        match leaf_encoding {
+            LeafEncoding::Packed => keccak256(&self.packed()),
+            LeafEncoding::Standard => keccak256(&keccak256(&self.abi_encoded())),
+        }
+    }
+}
+
+impl Ord for AccountWithBalance {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.sort_key().cmp(&other.sort_key())
+    }
+}
+
+impl PartialOrd for AccountWithBalance {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_utils::{accounts, full_accounts};
+
+    #[test]
+    fn test_leaf_hashes_match_merkletree() {
+        use merkletree::leaf::Leaf;
+
+        for (account, full_account) in accounts(40).iter().zip(full_accounts(40)) {
+            assert_eq!(account.packed(), full_account.packed());
+            assert_eq!(account.abi_encoded()[..], full_account.abi_encoded());
+            assert_eq!(account.sort_key(), full_account.sort_key());
+            for (encoding, full_encoding) in [
+                (
+                    LeafEncoding::Packed,
+                    merkletree::account_with_balance::LeafEncoding::Packed,
+                ),
+                (
+                    LeafEncoding::Standard,
+                    merkletree::account_with_balance::LeafEncoding::Standard,
+                ),
+            ] {
+                assert_eq!(
+                    account.leaf_hash(encoding),
+                    Leaf::leaf_hash(&full_account, full_encoding)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_sort_order_matches_merkletree() {
+        let accounts = accounts(40);
+        let full_accounts = full_accounts(40);
+        for (a, full_a) in accounts.iter().zip(&full_accounts) {
+            for (b, full_b) in accounts.iter().zip(&full_accounts) {
+                assert_eq!(a.cmp(b), full_a.cmp(full_b));
+            }
+        }
+    }
+
+    #[test]
+    fn test_zero_balance_is_packed_as_address() {
+        let account = AccountWithBalance::new([7; 20], 0);
+        assert_eq!(account.packed(), [7; 20]);
+        assert_eq!(AccountWithBalance::new([7; 20], 256).packed()[20..], [1, 0]);
+    }
+}
@@ -0,0 +1,15 @@
+//! Verification of `merkletree` inclusion and absence proofs for
+//! `AccountWithBalance` leaves, with `no_std` and `alloc` only.
+//!
+//! Keccak-256 comes from `tiny-keccak`, so proofs can be checked in wallets,
+//! relayers and WASM without pulling in `ethers`, `rand` or `serde`.
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+pub mod absence_proof;
+pub mod account_with_balance;
+pub mod verification;
+
+#[cfg(test)]
+mod test_utils;
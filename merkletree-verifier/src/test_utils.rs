@@ -0,0 +1,69 @@
+use merkletree::account_with_balance as full;
+use merkletree::alphabetic_merkle_tree as full_tree;
+
+use crate::account_with_balance::{keccak256, AccountWithBalance, LeafEncoding};
+use crate::verification::{HashMode, TreeOptions};
+
+/// Deterministic accounts, with balances of every length including zero
+pub fn accounts(count: usize) -> Vec<AccountWithBalance> {
+    (0..count)
+        .map(|i| {
+            let seed = keccak256(&i.to_be_bytes());
+            let mut balance = [0u8; 32];
+            let balance_len = i % 33;
+            balance[32 - balance_len..].copy_from_slice(&seed[..balance_len]);
+            AccountWithBalance {
+                address: seed[12..].try_into().unwrap(),
+                balance,
+            }
+        })
+        .collect()
+}
+
+/// The same accounts as [`accounts`], as `merkletree` accounts
+pub fn full_accounts(count: usize) -> Vec<full::AccountWithBalance> {
+    accounts(count).iter().map(to_full_account).collect()
+}
+
+pub fn to_full_account(account: &AccountWithBalance) -> full::AccountWithBalance {
+    let mut record = [0u8; full::ACCOUNT_RECORD_LEN];
+    record[..20].copy_from_slice(&account.address);
+    record[20..].copy_from_slice(&account.balance);
+    full::AccountWithBalance::from_record(&record)
+}
+
+pub fn to_account(account: &full::AccountWithBalance) -> AccountWithBalance {
+    let mut balance = [0u8; 32];
+    account.balance.to_big_endian(&mut balance);
+    AccountWithBalance {
+        address: account.address.0,
+        balance,
+    }
+}
+
+pub fn tree_options() -> Vec<TreeOptions> {
+    let mut options = vec![];
+    for hash_mode in [HashMode::Ordered, HashMode::Commutative] {
+        for leaf_encoding in [LeafEncoding::Packed, LeafEncoding::Standard] {
+            options.push(TreeOptions {
+                hash_mode,
+                leaf_encoding,
+            });
+        }
+    }
+    options
+}
+
+pub fn full_options(options: TreeOptions) -> full_tree::TreeOptions {
+    full_tree::TreeOptions {
+        hash_mode: match options.hash_mode {
+            HashMode::Ordered => full_tree::HashMode::Ordered,
+            HashMode::Commutative => full_tree::HashMode::Commutative,
+        },
+        leaf_encoding: match options.leaf_encoding {
+            LeafEncoding::Packed => full::LeafEncoding::Packed,
+            LeafEncoding::Standard => full::LeafEncoding::Standard,
+        },
+        ..Default::default()
+    }
+}
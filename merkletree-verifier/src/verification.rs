@@ -0,0 +1,295 @@
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::account_with_balance::{keccak256, AccountWithBalance, LeafEncoding};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    EmptyAccountsListError,
+    LeafIndexOutOfRangeError,
+    MerkleProofParsingError,
+    OrderedHashingRequiredError,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Error::EmptyAccountsListError => "Accounts list is empty",
+            Error::LeafIndexOutOfRangeError => "Leaf index is out of range",
+            Error::MerkleProofParsingError => "Error parsing Merkle proof",
+            Error::OrderedHashingRequiredError => "Proof requires ordered hashing",
+        })
+    }
+}
+
+/// How two nodes are hashed into their parent
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HashMode {
+    /// `keccak256(left ++ right)`, with the last node of an uneven layer
+    /// promoted as is, like `rs_merkle`
+    #[default]
+    Ordered,
+    /// `keccak256(min(a, b) ++ max(a, b))` over the tree layout of
+    /// `@openzeppelin/merkle-tree`
+    Commutative,
+}
+
+/// The options of the tree a proof comes from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TreeOptions {
+    pub hash_mode: HashMode,
+    pub leaf_encoding: LeafEncoding,
+}
+
+pub fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut concatenated = [0u8; 64];
+    concatenated[..32].copy_from_slice(left);
+    concatenated[32..].copy_from_slice(right);
+    keccak256(&concatenated)
+}
+
+/// Number of sibling hashes a single leaf proof has in an ordered tree. The
+/// last node of an uneven layer is promoted without a sibling.
+pub fn expected_proof_len(index: usize, leaf_count: usize) -> usize {
+    let mut index = index;
+    let mut layer_len = leaf_count;
+    let mut proof_len = 0;
+    while layer_len > 1 {
+        if index ^ 1 < layer_len {
+            proof_len += 1;
+        }
+        index /= 2;
+        layer_len = layer_len.div_ceil(2);
+    }
+    proof_len
+}
+
+/// Number of sibling hashes a single leaf proof has in a commutative tree, for
+/// an index within the leaves
+fn commutative_proof_len(index: usize, leaf_count: usize) -> usize {
+    let tree_index = 2 * leaf_count - 2 - index;
+    (tree_index + 1).ilog2() as usize
+}
+
+/// Splits a serialized proof into its sibling hashes
+pub fn proof_hashes_from_bytes(proof_bytes: &[u8]) -> Result<Vec<[u8; 32]>, Error> {
+    if !proof_bytes.len().is_multiple_of(32) {
+        return Err(Error::MerkleProofParsingError);
+    }
+    Ok(proof_bytes
+        .chunks_exact(32)
+        .map(|chunk| chunk.try_into().expect("chunks are 32 bytes long"))
+        .collect())
+}
+
+/// Root of an ordered tree from the leaf at `index` and its sibling hashes
+fn ordered_root(
+    leaf_hash: [u8; 32],
+    proof: &[[u8; 32]],
+    index: usize,
+    leaf_count: usize,
+    hash_pair: impl Fn(&[u8; 32], &[u8; 32]) -> [u8; 32],
+) -> [u8; 32] {
+    let mut siblings = proof.iter();
+    let mut node = leaf_hash;
+    let mut index = index;
+    let mut layer_len = leaf_count;
+    while layer_len > 1 {
+        if index ^ 1 < layer_len {
+            let sibling = siblings.next().expect("proof length is checked");
+            node = if index.is_multiple_of(2) {
+                hash_pair(&node, sibling)
+            } else {
+                hash_pair(sibling, &node)
+            };
+        }
+        index /= 2;
+        layer_len = layer_len.div_ceil(2);
+    }
+    node
+}
+
+fn commutative_root(
+    leaf_hash: [u8; 32],
+    proof: &[[u8; 32]],
+    hash_pair: impl Fn(&[u8; 32], &[u8; 32]) -> [u8; 32],
+) -> [u8; 32] {
+    proof.iter().fold(leaf_hash, |node, sibling| {
+        if node <= *sibling {
+            hash_pair(&node, sibling)
+        } else {
+            hash_pair(sibling, &node)
+        }
+    })
+}
+
+/// Root of the tree in which `leaf_hash` is the leaf at `index`, parents being
+/// computed by `hash_pair` so trees of other hash functions share the checks.
+/// Proofs with more or fewer hashes than the position needs are rejected with
+/// a parsing error.
+pub fn proof_root_with_hash_pair(
+    hash_mode: HashMode,
+    leaf_hash: [u8; 32],
+    proof_bytes: &[u8],
+    index: usize,
+    leaf_count: usize,
+    hash_pair: impl Fn(&[u8; 32], &[u8; 32]) -> [u8; 32],
+) -> Result<[u8; 32], Error> {
+    if leaf_count == 0 {
+        return Err(Error::EmptyAccountsListError);
+    }
+    if index >= leaf_count {
+        return Err(Error::LeafIndexOutOfRangeError);
+    }
+    let proof = proof_hashes_from_bytes(proof_bytes)?;
+    match hash_mode {
+        HashMode::Ordered => {
+            if proof.len() != expected_proof_len(index, leaf_count) {
+                return Err(Error::MerkleProofParsingError);
+            }
+            Ok(ordered_root(
+                leaf_hash, &proof, index, leaf_count, hash_pair,
+            ))
+        }
+        HashMode::Commutative => {
+            if proof.len() != commutative_proof_len(index, leaf_count) {
+                return Err(Error::MerkleProofParsingError);
+            }
+            Ok(commutative_root(leaf_hash, &proof, hash_pair))
+        }
+    }
+}
+
+/// Checks that `leaf_hash` is the leaf at `index` of the tree with the given
+/// root
+pub fn verify_leaf_inclusion(
+    hash_mode: HashMode,
+    root: [u8; 32],
+    leaf_hash: [u8; 32],
+    proof_bytes: &[u8],
+    index: usize,
+    leaf_count: usize,
+) -> Result<bool, Error> {
+    let proof_root = proof_root_with_hash_pair(
+        hash_mode,
+        leaf_hash,
+        proof_bytes,
+        index,
+        leaf_count,
+        hash_pair,
+    )?;
+    Ok(proof_root == root)
+}
+
+/// Checks that `account` is the leaf at `index` of a tree with the default
+/// options
+pub fn verify_inclusion(
+    root: [u8; 32],
+    account: &AccountWithBalance,
+    proof_bytes: &[u8],
+    index: usize,
+    leaf_count: usize,
+) -> Result<bool, Error> {
+    verify_inclusion_with_options(
+        TreeOptions::default(),
+        root,
+        account,
+        proof_bytes,
+        index,
+        leaf_count,
+    )
+}
+
+/// Same as [`verify_inclusion`] for a tree built with the given options
+pub fn verify_inclusion_with_options(
+    options: TreeOptions,
+    root: [u8; 32],
+    account: &AccountWithBalance,
+    proof_bytes: &[u8],
+    index: usize,
+    leaf_count: usize,
+) -> Result<bool, Error> {
+    verify_leaf_inclusion(
+        options.hash_mode,
+        root,
+        account.leaf_hash(options.leaf_encoding),
+        proof_bytes,
+        index,
+        leaf_count,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use merkletree::alphabetic_merkle_tree::AlphabeticMerkleTree;
+
+    use crate::test_utils::{accounts, full_accounts, full_options, to_account, tree_options};
+
+    #[test]
+    fn test_verify_merkletree_proofs() {
+        for leaf_count in [1, 2, 5, 13, 64] {
+            let full_accounts = full_accounts(leaf_count);
+            for options in tree_options() {
+                let tree =
+                    AlphabeticMerkleTree::with_options(&full_accounts, full_options(options))
+                        .unwrap();
+                for (index, full_account) in tree.accounts().iter().enumerate() {
+                    let proof = tree.generate_proof_of_inclusion(full_account).unwrap();
+                    assert!(verify_inclusion_with_options(
+                        options,
+                        tree.root(),
+                        &to_account(full_account),
+                        &proof,
+                        index,
+                        leaf_count
+                    )
+                    .unwrap());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_reject_wrong_proofs() {
+        let full_accounts = full_accounts(13);
+        let tree = AlphabeticMerkleTree::new(&full_accounts).unwrap();
+        let sorted: Vec<AccountWithBalance> = tree.accounts().iter().map(to_account).collect();
+        let proof = tree
+            .generate_proof_of_inclusion(&tree.accounts()[4])
+            .unwrap();
+
+        assert!(!verify_inclusion(tree.root(), &sorted[5], &proof, 4, 13).unwrap());
+        assert!(!verify_inclusion(tree.root(), &accounts(1)[0], &proof, 4, 13).unwrap());
+        assert!(!verify_inclusion([0; 32], &sorted[4], &proof, 4, 13).unwrap());
+        assert_eq!(
+            verify_inclusion(tree.root(), &sorted[4], &proof, 12, 13),
+            Err(Error::MerkleProofParsingError)
+        );
+        assert_eq!(
+            verify_inclusion(tree.root(), &sorted[4], &proof[32..], 4, 13),
+            Err(Error::MerkleProofParsingError)
+        );
+        assert_eq!(
+            verify_inclusion(tree.root(), &sorted[4], &proof, 13, 13),
+            Err(Error::LeafIndexOutOfRangeError)
+        );
+        assert_eq!(
+            verify_inclusion(tree.root(), &sorted[4], &proof, 0, 0),
+            Err(Error::EmptyAccountsListError)
+        );
+    }
+
+    #[test]
+    fn test_expected_proof_len() {
+        assert_eq!(expected_proof_len(0, 1), 0);
+        assert_eq!(expected_proof_len(3, 5), 3);
+        assert_eq!(expected_proof_len(4, 5), 1);
+        assert_eq!(commutative_proof_len(0, 1), 0);
+        assert_eq!(
+            commutative_proof_len(4, 5),
+            merkletree::commutative_merkle_tree::proof_len(4, 5)
+        );
+    }
+}
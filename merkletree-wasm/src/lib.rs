@@ -0,0 +1,183 @@
+//! JavaScript bindings of the default tree of `merkletree`, built with
+//! `wasm-pack`.
+//!
+//! Accounts are passed as two arrays of the same length: hex addresses and
+//! balances, either decimal or `0x` hex. Roots and proof hashes are hex
+//! strings, and proofs are JSON in the format of the `proof` field of the API
+//! responses for alphabetic proposals, so a frontend can check the proofs
+//! served by the API against its own root.
+
+use ethers::utils::hex;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use merkletree::absence_proof::{AbsenceProof, LeafProof};
+use merkletree::account_with_balance::{
+    parse_address, parse_balance, AccountWithBalance, ChecksumMode,
+};
+use merkletree::alphabetic_merkle_tree::AlphabeticMerkleTree;
+use merkletree::merkle_tree::Error;
+use merkletree::verification::verify_inclusion;
+
+/// Inclusion proof with the position needed to verify it
+#[derive(Debug, Deserialize, Serialize)]
+struct HexInclusionProof {
+    index: usize,
+    leaf_count: usize,
+    proof: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct HexLeafProof {
+    account: AccountWithBalance,
+    index: usize,
+    proof: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct HexAbsenceProof {
+    left: Option<HexLeafProof>,
+    right: Option<HexLeafProof>,
+    leaf_count: usize,
+}
+
+impl HexLeafProof {
+    fn from_leaf_proof(leaf: LeafProof) -> Self {
+        Self {
+            account: leaf.account,
+            index: leaf.index,
+            proof: hex::encode(leaf.proof),
+        }
+    }
+
+    fn to_leaf_proof(&self) -> Result<LeafProof, Error> {
+        Ok(LeafProof {
+            account: self.account,
+            index: self.index,
+            proof: decode_hex(&self.proof)?,
+        })
+    }
+}
+
+fn decode_hex(value: &str) -> Result<Vec<u8>, Error> {
+    let digits = value.strip_prefix("0x").unwrap_or(value);
+    hex::decode(digits).map_err(|_| Error::MerkleProofParsingError)
+}
+
+fn decode_root(root: &str) -> Result<[u8; 32], Error> {
+    decode_hex(root)?
+        .try_into()
+        .map_err(|_| Error::MerkleTreeRootError)
+}
+
+fn parse_account(address: &str, balance: &str) -> Result<AccountWithBalance, Error> {
+    Ok(AccountWithBalance {
+        address: parse_address(address, ChecksumMode::Ignore)?,
+        balance: parse_balance(balance)?,
+    })
+}
+
+fn build_tree(addresses: &[String], balances: &[String]) -> Result<AlphabeticMerkleTree, Error> {
+    if addresses.len() != balances.len() {
+        return Err(Error::AccountsLengthMismatchError(
+            addresses.len(),
+            balances.len(),
+        ));
+    }
+    let accounts = addresses
+        .iter()
+        .zip(balances)
+        .map(|(address, balance)| parse_account(address, balance))
+        .collect::<Result<Vec<_>, Error>>()?;
+    AlphabeticMerkleTree::new(&accounts)
+}
+
+/// `0x` hex root of the tree of the accounts
+#[wasm_bindgen(js_name = merkleRoot)]
+pub fn merkle_root(addresses: Vec<String>, balances: Vec<String>) -> Result<String, JsError> {
+    let tree = build_tree(&addresses, &balances)?;
+    Ok(format!("0x{}", hex::encode(tree.root())))
+}
+
+/// JSON inclusion proof of an account: `{index, leaf_count, proof}`
+#[wasm_bindgen(js_name = inclusionProof)]
+pub fn inclusion_proof(
+    addresses: Vec<String>,
+    balances: Vec<String>,
+    address: &str,
+    balance: &str,
+) -> Result<String, JsError> {
+    let tree = build_tree(&addresses, &balances)?;
+    let account = parse_account(address, balance)?;
+    let proof = HexInclusionProof {
+        index: tree.index_of(&account).ok_or(Error::AccountNotFoundError)?,
+        leaf_count: tree.leaves_len(),
+        proof: hex::encode(tree.generate_proof_of_inclusion(&account)?),
+    };
+    Ok(serde_json::to_string(&proof)?)
+}
+
+#[wasm_bindgen(js_name = verifyInclusion)]
+pub fn verify_inclusion_proof(
+    root: &str,
+    address: &str,
+    balance: &str,
+    proof: &str,
+    index: usize,
+    leaf_count: usize,
+) -> Result<bool, JsError> {
+    let root = decode_root(root)?;
+    let account = parse_account(address, balance)?;
+    Ok(verify_inclusion(
+        root,
+        &account,
+        &decode_hex(proof)?,
+        index,
+        leaf_count,
+    )?)
+}
+
+/// JSON absence proof of an account, as returned by the API
+#[wasm_bindgen(js_name = absenceProof)]
+pub fn absence_proof(
+    addresses: Vec<String>,
+    balances: Vec<String>,
+    address: &str,
+    balance: &str,
+) -> Result<String, JsError> {
+    let tree = build_tree(&addresses, &balances)?;
+    let proof = tree.generate_proof_of_absense(&parse_account(address, balance)?)?;
+    let hex_proof = HexAbsenceProof {
+        left: proof.left.map(HexLeafProof::from_leaf_proof),
+        right: proof.right.map(HexLeafProof::from_leaf_proof),
+        leaf_count: proof.leaf_count,
+    };
+    Ok(serde_json::to_string(&hex_proof)?)
+}
+
+#[wasm_bindgen(js_name = verifyAbsence)]
+pub fn verify_absence_proof(
+    root: &str,
+    proof: &str,
+    address: &str,
+    balance: &str,
+) -> Result<bool, JsError> {
+    let root = decode_root(root)?;
+    let hex_proof: HexAbsenceProof = serde_json::from_str(proof)?;
+    let proof = AbsenceProof {
+        left: hex_proof
+            .left
+            .as_ref()
+            .map(HexLeafProof::to_leaf_proof)
+            .transpose()?,
+        right: hex_proof
+            .right
+            .as_ref()
+            .map(HexLeafProof::to_leaf_proof)
+            .transpose()?,
+        leaf_count: hex_proof.leaf_count,
+        hash_mode: Default::default(),
+        leaf_encoding: Default::default(),
+    };
+    Ok(proof.verify(root, &parse_account(address, balance)?)?)
+}
@@ -0,0 +1,91 @@
+//! Tests of the JavaScript bindings, run under node with
+//! `wasm-pack test --node merkletree-wasm`
+#![cfg(target_arch = "wasm32")]
+
+use merkletree::account_with_balance::{AccountWithBalance, ChecksumMode};
+use merkletree::alphabetic_merkle_tree::AlphabeticMerkleTree;
+use merkletree_wasm::{
+    absence_proof, inclusion_proof, merkle_root, verify_absence_proof, verify_inclusion_proof,
+};
+use serde_json::Value;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+const ADDRESSES: [&str; 5] = [
+    "0xF977814e90dA44bFA03b6295A0616a897441aceC",
+    "0x47ac0Fb4F2D84898e4D9E7b4DaB3C24507a6D503",
+    "0xA7A93fd0a276fc1C0197a5B5623eD117786eeD06",
+    "0xcEe284F754E854890e311e3280b767F80797180d",
+    "0x5754284f345afc66a98fbB0a0Afe71e0F007B949",
+];
+const BALANCES: [&str; 5] = ["1", "2", "3", "10", "100"];
+
+fn addresses() -> Vec<String> {
+    ADDRESSES.iter().map(|x| x.to_string()).collect()
+}
+
+fn balances() -> Vec<String> {
+    BALANCES.iter().map(|x| x.to_string()).collect()
+}
+
+#[wasm_bindgen_test]
+fn test_merkle_root() {
+    let accounts: Vec<AccountWithBalance> = ADDRESSES
+        .iter()
+        .zip(BALANCES)
+        .map(|(address, balance)| {
+            AccountWithBalance::parse(address, balance, ChecksumMode::Ignore).unwrap()
+        })
+        .collect();
+    let tree = AlphabeticMerkleTree::new(&accounts).unwrap();
+    let root = merkle_root(addresses(), balances()).unwrap();
+    assert_eq!(
+        root,
+        format!("0x{}", ethers::utils::hex::encode(tree.root()))
+    );
+
+    // Hex balances give the same tree
+    let hex_balances = ["0x1", "0x2", "0x3", "0xa", "0x64"];
+    let hex_balances = hex_balances.iter().map(|x| x.to_string()).collect();
+    assert_eq!(merkle_root(addresses(), hex_balances).unwrap(), root);
+
+    assert!(merkle_root(addresses(), balances()[1..].to_vec()).is_err());
+    assert!(merkle_root(vec!["0x12".to_string()], vec!["1".to_string()]).is_err());
+    assert!(merkle_root(addresses()[..1].to_vec(), vec!["0x".to_string()]).is_err());
+}
+
+#[wasm_bindgen_test]
+fn test_inclusion_proofs() {
+    let root = merkle_root(addresses(), balances()).unwrap();
+    for (address, balance) in ADDRESSES.iter().zip(BALANCES) {
+        let proof = inclusion_proof(addresses(), balances(), address, balance).unwrap();
+        let proof: Value = serde_json::from_str(&proof).unwrap();
+        let hashes = proof["proof"].as_str().unwrap();
+        let index = proof["index"].as_u64().unwrap() as usize;
+        let leaf_count = proof["leaf_count"].as_u64().unwrap() as usize;
+        assert_eq!(leaf_count, 5);
+
+        assert!(
+            verify_inclusion_proof(&root, address, balance, hashes, index, leaf_count).unwrap()
+        );
+        assert!(!verify_inclusion_proof(&root, address, "7", hashes, index, leaf_count).unwrap());
+    }
+
+    assert!(inclusion_proof(addresses(), balances(), ADDRESSES[0], "7").is_err());
+}
+
+#[wasm_bindgen_test]
+fn test_absence_proofs() {
+    let root = merkle_root(addresses(), balances()).unwrap();
+    for (address, balance) in [
+        ("0x0000000000000000000000000000000000000001", "1"),
+        ("0xA7A93fd0a276fc1C0197a5B5623eD117786eeD06", "4"),
+        ("0xFF54284f345afc66a98fbB0a0Afe71e0F007B948", "1"),
+    ] {
+        let proof = absence_proof(addresses(), balances(), address, balance).unwrap();
+        assert!(verify_absence_proof(&root, &proof, address, balance).unwrap());
+        assert!(!verify_absence_proof(&root, &proof, ADDRESSES[0], BALANCES[0]).unwrap());
+    }
+
+    assert!(absence_proof(addresses(), balances(), ADDRESSES[0], BALANCES[0]).is_err());
+    assert!(verify_absence_proof(&root, "{}", ADDRESSES[0], BALANCES[0]).is_err());
+}
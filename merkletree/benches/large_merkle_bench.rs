@@ -1,4 +1,4 @@
-use criterion::{criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
 
 use ethers::types::{Address, U256};
 use rand::Rng;
@@ -6,11 +6,13 @@ use rand::Rng;
 use merkletree::account_with_balance::AccountWithBalance;
 use merkletree::merkle_tree::get_merkle_root;
 
+const SIZES: [usize; 3] = [100_000, 1_000_000, 10_000_000];
+
 fn bench_merkle(c: &mut Criterion) {
     let mut rnd_addresses: Vec<AccountWithBalance> = vec![];
     let mut rng = rand::thread_rng();
 
-    for _ in 0..1000000 {
+    for _ in 0..SIZES[SIZES.len() - 1] {
         let random_number: f64 = rng.gen();
         rnd_addresses.push(AccountWithBalance {
             address: Address::random(),
@@ -18,12 +20,28 @@ fn bench_merkle(c: &mut Criterion) {
         });
     }
 
-    c.bench_function("Test 100000", |b| {
-        b.iter(|| get_merkle_root(&rnd_addresses[0..100000].to_vec()))
-    });
-    c.bench_function("Test 1000000", |b| {
-        b.iter(|| get_merkle_root(&rnd_addresses[0..1000000].to_vec()))
-    });
+    let mut group = c.benchmark_group("get_merkle_root");
+    group.sample_size(10);
+    for size in SIZES {
+        let accounts = &rnd_addresses[0..size];
+        // With the `parallel` feature, compare against the same code on a
+        // single thread to show the speedup
+        let single_thread = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap();
+        group.bench_with_input(
+            BenchmarkId::new("single_thread", size),
+            accounts,
+            |b, accounts| b.iter(|| single_thread.install(|| get_merkle_root(accounts))),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("all_threads", size),
+            accounts,
+            |b, accounts| b.iter(|| get_merkle_root(accounts)),
+        );
+    }
+    group.finish();
 }
 
 criterion_group!(benches, bench_merkle);
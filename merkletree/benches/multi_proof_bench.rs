@@ -0,0 +1,62 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use ethers::types::{Address, U256};
+use rand::Rng;
+
+use merkletree::account_with_balance::AccountWithBalance;
+use merkletree::alphabetic_merkle_tree::AlphabeticMerkleTree;
+
+fn bench_multi_proof(c: &mut Criterion) {
+    let mut rnd_addresses: Vec<AccountWithBalance> = vec![];
+    let mut rng = rand::thread_rng();
+
+    for _ in 0..100000 {
+        let random_number: f64 = rng.gen();
+        rnd_addresses.push(AccountWithBalance {
+            address: Address::random(),
+            balance: U256::from_little_endian(&random_number.to_le_bytes()),
+        });
+    }
+    let merkle_tree = AlphabeticMerkleTree::new(&rnd_addresses).unwrap();
+
+    // Criterion only measures time, so the proof sizes are reported as a table
+    println!("accounts  multiproof bytes  single proofs bytes");
+    for accounts_to_prove in [10, 100, 1000] {
+        let batch = &rnd_addresses[0..accounts_to_prove];
+        let batch_proof_size = merkle_tree
+            .generate_batch_proof(batch)
+            .unwrap()
+            .proof_size();
+        let single_proofs_size: usize = batch
+            .iter()
+            .map(|x| merkle_tree.generate_proof_of_inclusion(x).unwrap().len())
+            .sum();
+        println!("{accounts_to_prove:>8}  {batch_proof_size:>16}  {single_proofs_size:>19}");
+    }
+
+    let mut group = c.benchmark_group("batch_proof");
+    for accounts_to_prove in [10, 100, 1000] {
+        let batch = &rnd_addresses[0..accounts_to_prove];
+        group.bench_with_input(
+            BenchmarkId::new("multiproof", accounts_to_prove),
+            batch,
+            |b, batch| b.iter(|| merkle_tree.generate_batch_proof(batch)),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("single_proofs", accounts_to_prove),
+            batch,
+            |b, batch| {
+                b.iter(|| {
+                    batch
+                        .iter()
+                        .map(|x| merkle_tree.generate_proof_of_inclusion(x))
+                        .collect::<Vec<_>>()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_multi_proof);
+criterion_main!(benches);
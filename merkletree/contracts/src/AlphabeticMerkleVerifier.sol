@@ -0,0 +1,122 @@
+// SPDX-License-Identifier: MIT
+// Generated by the merkletree crate, do not edit
+pragma solidity ^0.8.20;
+
+/// @notice Verifies proofs of an alphabetic merkle tree with Packed leaves and Ordered hashing
+library AlphabeticMerkleVerifier {
+    error LeafIndexOutOfRange();
+    error InvalidProofLength();
+
+    /// @notice Leaf of the tree with its position and sibling hashes
+    struct Leaf {
+        address account;
+        uint256 balance;
+        uint256 index;
+        bytes32[] proof;
+    }
+
+    /// @notice Leaves surrounding an absent account. An account sorting before
+    /// the first or after the last leaf only has one neighbour.
+    struct AbsenceProof {
+        bool hasLeft;
+        Leaf left;
+        bool hasRight;
+        Leaf right;
+        uint256 leafCount;
+    }
+
+    /// @notice `keccak256(account ++ balance)` with the balance in as few bytes
+    /// as it fits, like `encode_packed` of ethers-rs
+    function leafHash(address account, uint256 balance) internal pure returns (bytes32 hash) {
+        uint256 length = byteLength(balance);
+        assembly {
+            let ptr := mload(0x40)
+            mstore(ptr, shl(96, account))
+            mstore(add(ptr, 20), shl(mul(8, sub(32, length)), balance))
+            hash := keccak256(ptr, add(20, length))
+        }
+    }
+
+    function hashPair(bytes32 left, bytes32 right) private pure returns (bytes32) {
+        return keccak256(abi.encodePacked(left, right));
+    }
+
+    /// @notice Root obtained from the leaf at `index` and its sibling hashes.
+    /// Reverts if the proof doesn't have as many hashes as the position requires.
+    function processProof(bytes32 leaf, bytes32[] memory proof, uint256 index, uint256 leafCount)
+        internal
+        pure
+        returns (bytes32 node)
+    {
+        if (index >= leafCount) revert LeafIndexOutOfRange();
+        node = leaf;
+        uint256 position;
+        for (uint256 layerLength = leafCount; layerLength > 1; layerLength = (layerLength + 1) / 2) {
+            // The last node of an uneven layer is promoted without a sibling
+            if ((index ^ 1) < layerLength) {
+                if (position == proof.length) revert InvalidProofLength();
+                bytes32 sibling = proof[position++];
+                node = index % 2 == 0 ? hashPair(node, sibling) : hashPair(sibling, node);
+            }
+            index /= 2;
+        }
+        if (position != proof.length) revert InvalidProofLength();
+    }
+
+    /// @notice Checks that the account of `leaf` is at `leaf.index` of the tree
+    function verifyInclusion(bytes32 root, uint256 leafCount, Leaf memory leaf) internal pure returns (bool) {
+        return processProof(leafHash(leaf.account, leaf.balance), leaf.proof, leaf.index, leafCount) == root;
+    }
+
+    /// @notice Whether `(a, aBalance)` sorts before `(b, bBalance)` in leaf order:
+    /// by address, then by the packed balance bytes, then by their length
+    function sortsBefore(address a, uint256 aBalance, address b, uint256 bBalance) private pure returns (bool) {
+        if (a != b) return a < b;
+        uint256 aLength = byteLength(aBalance);
+        uint256 bLength = byteLength(bBalance);
+        uint256 aKey = aBalance << (8 * (32 - aLength));
+        uint256 bKey = bBalance << (8 * (32 - bLength));
+        if (aKey != bKey) return aKey < bKey;
+        return aLength < bLength;
+    }
+
+    /// @notice Checks that the account is not a leaf of the tree: its neighbours
+    /// are in the tree, consecutive, and sort on both sides of it
+    function verifyAbsence(bytes32 root, AbsenceProof memory proof, address account, uint256 balance)
+        internal
+        pure
+        returns (bool)
+    {
+        if (proof.hasLeft && !sortsBefore(proof.left.account, proof.left.balance, account, balance)) return false;
+        if (proof.hasRight && !sortsBefore(account, balance, proof.right.account, proof.right.balance)) return false;
+        return verifyGap(root, proof);
+    }
+
+    /// @notice Checks that no leaf of the tree has the address, whatever its balance
+    function verifyAddressAbsence(bytes32 root, AbsenceProof memory proof, address account)
+        internal
+        pure
+        returns (bool)
+    {
+        if (proof.hasLeft && !(proof.left.account < account)) return false;
+        if (proof.hasRight && !(account < proof.right.account)) return false;
+        return verifyGap(root, proof);
+    }
+
+    function verifyGap(bytes32 root, AbsenceProof memory proof) private pure returns (bool) {
+        if (proof.hasLeft && !verifyInclusion(root, proof.leafCount, proof.left)) return false;
+        if (proof.hasRight && !verifyInclusion(root, proof.leafCount, proof.right)) return false;
+        if (proof.hasLeft && proof.hasRight) return proof.right.index == proof.left.index + 1;
+        if (proof.hasLeft) return proof.left.index == proof.leafCount - 1;
+        if (proof.hasRight) return proof.right.index == 0;
+        return false;
+    }
+
+    /// @notice Number of bytes of `value` without its leading zero bytes
+    function byteLength(uint256 value) private pure returns (uint256 length) {
+        while (value != 0) {
+            length++;
+            value >>= 8;
+        }
+    }
+}
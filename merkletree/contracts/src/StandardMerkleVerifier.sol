@@ -0,0 +1,33 @@
+// SPDX-License-Identifier: MIT
+// Generated by the merkletree crate, do not edit
+pragma solidity ^0.8.20;
+
+/// @notice Verifies proofs of an alphabetic merkle tree with Standard leaves and Commutative hashing
+library StandardMerkleVerifier {
+    /// @notice Leaf of OpenZeppelin's `StandardMerkleTree` with `["address", "uint256"]`
+    function leafHash(address account, uint256 balance) internal pure returns (bytes32) {
+        return keccak256(bytes.concat(keccak256(abi.encode(account, balance))));
+    }
+
+    function hashPair(bytes32 a, bytes32 b) private pure returns (bytes32) {
+        return a < b ? keccak256(abi.encodePacked(a, b)) : keccak256(abi.encodePacked(b, a));
+    }
+
+    /// @notice Root obtained from a leaf and its proof, as `MerkleProof.processProof`
+    function processProof(bytes32 leaf, bytes32[] memory proof) internal pure returns (bytes32 node) {
+        node = leaf;
+        for (uint256 i = 0; i < proof.length; i++) {
+            node = hashPair(node, proof[i]);
+        }
+    }
+
+    /// @notice Checks that the account is a leaf of the tree. Proofs don't pin
+    /// the position of the leaf, so there are no absence proofs.
+    function verifyInclusion(bytes32 root, address account, uint256 balance, bytes32[] memory proof)
+        internal
+        pure
+        returns (bool)
+    {
+        return processProof(leafHash(account, balance), proof) == root;
+    }
+}
@@ -0,0 +1,120 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.20;
+
+import {Test} from "forge-std/Test.sol";
+
+import {AlphabeticMerkleVerifier} from "../src/AlphabeticMerkleVerifier.sol";
+import {StandardMerkleVerifier} from "../src/StandardMerkleVerifier.sol";
+
+/// @notice Checks the generated verifiers against the vectors the Rust tests
+/// verify, written by `cargo test` with `UPDATE_SOLIDITY_FIXTURES=1`
+contract MerkleVerifierTest is Test {
+    string alphabetic;
+    string standard;
+
+    function setUp() public {
+        alphabetic = vm.readFile("test/fixtures/alphabetic.json");
+        standard = vm.readFile("test/fixtures/standard.json");
+    }
+
+    function leafAt(string memory json, string memory path)
+        internal
+        pure
+        returns (AlphabeticMerkleVerifier.Leaf memory)
+    {
+        return AlphabeticMerkleVerifier.Leaf({
+            account: vm.parseJsonAddress(json, string.concat(path, ".account")),
+            balance: vm.parseJsonUint(json, string.concat(path, ".balance")),
+            index: vm.parseJsonUint(json, string.concat(path, ".index")),
+            proof: vm.parseJsonBytes32Array(json, string.concat(path, ".proof"))
+        });
+    }
+
+    function inclusionPath(uint256 index) internal pure returns (string memory) {
+        return string.concat(".inclusion[", vm.toString(index), "]");
+    }
+
+    function test_leafHash() public view {
+        for (uint256 i = 0; i < 2; i++) {
+            string memory json = i == 0 ? alphabetic : standard;
+            bytes32[] memory leaves = vm.parseJsonBytes32Array(json, ".leaves");
+            for (uint256 j = 0; j < leaves.length; j++) {
+                AlphabeticMerkleVerifier.Leaf memory leaf = leafAt(json, inclusionPath(j));
+                bytes32 leafHash = i == 0
+                    ? AlphabeticMerkleVerifier.leafHash(leaf.account, leaf.balance)
+                    : StandardMerkleVerifier.leafHash(leaf.account, leaf.balance);
+                assertEq(leafHash, leaves[j]);
+            }
+        }
+    }
+
+    function test_verifyInclusion() public view {
+        bytes32 root = vm.parseJsonBytes32(alphabetic, ".root");
+        uint256 leafCount = vm.parseJsonUint(alphabetic, ".leafCount");
+        for (uint256 i = 0; i < leafCount; i++) {
+            AlphabeticMerkleVerifier.Leaf memory leaf = leafAt(alphabetic, inclusionPath(i));
+            assertTrue(AlphabeticMerkleVerifier.verifyInclusion(root, leafCount, leaf));
+
+            leaf.balance += 1;
+            assertFalse(AlphabeticMerkleVerifier.verifyInclusion(root, leafCount, leaf));
+        }
+    }
+
+    function test_verifyInclusion_revertsOnWrongProofLength() public {
+        bytes32 root = vm.parseJsonBytes32(alphabetic, ".root");
+        uint256 leafCount = vm.parseJsonUint(alphabetic, ".leafCount");
+        AlphabeticMerkleVerifier.Leaf memory leaf = leafAt(alphabetic, inclusionPath(0));
+        leaf.index = leafCount - 1;
+
+        vm.expectRevert(AlphabeticMerkleVerifier.InvalidProofLength.selector);
+        this.verifyInclusion(root, leafCount, leaf);
+    }
+
+    function verifyInclusion(bytes32 root, uint256 leafCount, AlphabeticMerkleVerifier.Leaf memory leaf)
+        external
+        pure
+        returns (bool)
+    {
+        return AlphabeticMerkleVerifier.verifyInclusion(root, leafCount, leaf);
+    }
+
+    function test_verifyAbsence() public view {
+        bytes32 root = vm.parseJsonBytes32(alphabetic, ".root");
+        uint256 leafCount = vm.parseJsonUint(alphabetic, ".leafCount");
+        for (uint256 i = 0; vm.keyExistsJson(alphabetic, absencePath(i)); i++) {
+            string memory path = absencePath(i);
+            address account = vm.parseJsonAddress(alphabetic, string.concat(path, ".account"));
+            uint256 balance = vm.parseJsonUint(alphabetic, string.concat(path, ".balance"));
+
+            AlphabeticMerkleVerifier.AbsenceProof memory proof;
+            proof.leafCount = leafCount;
+            proof.hasLeft = vm.keyExistsJson(alphabetic, string.concat(path, ".left"));
+            if (proof.hasLeft) proof.left = leafAt(alphabetic, string.concat(path, ".left"));
+            proof.hasRight = vm.keyExistsJson(alphabetic, string.concat(path, ".right"));
+            if (proof.hasRight) proof.right = leafAt(alphabetic, string.concat(path, ".right"));
+
+            assertTrue(AlphabeticMerkleVerifier.verifyAbsence(root, proof, account, balance));
+
+            // The neighbours of an included leaf don't prove it absent
+            if (proof.hasLeft) {
+                assertFalse(
+                    AlphabeticMerkleVerifier.verifyAbsence(root, proof, proof.left.account, proof.left.balance)
+                );
+            }
+        }
+    }
+
+    function absencePath(uint256 index) internal pure returns (string memory) {
+        return string.concat(".absence[", vm.toString(index), "]");
+    }
+
+    function test_verifyStandardInclusion() public view {
+        bytes32 root = vm.parseJsonBytes32(standard, ".root");
+        uint256 leafCount = vm.parseJsonUint(standard, ".leafCount");
+        for (uint256 i = 0; i < leafCount; i++) {
+            AlphabeticMerkleVerifier.Leaf memory leaf = leafAt(standard, inclusionPath(i));
+            assertTrue(StandardMerkleVerifier.verifyInclusion(root, leaf.account, leaf.balance, leaf.proof));
+            assertFalse(StandardMerkleVerifier.verifyInclusion(root, leaf.account, leaf.balance + 1, leaf.proof));
+        }
+    }
+}
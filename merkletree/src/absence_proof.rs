@@ -0,0 +1,274 @@
+use ethers::types::Address;
+use merkletree_verifier::absence_proof::is_gap;
+use serde::{Deserialize, Serialize};
+
+use crate::account_with_balance::{AccountWithBalance, LeafEncoding};
+use crate::alphabetic_merkle_tree::HashMode;
+use crate::hasher::{Keccak256, MerkleHasher};
+use crate::leaf::Leaf;
+use crate::merkle_tree::Error;
+use crate::verification::verify_leaf_inclusion_with_hasher;
+
+/// Leaf of the tree with its position and inclusion proof
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LeafProof<L = AccountWithBalance> {
+    pub account: L,
+    pub index: usize,
+    pub proof: Vec<u8>,
+}
+
+impl<L: Leaf> LeafProof<L> {
+    pub fn verify(
+        &self,
+        root: [u8; 32],
+        leaf_count: usize,
+        hash_mode: HashMode,
+        leaf_encoding: LeafEncoding,
+    ) -> Result<bool, Error> {
+        self.verify_with_hasher::<Keccak256>(root, leaf_count, hash_mode, leaf_encoding)
+    }
+
+    pub fn verify_with_hasher<H: MerkleHasher>(
+        &self,
+        root: [u8; 32],
+        leaf_count: usize,
+        hash_mode: HashMode,
+        leaf_encoding: LeafEncoding,
+    ) -> Result<bool, Error> {
+        verify_leaf_inclusion_with_hasher::<H>(
+            hash_mode,
+            root,
+            self.account.leaf_hash_with_hasher::<H>(leaf_encoding),
+            &self.proof,
+            self.index,
+            leaf_count,
+        )
+    }
+}
+
+/// Proof that an account is not a leaf of the tree.
+///
+/// Leaves are sorted, so an account is absent when the leaves on both sides of
+/// where it would be are consecutive. An account sorting before the first or
+/// after the last leaf only has one neighbour.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AbsenceProof<L = AccountWithBalance> {
+    pub left: Option<LeafProof<L>>,
+    pub right: Option<LeafProof<L>>,
+    pub leaf_count: usize,
+    #[serde(default)]
+    pub hash_mode: HashMode,
+    #[serde(default)]
+    pub leaf_encoding: LeafEncoding,
+}
+
+impl<L: Leaf> AbsenceProof<L> {
+    pub fn verify(&self, root: [u8; 32], account: &L) -> Result<bool, Error> {
+        self.verify_with_hasher::<Keccak256>(root, account)
+    }
+
+    /// Same as [`AbsenceProof::verify`] for a tree hashed with `H`
+    pub fn verify_with_hasher<H: MerkleHasher>(
+        &self,
+        root: [u8; 32],
+        account: &L,
+    ) -> Result<bool, Error> {
+        self.verify_gap::<H>(root, |leaf| leaf.lt(account), |leaf| account.lt(leaf))
+    }
+
+    /// Checks that no leaf of the tree has the given key, whatever its other
+    /// fields
+    pub fn verify_key(&self, root: [u8; 32], key: L::Key) -> Result<bool, Error> {
+        self.verify_key_with_hasher::<Keccak256>(root, key)
+    }
+
+    pub fn verify_key_with_hasher<H: MerkleHasher>(
+        &self,
+        root: [u8; 32],
+        key: L::Key,
+    ) -> Result<bool, Error> {
+        self.verify_gap::<H>(root, |leaf| leaf.key() < key, |leaf| key < leaf.key())
+    }
+
+    fn verify_gap<H: MerkleHasher>(
+        &self,
+        root: [u8; 32],
+        sorts_before: impl Fn(&L) -> bool,
+        sorts_after: impl Fn(&L) -> bool,
+    ) -> Result<bool, Error> {
+        if self.leaf_count == 0 {
+            return Err(Error::EmptyAccountsListError);
+        }
+        // Commutative proofs don't pin the leaf index, so adjacency can't be checked
+        if self.hash_mode == HashMode::Commutative {
+            return Err(Error::OrderedHashingRequiredError);
+        }
+        for neighbour in [&self.left, &self.right].into_iter().flatten() {
+            if !neighbour.verify_with_hasher::<H>(
+                root,
+                self.leaf_count,
+                self.hash_mode,
+                self.leaf_encoding,
+            )? {
+                return Ok(false);
+            }
+        }
+
+        Ok(is_gap(
+            self.left.as_ref().map(|x| (x.index, &x.account)),
+            self.right.as_ref().map(|x| (x.index, &x.account)),
+            self.leaf_count,
+            sorts_before,
+            sorts_after,
+        ))
+    }
+}
+
+impl<L: Leaf<Key = Address>> AbsenceProof<L> {
+    /// Checks that no leaf of the tree has the given address, whatever its balance
+    pub fn verify_address(&self, root: [u8; 32], address: Address) -> Result<bool, Error> {
+        self.verify_key(root, address)
+    }
+
+    pub fn verify_address_with_hasher<H: MerkleHasher>(
+        &self,
+        root: [u8; 32],
+        address: Address,
+    ) -> Result<bool, Error> {
+        self.verify_key_with_hasher::<H>(root, address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::alphabetic_merkle_tree::AlphabeticMerkleTree;
+    use crate::leaf::DelegatedAccount;
+    use crate::test_utils::{fixed_accounts, node_as_delegation, random_delegations};
+
+    fn absent_accounts() -> Vec<AccountWithBalance> {
+        vec![
+            AccountWithBalance::new("0000000000000000000000000000000000000001", "1"),
+            AccountWithBalance::new("A7A93fd0a276fc1C0197a5B5623eD117786eeD06", "4"),
+            AccountWithBalance::new("FF54284f345afc66a98fbB0a0Afe71e0F007B948", "1"),
+        ]
+    }
+
+    #[test]
+    fn test_verify_absence_proof() {
+        let tree = AlphabeticMerkleTree::new(&fixed_accounts()).unwrap();
+
+        for account in absent_accounts() {
+            let proof = tree.generate_proof_of_absense(&account).unwrap();
+            assert!(proof.verify(tree.root(), &account).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_verify_absence_proof_at_edges() {
+        let tree = AlphabeticMerkleTree::new(&fixed_accounts()).unwrap();
+        let [first, _, last] = absent_accounts()[..] else {
+            unreachable!()
+        };
+
+        let proof = tree.generate_proof_of_absense(&first).unwrap();
+        assert!(proof.left.is_none());
+        assert_eq!(proof.right.as_ref().unwrap().index, 0);
+
+        let proof = tree.generate_proof_of_absense(&last).unwrap();
+        assert_eq!(proof.left.as_ref().unwrap().index, 4);
+        assert!(proof.right.is_none());
+    }
+
+    #[test]
+    fn test_verify_absence_proof_for_other_account() {
+        let tree = AlphabeticMerkleTree::new(&fixed_accounts()).unwrap();
+        let accounts = absent_accounts();
+
+        // A proof only covers the gap it was generated for
+        let proof = tree.generate_proof_of_absense(&accounts[1]).unwrap();
+        assert!(!proof.verify(tree.root(), &accounts[0]).unwrap());
+        assert!(!proof.verify(tree.root(), &accounts[2]).unwrap());
+    }
+
+    #[test]
+    fn test_verify_absence_proof_non_adjacent_neighbours() {
+        let accounts = fixed_accounts();
+        let tree = AlphabeticMerkleTree::new(&accounts).unwrap();
+        let account = absent_accounts()[1];
+
+        // Both neighbours are in the tree, but leaf 2 lies between them
+        let proof = AbsenceProof {
+            left: Some(LeafProof {
+                account: accounts[1],
+                index: 1,
+                proof: tree.generate_proof_of_inclusion(&accounts[1]).unwrap(),
+            }),
+            right: Some(LeafProof {
+                account: accounts[3],
+                index: 3,
+                proof: tree.generate_proof_of_inclusion(&accounts[3]).unwrap(),
+            }),
+            leaf_count: accounts.len(),
+            hash_mode: HashMode::Ordered,
+            leaf_encoding: LeafEncoding::Packed,
+        };
+        assert!(!proof.verify(tree.root(), &accounts[2]).unwrap());
+        assert!(!proof.verify(tree.root(), &account).unwrap());
+    }
+
+    #[test]
+    fn test_verify_absence_proof_missing_edge() {
+        let accounts = fixed_accounts();
+        let tree = AlphabeticMerkleTree::new(&accounts).unwrap();
+
+        // Dropping the left neighbour would hide every leaf before the right one
+        let mut proof = tree
+            .generate_proof_of_absense(&absent_accounts()[1])
+            .unwrap();
+        proof.left = None;
+        assert!(!proof.verify(tree.root(), &absent_accounts()[1]).unwrap());
+    }
+
+    #[test]
+    fn test_absence_proof_with_commutative_hashing() {
+        let tree = AlphabeticMerkleTree::new(&fixed_accounts()).unwrap();
+        let account = absent_accounts()[1];
+
+        let mut proof = tree.generate_proof_of_absense(&account).unwrap();
+        proof.hash_mode = HashMode::Commutative;
+        let result = proof.verify(tree.root(), &account);
+        assert!(matches!(result, Err(Error::OrderedHashingRequiredError)));
+    }
+
+    #[test]
+    fn test_internal_nodes_are_not_neighbours() {
+        let tree = AlphabeticMerkleTree::new(&random_delegations(64, 7)).unwrap();
+        let leaves = tree.accounts();
+        let leaf_hashes: Vec<[u8; 32]> = leaves
+            .iter()
+            .map(|x| x.leaf_hash(LeafEncoding::Packed))
+            .collect();
+        // A node posing as a leaf of the tree of the 32 parents of the leaves
+        let node_proof = |node: usize| LeafProof {
+            account: node_as_delegation(&leaf_hashes[2 * node], &leaf_hashes[2 * node + 1]),
+            index: node,
+            proof: tree.generate_proof_hashes(&leaves[2 * node]).unwrap()[1..].concat(),
+        };
+
+        for node in 0..31 {
+            let proof = AbsenceProof::<DelegatedAccount> {
+                left: Some(node_proof(node)),
+                right: Some(node_proof(node + 1)),
+                leaf_count: 32,
+                hash_mode: HashMode::Ordered,
+                leaf_encoding: LeafEncoding::Packed,
+            };
+            for leaf in leaves {
+                assert!(!proof.verify(tree.root(), leaf).unwrap());
+                assert!(!proof.verify_address(tree.root(), leaf.address).unwrap());
+            }
+        }
+    }
+}
@@ -1,10 +1,82 @@
+use std::str::FromStr;
+
 use ethers::{
-    abi::{encode_packed, Token},
+    abi::{encode, encode_packed, Token},
     types::{Address, U256},
-    utils::keccak256,
+    utils::{hex, keccak256, to_checksum},
 };
 use serde::{Deserialize, Serialize};
 
+use crate::hasher::MerkleHasher;
+use crate::leaf::Leaf;
+use crate::merkle_tree::Error;
+
+/// How an account is turned into a leaf hash, `H` being the hasher of the tree
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum LeafEncoding {
+    /// `H(encode_packed(address, balance))`
+    #[default]
+    Packed,
+    /// `H(H(abi.encode(address, uint256)))`, with keccak256 the leaf of
+    /// OpenZeppelin's `StandardMerkleTree` with `["address", "uint256"]`
+    Standard,
+}
+
+/// Fixed size key sorting like `packed()`: the address, the balance without
+/// leading zero bytes padded on the right, and the length of the balance
+pub type SortKey = [u8; 53];
+
+/// Whether parsed addresses must carry an EIP-55 checksum
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChecksumMode {
+    /// Any case is accepted
+    #[default]
+    Ignore,
+    /// Mixed-case addresses must have a valid EIP-55 checksum. All lower or
+    /// all upper case addresses carry no checksum and are accepted.
+    Enforce,
+}
+
+/// Parses a hex address with or without the `0x` prefix
+pub fn parse_address(address: &str, checksum_mode: ChecksumMode) -> Result<Address, Error> {
+    let invalid = || Error::InvalidAddressError(address.to_string());
+    let digits = address
+        .strip_prefix("0x")
+        .or_else(|| address.strip_prefix("0X"))
+        .unwrap_or(address);
+    if digits.len() != 40 || !digits.bytes().all(|x| x.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    let parsed = Address::from_slice(&hex::decode(digits).map_err(|_| invalid())?);
+
+    let mixed_case = digits.bytes().any(|x| x.is_ascii_lowercase())
+        && digits.bytes().any(|x| x.is_ascii_uppercase());
+    if checksum_mode == ChecksumMode::Enforce
+        && mixed_case
+        && to_checksum(&parsed, None)[2..] != *digits
+    {
+        return Err(invalid());
+    }
+    Ok(parsed)
+}
+
+/// Parses a decimal balance, or a hex one with a `0x` prefix
+pub fn parse_balance(balance: &str) -> Result<U256, Error> {
+    let (digits, radix) = match balance.strip_prefix("0x") {
+        Some(digits) => (digits, 16),
+        None => (balance, 10),
+    };
+    if digits.is_empty() || !digits.chars().all(|x| x.is_digit(radix)) {
+        return Err(Error::InvalidBalanceError(balance.to_string()));
+    }
+    U256::from_str_radix(digits, radix)
+        .map_err(|_| Error::BalanceOutOfRangeError(balance.to_string()))
+}
+
+/// Size of an account in binary files: the address and the 32-byte big-endian
+/// balance
+pub const ACCOUNT_RECORD_LEN: usize = 52;
+
 #[derive(Debug, Clone, Copy, Deserialize, Serialize)]
 pub struct AccountWithBalance {
     pub address: Address,
@@ -12,25 +84,95 @@ pub struct AccountWithBalance {
 }
 
 impl AccountWithBalance {
+    /// Panics on invalid input, see [`AccountWithBalance::parse`]
+    #[cfg(test)]
     pub fn new(address: &str, balance: &str) -> Self {
-        Self {
-            address: address.to_lowercase().parse().unwrap(),
-            balance: U256::from_dec_str(balance).unwrap(),
-        }
+        Self::parse(address, balance, ChecksumMode::Ignore).unwrap()
+    }
+
+    /// Account from a hex address and a balance read by [`parse_balance`]
+    pub fn parse(address: &str, balance: &str, checksum_mode: ChecksumMode) -> Result<Self, Error> {
+        Ok(Self {
+            address: parse_address(address, checksum_mode)?,
+            balance: parse_balance(balance)?,
+        })
     }
 
     pub fn packed(&self) -> Vec<u8> {
         encode_packed(&[Token::Address(self.address), Token::Uint(self.balance)]).unwrap()
     }
 
+    /// Key with the same order as `packed()`, built without allocating
+    pub fn sort_key(&self) -> SortKey {
+        let mut balance = [0u8; 32];
+        self.balance.to_big_endian(&mut balance);
+        let balance_len = self.balance.bits().div_ceil(8);
+
+        let mut key = [0u8; 53];
+        key[..20].copy_from_slice(self.address.as_bytes());
+        key[20..20 + balance_len].copy_from_slice(&balance[32 - balance_len..]);
+        key[52] = balance_len as u8;
+        key
+    }
+
+    pub fn to_record(&self) -> [u8; ACCOUNT_RECORD_LEN] {
+        let mut record = [0u8; ACCOUNT_RECORD_LEN];
+        record[..20].copy_from_slice(self.address.as_bytes());
+        self.balance.to_big_endian(&mut record[20..]);
+        record
+    }
+
+    pub fn from_record(record: &[u8; ACCOUNT_RECORD_LEN]) -> Self {
+        Self {
+            address: Address::from_slice(&record[..20]),
+            balance: U256::from_big_endian(&record[20..]),
+        }
+    }
+
     pub fn generate_hash(&self) -> [u8; 32] {
         keccak256(self.packed())
     }
+
+    /// `abi.encode(address, uint256)`
+    pub fn abi_encoded(&self) -> Vec<u8> {
+        encode(&[Token::Address(self.address), Token::Uint(self.balance)])
+    }
+
+    pub fn generate_standard_hash(&self) -> [u8; 32] {
+        keccak256(keccak256(self.abi_encoded()))
+    }
+
+    pub fn leaf_hash(&self, leaf_encoding: LeafEncoding) -> [u8; 32] {
+        Leaf::leaf_hash(self, leaf_encoding)
+    }
+
+    /// Leaf hash with the given hash function in place of Keccak-256
+    pub fn leaf_hash_with_hasher<H: MerkleHasher>(&self, leaf_encoding: LeafEncoding) -> [u8; 32] {
+        Leaf::leaf_hash_with_hasher::<H>(self, leaf_encoding)
+    }
+}
+
+impl TryFrom<(&str, &str)> for AccountWithBalance {
+    type Error = Error;
+
+    fn try_from((address, balance): (&str, &str)) -> Result<Self, Error> {
+        Self::parse(address, balance, ChecksumMode::Ignore)
+    }
+}
+
+/// Parses an `address,balance` row, ignoring whitespace around the fields
+impl FromStr for AccountWithBalance {
+    type Err = Error;
+
+    fn from_str(row: &str) -> Result<Self, Error> {
+        let (address, balance) = row.split_once(',').unwrap_or((row, ""));
+        Self::parse(address.trim(), balance.trim(), ChecksumMode::Ignore)
+    }
 }
 
 impl Ord for AccountWithBalance {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.packed().cmp(&other.packed())
+        self.sort_key().cmp(&other.sort_key())
     }
 }
 
@@ -42,8 +184,135 @@ impl PartialOrd for AccountWithBalance {
 
 impl PartialEq for AccountWithBalance {
     fn eq(&self, other: &Self) -> bool {
-        self.packed() == other.packed()
+        self.address == other.address && self.balance == other.balance
     }
 }
 
 impl Eq for AccountWithBalance {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_utils::random_accounts;
+
+    #[test]
+    fn test_sort_key_orders_like_packed() {
+        let mut accounts = random_accounts(200, 7);
+        let address = accounts[0].address;
+        for balance in [0u64, 1, 2, 255, 256, 257, 65535, 65536, u64::MAX] {
+            accounts.push(AccountWithBalance {
+                address,
+                balance: U256::from(balance),
+            });
+        }
+        accounts.push(AccountWithBalance {
+            address,
+            balance: U256::MAX,
+        });
+
+        for a in accounts.iter() {
+            for b in accounts.iter() {
+                assert_eq!(a.sort_key().cmp(&b.sort_key()), a.packed().cmp(&b.packed()));
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_account() {
+        let expected = AccountWithBalance {
+            address: "0x47ac0fb4f2d84898e4d9e7b4dab3c24507a6d503"
+                .parse()
+                .unwrap(),
+            balance: U256::from(1000),
+        };
+        let parsed = AccountWithBalance::parse(
+            "47ac0Fb4F2D84898e4D9E7b4DaB3C24507a6D503",
+            "1000",
+            ChecksumMode::Ignore,
+        )
+        .unwrap();
+        assert_eq!(parsed, expected);
+        assert_eq!(
+            AccountWithBalance::try_from(("0x47AC0FB4F2D84898E4D9E7B4DAB3C24507A6D503", "1000"))
+                .unwrap(),
+            expected
+        );
+        assert_eq!(
+            " 0x47ac0fb4f2d84898e4d9e7b4dab3c24507a6d503 , 1000"
+                .parse::<AccountWithBalance>()
+                .unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let address = "0x47ac0fb4f2d84898e4d9e7b4dab3c24507a6d503";
+        for invalid_address in ["", "0x", "0x47ac", &format!("{address}00"), "0xzz"] {
+            assert!(matches!(
+                AccountWithBalance::try_from((invalid_address, "1")),
+                Err(Error::InvalidAddressError(_))
+            ));
+        }
+        for invalid_balance in ["", "-1", "1.5", "0x", "0xg1", "1e18"] {
+            assert!(matches!(
+                AccountWithBalance::try_from((address, invalid_balance)),
+                Err(Error::InvalidBalanceError(_))
+            ));
+        }
+        let too_large = format!("{}0", U256::MAX);
+        assert!(matches!(
+            AccountWithBalance::try_from((address, too_large.as_str())),
+            Err(Error::BalanceOutOfRangeError(_))
+        ));
+        assert_eq!(parse_balance(&U256::MAX.to_string()).unwrap(), U256::MAX);
+        assert_eq!(parse_balance("0x10").unwrap(), U256::from(16));
+        let too_large = format!("0x{:x}0", U256::MAX);
+        assert!(matches!(
+            parse_balance(&too_large),
+            Err(Error::BalanceOutOfRangeError(_))
+        ));
+        assert!(matches!(
+            address.parse::<AccountWithBalance>(),
+            Err(Error::InvalidBalanceError(_))
+        ));
+    }
+
+    #[test]
+    fn test_eip55_checksum() {
+        // Test vectors from EIP-55
+        for address in [
+            "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+            "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+            "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB",
+            "0xD1220A0cf47c7B9Be7A2E6BA89F429762e7b9aDb",
+        ] {
+            assert!(parse_address(address, ChecksumMode::Enforce).is_ok());
+            assert!(parse_address(&address.to_lowercase(), ChecksumMode::Enforce).is_ok());
+            assert!(parse_address(&address[2..].to_uppercase(), ChecksumMode::Enforce).is_ok());
+
+            // Flipping the case of one letter breaks the checksum
+            let position = address[2..]
+                .find(|x: char| x.is_ascii_alphabetic())
+                .unwrap()
+                + 2;
+            let mut flipped = address.to_string();
+            let letter = address.as_bytes()[position] as char;
+            flipped.replace_range(
+                position..position + 1,
+                &if letter.is_ascii_lowercase() {
+                    letter.to_ascii_uppercase()
+                } else {
+                    letter.to_ascii_lowercase()
+                }
+                .to_string(),
+            );
+            assert!(matches!(
+                parse_address(&flipped, ChecksumMode::Enforce),
+                Err(Error::InvalidAddressError(_))
+            ));
+            assert!(parse_address(&flipped, ChecksumMode::Ignore).is_ok());
+        }
+    }
+}
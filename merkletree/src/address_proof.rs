@@ -0,0 +1,132 @@
+use ethers::types::U256;
+use serde::{Deserialize, Serialize};
+
+use crate::absence_proof::{AbsenceProof, LeafProof};
+use crate::account_with_balance::{AccountWithBalance, LeafEncoding};
+use crate::alphabetic_merkle_tree::HashMode;
+use crate::hasher::{Keccak256, MerkleHasher};
+use crate::leaf::Leaf;
+use crate::merkle_tree::Error;
+
+/// Answer to an address query on a tree ordered by address: either the leaf
+/// holding the address, or the neighbours proving no such leaf exists. Trees
+/// of other leaves answer queries by [`Leaf::key`] the same way.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum AddressProof<L = AccountWithBalance> {
+    Included {
+        leaf: LeafProof<L>,
+        leaf_count: usize,
+        #[serde(default)]
+        hash_mode: HashMode,
+        #[serde(default)]
+        leaf_encoding: LeafEncoding,
+    },
+    Absent(AbsenceProof<L>),
+}
+
+impl<L: Leaf> AddressProof<L> {
+    /// Leaf of the key, `None` if the proof is for an absent key
+    pub fn leaf(&self) -> Option<&L> {
+        match self {
+            AddressProof::Included { leaf, .. } => Some(&leaf.account),
+            AddressProof::Absent(_) => None,
+        }
+    }
+
+    pub fn verify(&self, root: [u8; 32], key: L::Key) -> Result<bool, Error> {
+        self.verify_with_hasher::<Keccak256>(root, key)
+    }
+
+    pub fn verify_with_hasher<H: MerkleHasher>(
+        &self,
+        root: [u8; 32],
+        key: L::Key,
+    ) -> Result<bool, Error> {
+        match self {
+            AddressProof::Included {
+                leaf,
+                leaf_count,
+                hash_mode,
+                leaf_encoding,
+            } => Ok(leaf.account.key() == key
+                && leaf.verify_with_hasher::<H>(root, *leaf_count, *hash_mode, *leaf_encoding)?),
+            AddressProof::Absent(proof) => proof.verify_key_with_hasher::<H>(root, key),
+        }
+    }
+}
+
+impl AddressProof {
+    /// Balance of the address, `None` if the proof is for an absent address
+    pub fn balance(&self) -> Option<U256> {
+        self.leaf().map(|account| account.balance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ethers::types::Address;
+
+    use crate::merkle_tree::{generate_address_proof, generate_proof_of_absense, get_merkle_root};
+    use crate::test_utils::fixed_accounts;
+
+    #[test]
+    fn test_address_proof_of_included_address() {
+        let accounts = fixed_accounts();
+        let root = get_merkle_root(&accounts).unwrap();
+
+        for account in accounts.iter() {
+            let proof = generate_address_proof(&accounts, account.address).unwrap();
+            assert_eq!(proof.balance(), Some(account.balance));
+            assert!(proof.verify(root, account.address).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_address_proof_of_absent_address() {
+        let accounts = fixed_accounts();
+        let root = get_merkle_root(&accounts).unwrap();
+
+        for address in [
+            "0000000000000000000000000000000000000001",
+            "A7A93fd0a276fc1C0197a5B5623eD117786eeD07",
+            "FF54284f345afc66a98fbB0a0Afe71e0F007B948",
+        ] {
+            let address: Address = address.parse().unwrap();
+            let proof = generate_address_proof(&accounts, address).unwrap();
+            assert_eq!(proof.balance(), None);
+            assert!(proof.verify(root, address).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_absence_proof_does_not_cover_included_address() {
+        let accounts = fixed_accounts();
+        let root = get_merkle_root(&accounts).unwrap();
+
+        // With packed ordering an account with the wrong balance is "absent"...
+        let wrong_balance = AccountWithBalance {
+            balance: U256::from(4),
+            ..accounts[2]
+        };
+        let packed_proof = generate_proof_of_absense(&accounts, wrong_balance).unwrap();
+        assert!(packed_proof.verify(root, &wrong_balance).unwrap());
+
+        // ...but its neighbours don't prove the address itself is missing
+        assert!(!packed_proof
+            .verify_address(root, wrong_balance.address)
+            .unwrap());
+        let proof = AddressProof::Absent(packed_proof);
+        assert!(!proof.verify(root, wrong_balance.address).unwrap());
+    }
+
+    #[test]
+    fn test_included_proof_for_other_address() {
+        let accounts = fixed_accounts();
+        let root = get_merkle_root(&accounts).unwrap();
+
+        let proof = generate_address_proof(&accounts, accounts[0].address).unwrap();
+        assert!(!proof.verify(root, accounts[1].address).unwrap());
+    }
+}
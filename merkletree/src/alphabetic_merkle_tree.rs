@@ -0,0 +1,952 @@
+use std::ops::Range;
+
+use ethers::types::{Address, U256};
+use serde::{Deserialize, Serialize};
+
+use crate::absence_proof::{AbsenceProof, LeafProof};
+use crate::account_with_balance::{AccountWithBalance, LeafEncoding};
+use crate::address_proof::AddressProof;
+use crate::batch_proof::BatchProof;
+use crate::commutative_merkle_tree::{CommutativeMerkleTree, MultiProof};
+use crate::duplicates::{self, duplicates_error, resolve_duplicates, Duplicate, DuplicatePolicy};
+use crate::hasher::{Keccak256, MerkleHasher};
+use crate::leaf::Leaf;
+use crate::merkle_tree::{order_accounts, Error};
+use crate::ordered_merkle_tree::OrderedMerkleTree;
+use crate::parallel;
+use crate::range_proof::{AddressPrefix, RangeProof};
+use crate::sibling_path_proof::SiblingPathProof;
+
+/// How leaves are sorted and what identifies a leaf
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OrderingMode {
+    /// Leaves are sorted by packed address and balance, so the same address can
+    /// appear with different balances
+    #[default]
+    Packed,
+    /// Leaves are sorted by address alone and every address appears once. Other
+    /// leaves are sorted by their [`Leaf::key`].
+    Address,
+    /// Leaves are sorted by leaf hash, like `StandardMerkleTree.of` from
+    /// `@openzeppelin/merkle-tree` does by default. Absence and range proofs
+    /// are not available.
+    LeafHash,
+}
+
+/// How two nodes are hashed into their parent, `H` being the [`MerkleHasher`]
+/// of the tree
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum HashMode {
+    /// `H(left ++ right)`, with the last node of an uneven layer
+    /// promoted as is, like `rs_merkle`
+    #[default]
+    Ordered,
+    /// `H(min(a, b) ++ max(a, b))` over the tree layout of
+    /// `@openzeppelin/merkle-tree`, verifiable with OpenZeppelin's `MerkleProof`.
+    /// Proofs don't pin the position of a leaf, so there are no absence proofs.
+    Commutative,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TreeOptions {
+    pub ordering: OrderingMode,
+    pub hash_mode: HashMode,
+    pub leaf_encoding: LeafEncoding,
+    pub duplicate_policy: DuplicatePolicy,
+}
+
+impl TreeOptions {
+    /// Options matching OpenZeppelin's `StandardMerkleTree` with
+    /// `["address", "uint256"]` leaves
+    pub fn standard() -> Self {
+        Self {
+            hash_mode: HashMode::Commutative,
+            leaf_encoding: LeafEncoding::Standard,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Clone)]
+enum MerkleNodes<H: MerkleHasher> {
+    Ordered(OrderedMerkleTree<H>),
+    Commutative(CommutativeMerkleTree<H>),
+}
+
+impl<H: MerkleHasher> MerkleNodes<H> {
+    fn from_leaves(leaves: &[[u8; 32]], hash_mode: HashMode) -> Result<Self, Error> {
+        Ok(match hash_mode {
+            HashMode::Ordered => {
+                MerkleNodes::Ordered(OrderedMerkleTree::from_leaves_with_hasher(leaves)?)
+            }
+            HashMode::Commutative => {
+                MerkleNodes::Commutative(CommutativeMerkleTree::from_leaves_with_hasher(leaves)?)
+            }
+        })
+    }
+
+    fn root(&self) -> [u8; 32] {
+        match self {
+            MerkleNodes::Ordered(merkle_tree) => merkle_tree.root(),
+            MerkleNodes::Commutative(merkle_tree) => merkle_tree.root(),
+        }
+    }
+
+    fn depth(&self) -> usize {
+        match self {
+            MerkleNodes::Ordered(merkle_tree) => merkle_tree.depth(),
+            MerkleNodes::Commutative(merkle_tree) => merkle_tree.depth(),
+        }
+    }
+
+    fn proof_hashes(&self, index: usize) -> Vec<[u8; 32]> {
+        match self {
+            MerkleNodes::Ordered(merkle_tree) => merkle_tree.proof(&[index]),
+            MerkleNodes::Commutative(merkle_tree) => merkle_tree.proof(index),
+        }
+        .expect("leaf index comes from the tree")
+    }
+
+    fn update_leaf(&mut self, index: usize, leaf: [u8; 32]) -> Result<(), Error> {
+        match self {
+            MerkleNodes::Ordered(merkle_tree) => merkle_tree.update_leaf(index, leaf),
+            MerkleNodes::Commutative(merkle_tree) => merkle_tree.update_leaf(index, leaf),
+        }
+    }
+
+    fn insert_leaf(&mut self, index: usize, leaf: [u8; 32]) -> Result<(), Error> {
+        match self {
+            MerkleNodes::Ordered(merkle_tree) => merkle_tree.insert_leaf(index, leaf),
+            MerkleNodes::Commutative(merkle_tree) => merkle_tree.insert_leaf(index, leaf),
+        }
+    }
+
+    fn remove_leaf(&mut self, index: usize) -> Result<[u8; 32], Error> {
+        match self {
+            MerkleNodes::Ordered(merkle_tree) => merkle_tree.remove_leaf(index),
+            MerkleNodes::Commutative(merkle_tree) => merkle_tree.remove_leaf(index),
+        }
+    }
+
+    /// Sibling hashes and `multiProofVerify` flags for sorted leaf indices
+    fn multi_proof_hashes(&self, indices: &[usize]) -> (Vec<[u8; 32]>, Vec<bool>) {
+        match self {
+            MerkleNodes::Ordered(merkle_tree) => (
+                merkle_tree
+                    .proof(indices)
+                    .expect("leaf indices come from the tree"),
+                vec![],
+            ),
+            MerkleNodes::Commutative(merkle_tree) => {
+                let multi_proof = merkle_tree
+                    .multi_proof(indices)
+                    .expect("leaf indices come from the tree");
+                (multi_proof.proof, multi_proof.proof_flags)
+            }
+        }
+    }
+}
+
+/// Merkle tree over an alphabetically ordered list of accounts.
+///
+/// The tree is built once and keeps the sorted leaves and the merkle layers, so
+/// root, inclusion and absence queries don't rebuild anything. Accounts can be
+/// inserted, updated and removed, recomputing only the nodes that change.
+/// Nodes are hashed with Keccak-256 unless another [`MerkleHasher`] is given,
+/// and leaves can be any [`Leaf`] besides accounts with a balance.
+#[derive(Clone)]
+pub struct AlphabeticMerkleTree<H: MerkleHasher = Keccak256, L: Leaf = AccountWithBalance> {
+    options: TreeOptions,
+    accounts: Vec<L>,
+    merkle_tree: MerkleNodes<H>,
+    root: [u8; 32],
+}
+
+impl<L: Leaf> AlphabeticMerkleTree<Keccak256, L> {
+    pub fn new(accounts: &[L]) -> Result<Self, Error> {
+        Self::with_options(accounts, TreeOptions::default())
+    }
+
+    pub fn with_options(accounts: &[L], options: TreeOptions) -> Result<Self, Error> {
+        Self::with_hasher(accounts, options)
+    }
+}
+
+impl<H: MerkleHasher, L: Leaf> AlphabeticMerkleTree<H, L> {
+    /// Tree hashed with `H`, e.g. `AlphabeticMerkleTree::<Sha256>::with_hasher`
+    pub fn with_hasher(accounts: &[L], options: TreeOptions) -> Result<Self, Error> {
+        if accounts.is_empty() {
+            return Err(Error::EmptyAccountsListError);
+        }
+        let accounts = resolve_duplicates(accounts, options.duplicate_policy)?;
+        let accounts = match options.ordering {
+            OrderingMode::Packed => order_accounts(&accounts),
+            OrderingMode::Address => order_accounts_by_key(&accounts)?,
+            OrderingMode::LeafHash => {
+                order_accounts_by_leaf_hash::<H, L>(&accounts, options.leaf_encoding)
+            }
+        };
+        let leaves = parallel::map(&accounts, |x| {
+            x.leaf_hash_with_hasher::<H>(options.leaf_encoding)
+        });
+        let merkle_tree = MerkleNodes::from_leaves(&leaves, options.hash_mode)?;
+        let root = merkle_tree.root();
+
+        Ok(Self {
+            options,
+            accounts,
+            merkle_tree,
+            root,
+        })
+    }
+
+    pub fn options(&self) -> TreeOptions {
+        self.options
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.root
+    }
+
+    pub fn leaves_len(&self) -> usize {
+        self.accounts.len()
+    }
+
+    pub fn depth(&self) -> usize {
+        self.merkle_tree.depth()
+    }
+
+    /// Accounts in leaf order
+    pub fn accounts(&self) -> &[L] {
+        &self.accounts
+    }
+
+    /// Nodes of a tree with commutative hashing, in `@openzeppelin/merkle-tree`
+    /// array order
+    pub fn commutative_nodes(&self) -> Option<&[[u8; 32]]> {
+        match &self.merkle_tree {
+            MerkleNodes::Ordered(_) => None,
+            MerkleNodes::Commutative(merkle_tree) => Some(merkle_tree.nodes()),
+        }
+    }
+
+    /// Every node of the tree: the layers from the leaves up with ordered
+    /// hashing, the array layout with commutative hashing
+    pub(crate) fn node_layers(&self) -> Vec<&[[u8; 32]]> {
+        match &self.merkle_tree {
+            MerkleNodes::Ordered(merkle_tree) => merkle_tree
+                .layers()
+                .iter()
+                .map(|layer| layer.as_slice())
+                .collect(),
+            MerkleNodes::Commutative(merkle_tree) => vec![merkle_tree.nodes()],
+        }
+    }
+
+    /// Leaf index of the account, if it is part of the tree
+    pub fn index_of(&self, account: &L) -> Option<usize> {
+        match self.options.ordering {
+            OrderingMode::LeafHash => self.leaf_hash_position(account).ok(),
+            _ => self.accounts.binary_search(account).ok(),
+        }
+    }
+
+    /// Position of the account among leaves sorted by hash
+    fn leaf_hash_position(&self, account: &L) -> Result<usize, usize> {
+        let leaf_hash = self.leaf_hash(account);
+        self.accounts
+            .binary_search_by(|x| self.leaf_hash(x).cmp(&leaf_hash))
+    }
+
+    /// Leaf index of the key, e.g. the address of an account. Only meaningful
+    /// when ordering by key, otherwise the first leaf of the key is returned.
+    /// Leaves sorted by hash are searched one by one.
+    pub fn index_of_key(&self, key: L::Key) -> Option<usize> {
+        if self.options.ordering == OrderingMode::LeafHash {
+            return self.accounts.iter().position(|x| x.key() == key);
+        }
+        // The other orderings sort by key first
+        let index = self.accounts.partition_point(|x| x.key() < key);
+        (self.accounts.get(index)?.key() == key).then_some(index)
+    }
+
+    /// Index of the only leaf of the key
+    fn single_index_of_key(&self, key: L::Key) -> Result<usize, Error> {
+        let index = self.index_of_key(key).ok_or(Error::AccountNotFoundError)?;
+        let duplicated = match self.options.ordering {
+            OrderingMode::LeafHash => self.accounts[index + 1..].iter().any(|x| x.key() == key),
+            _ => self.accounts.get(index + 1).is_some_and(|x| x.key() == key),
+        };
+        if duplicated {
+            let positions = (index..self.leaves_len())
+                .filter(|x| self.accounts[*x].key() == key)
+                .collect();
+            return Err(Error::DuplicateAddressError(vec![Duplicate {
+                key,
+                positions,
+            }
+            .boxed()]));
+        }
+        Ok(index)
+    }
+
+    /// Replaces the leaf at `index`, moving it to its new position when leaves
+    /// are sorted by hash. Otherwise keys are unique and the leaf stays put.
+    fn replace_leaf(&mut self, index: usize, account: L) -> Result<usize, Error> {
+        if self.options.ordering != OrderingMode::LeafHash || self.leaves_len() == 1 {
+            self.merkle_tree
+                .update_leaf(index, self.leaf_hash(&account))?;
+            self.accounts[index] = account;
+            self.root = self.merkle_tree.root();
+            return Ok(index);
+        }
+        self.merkle_tree.remove_leaf(index)?;
+        self.accounts.remove(index);
+        let new_index = match self.leaf_hash_position(&account) {
+            Ok(index) | Err(index) => index,
+        };
+        self.merkle_tree
+            .insert_leaf(new_index, self.leaf_hash(&account))?;
+        self.accounts.insert(new_index, account);
+        self.root = self.merkle_tree.root();
+        Ok(new_index)
+    }
+
+    fn leaf_hash(&self, account: &L) -> [u8; 32] {
+        account.leaf_hash_with_hasher::<H>(self.options.leaf_encoding)
+    }
+
+    /// Adds an account at its sorted position and returns its leaf index. With
+    /// address ordering the key must not be in the tree yet, unless the
+    /// duplicate policy of the tree merges it with the existing leaf.
+    pub fn insert(&mut self, account: L) -> Result<usize, Error> {
+        if let DuplicatePolicy::Sum | DuplicatePolicy::KeepMax | DuplicatePolicy::KeepLast =
+            self.options.duplicate_policy
+        {
+            if let Some(index) = self.index_of_key(account.key()) {
+                let merged = duplicates::merge(
+                    &self.accounts[index],
+                    &account,
+                    self.options.duplicate_policy,
+                )?;
+                return self.replace_leaf(index, merged);
+            }
+        }
+        let index = match self.options.ordering {
+            OrderingMode::Packed if self.options.duplicate_policy == DuplicatePolicy::Allow => {
+                self.accounts.binary_search(&account).err()
+            }
+            OrderingMode::LeafHash
+                if self.options.duplicate_policy == DuplicatePolicy::Allow
+                    || self.index_of_key(account.key()).is_none() =>
+            {
+                self.leaf_hash_position(&account).err()
+            }
+            OrderingMode::LeafHash => None,
+            _ => match self.index_of_key(account.key()) {
+                Some(_) => None,
+                None => Some(self.accounts.partition_point(|x| x.key() < account.key())),
+            },
+        }
+        .ok_or(Error::AccountAlreadyExistsError)?;
+
+        self.merkle_tree
+            .insert_leaf(index, self.leaf_hash(&account))?;
+        self.accounts.insert(index, account);
+        self.root = self.merkle_tree.root();
+        Ok(index)
+    }
+
+    /// Removes the leaf of a key and returns it. Fails if the key has several
+    /// leaves or is the last one in the tree.
+    pub fn remove(&mut self, key: L::Key) -> Result<L, Error> {
+        let index = self.single_index_of_key(key)?;
+        self.merkle_tree.remove_leaf(index)?;
+        self.root = self.merkle_tree.root();
+        Ok(self.accounts.remove(index))
+    }
+
+    pub fn generate_proof_of_inclusion(&self, account: &L) -> Result<Vec<u8>, Error> {
+        Ok(self.generate_proof_hashes(account)?.concat())
+    }
+
+    /// Inclusion proof as a list of sibling hashes, the `bytes32[]` taken by
+    /// on-chain verifiers
+    pub fn generate_proof_hashes(&self, account: &L) -> Result<Vec<[u8; 32]>, Error> {
+        let index = self.index_of(account).ok_or(Error::AccountNotFoundError)?;
+        Ok(self.merkle_tree.proof_hashes(index))
+    }
+
+    /// Inclusion proof as sibling hashes with the leaf position
+    pub fn generate_sibling_path_proof(&self, account: &L) -> Result<SiblingPathProof, Error> {
+        let index = self.index_of(account).ok_or(Error::AccountNotFoundError)?;
+        Ok(SiblingPathProof {
+            siblings: self.merkle_tree.proof_hashes(index),
+            index,
+            leaf_count: self.leaves_len(),
+            hash_mode: self.options.hash_mode,
+        })
+    }
+
+    /// Proof for several accounts in the format of OpenZeppelin's
+    /// `multiProofVerify`. Requires commutative hashing.
+    pub fn generate_commutative_multi_proof(&self, accounts: &[L]) -> Result<MultiProof, Error> {
+        let MerkleNodes::Commutative(merkle_tree) = &self.merkle_tree else {
+            return Err(Error::CommutativeHashingRequiredError);
+        };
+        let indices = accounts
+            .iter()
+            .map(|account| self.index_of(account).ok_or(Error::AccountNotFoundError))
+            .collect::<Result<Vec<usize>, Error>>()?;
+        merkle_tree.multi_proof(&indices)
+    }
+
+    /// Single proof for several accounts. Duplicated accounts are proven once.
+    pub fn generate_batch_proof(&self, accounts: &[L]) -> Result<BatchProof, Error> {
+        let mut indices = accounts
+            .iter()
+            .map(|account| self.index_of(account).ok_or(Error::AccountNotFoundError))
+            .collect::<Result<Vec<usize>, Error>>()?;
+        if indices.is_empty() {
+            return Err(Error::EmptyAccountsListError);
+        }
+        indices.sort_unstable();
+        indices.dedup();
+
+        let (proof, proof_flags) = self.merkle_tree.multi_proof_hashes(&indices);
+        Ok(BatchProof {
+            leaf_hashes: indices
+                .iter()
+                .map(|index| self.leaf_hash(&self.accounts[*index]))
+                .collect(),
+            indices,
+            proof,
+            proof_flags,
+            leaf_count: self.leaves_len(),
+            hash_mode: self.options.hash_mode,
+        })
+    }
+
+    /// Indices of the leaves surrounding an account that is not in the tree
+    pub fn find_adjacents(&self, account: &L) -> Result<(Option<usize>, Option<usize>), Error> {
+        if self.options.ordering == OrderingMode::LeafHash {
+            return Err(Error::AccountOrderingRequiredError);
+        }
+        match self.accounts.binary_search(account) {
+            Ok(_) => Err(Error::AccountAlreadyExistsError),
+            Err(next_index) => Ok((
+                next_index.checked_sub(1),
+                (next_index < self.accounts.len()).then_some(next_index),
+            )),
+        }
+    }
+
+    pub fn generate_proof_of_absense(&self, account: &L) -> Result<AbsenceProof<L>, Error> {
+        let (previous_index_opt, next_index_opt) = self.find_adjacents(account)?;
+        self.absence_proof(previous_index_opt, next_index_opt)
+    }
+
+    /// Proves the leaf of a key, or that the key has no leaf. Requires the
+    /// tree to be ordered by key.
+    pub fn generate_key_proof(&self, key: L::Key) -> Result<AddressProof<L>, Error> {
+        if self.options.ordering != OrderingMode::Address {
+            return Err(Error::AddressOrderingRequiredError);
+        }
+        if let Some(index) = self.index_of_key(key) {
+            return Ok(AddressProof::Included {
+                leaf: self.leaf_proof(index),
+                leaf_count: self.leaves_len(),
+                hash_mode: self.options.hash_mode,
+                leaf_encoding: self.options.leaf_encoding,
+            });
+        }
+
+        let next_index = self.accounts.partition_point(|x| x.key() < key);
+        Ok(AddressProof::Absent(self.absence_proof(
+            next_index.checked_sub(1),
+            (next_index < self.accounts.len()).then_some(next_index),
+        )?))
+    }
+
+    /// Proof of the consecutive leaves in `range`, e.g. a page of the
+    /// snapshot. Requires ordered hashing.
+    pub fn generate_range_proof(&self, range: Range<usize>) -> Result<RangeProof<L>, Error> {
+        if range.is_empty() {
+            return Err(Error::EmptyAccountsListError);
+        }
+        if range.end > self.leaves_len() {
+            return Err(Error::LeafIndexOutOfRangeError);
+        }
+        self.range_proof(range, false)
+    }
+
+    fn range_proof(
+        &self,
+        range: Range<usize>,
+        with_neighbours: bool,
+    ) -> Result<RangeProof<L>, Error> {
+        if self.options.hash_mode == HashMode::Commutative {
+            return Err(Error::OrderedHashingRequiredError);
+        }
+        if self.options.ordering == OrderingMode::LeafHash {
+            return Err(Error::AccountOrderingRequiredError);
+        }
+        let before = (with_neighbours && range.start > 0).then(|| self.accounts[range.start - 1]);
+        let after =
+            (with_neighbours && range.end < self.leaves_len()).then(|| self.accounts[range.end]);
+        let indices: Vec<usize> = (range.start - before.is_some() as usize
+            ..range.end + after.is_some() as usize)
+            .collect();
+        let (proof, _) = self.merkle_tree.multi_proof_hashes(&indices);
+
+        Ok(RangeProof {
+            start: range.start,
+            accounts: self.accounts[range].to_vec(),
+            before,
+            after,
+            proof,
+            leaf_count: self.leaves_len(),
+            leaf_encoding: self.options.leaf_encoding,
+        })
+    }
+
+    fn absence_proof(
+        &self,
+        previous_index_opt: Option<usize>,
+        next_index_opt: Option<usize>,
+    ) -> Result<AbsenceProof<L>, Error> {
+        if self.options.hash_mode == HashMode::Commutative {
+            return Err(Error::OrderedHashingRequiredError);
+        }
+        Ok(AbsenceProof {
+            left: previous_index_opt.map(|index| self.leaf_proof(index)),
+            right: next_index_opt.map(|index| self.leaf_proof(index)),
+            leaf_count: self.leaves_len(),
+            hash_mode: self.options.hash_mode,
+            leaf_encoding: self.options.leaf_encoding,
+        })
+    }
+
+    fn leaf_proof(&self, index: usize) -> LeafProof<L> {
+        LeafProof {
+            account: self.accounts[index],
+            index,
+            proof: self.merkle_tree.proof_hashes(index).concat(),
+        }
+    }
+}
+
+impl<H: MerkleHasher, L: Leaf<Key = Address>> AlphabeticMerkleTree<H, L> {
+    /// Leaf index of the address. Only meaningful when ordering by address,
+    /// otherwise the first leaf of the address is returned.
+    pub fn index_of_address(&self, address: Address) -> Option<usize> {
+        self.index_of_key(address)
+    }
+
+    /// Proves the leaf of an address, or that the address has no leaf.
+    /// Requires the tree to be ordered by address.
+    pub fn generate_address_proof(&self, address: Address) -> Result<AddressProof<L>, Error> {
+        self.generate_key_proof(address)
+    }
+
+    /// Proof of every leaf whose address has the prefix, with the leaves
+    /// around them. Requires ordered hashing.
+    pub fn generate_prefix_proof(&self, prefix: &AddressPrefix) -> Result<RangeProof<L>, Error> {
+        if self.options.ordering == OrderingMode::LeafHash {
+            return Err(Error::AccountOrderingRequiredError);
+        }
+        // Both other orderings sort by address first, so the leaves with the
+        // prefix are consecutive
+        let start = self
+            .accounts
+            .partition_point(|x| prefix.compare(&x.key()).is_lt());
+        let end = self
+            .accounts
+            .partition_point(|x| prefix.compare(&x.key()).is_le());
+        self.range_proof(start..end, true)
+    }
+}
+
+impl<H: MerkleHasher> AlphabeticMerkleTree<H> {
+    /// Sets the balance of an address, recomputing only its path to the root
+    /// unless leaves are sorted by hash. Fails if the address has several
+    /// leaves.
+    pub fn update_balance(&mut self, address: Address, balance: U256) -> Result<(), Error> {
+        let index = self.single_index_of_key(address)?;
+        self.replace_leaf(index, AccountWithBalance { address, balance })?;
+        Ok(())
+    }
+}
+
+fn order_accounts_by_key<L: Leaf>(accounts: &[L]) -> Result<Vec<L>, Error> {
+    let mut sorted_accounts = accounts.to_owned();
+    parallel::sort_unstable_by(&mut sorted_accounts, |a, b| a.key().cmp(&b.key()));
+    if sorted_accounts.windows(2).any(|x| x[0].key() == x[1].key()) {
+        return Err(duplicates_error(accounts));
+    }
+    Ok(sorted_accounts)
+}
+
+fn order_accounts_by_leaf_hash<H: MerkleHasher, L: Leaf>(
+    accounts: &[L],
+    leaf_encoding: LeafEncoding,
+) -> Vec<L> {
+    let mut hashed_accounts = parallel::map(accounts, |x| {
+        (x.leaf_hash_with_hasher::<H>(leaf_encoding), *x)
+    });
+    parallel::sort_unstable_by(&mut hashed_accounts, |a, b| a.0.cmp(&b.0));
+    hashed_accounts.into_iter().map(|x| x.1).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::commutative_merkle_tree::{
+        verify_multi_proof, verify_multi_proof_with_hasher, verify_proof,
+    };
+    use crate::merkle_tree::get_merkle_root;
+    use crate::test_utils::{fixed_accounts, random_accounts};
+    use crate::verification::{
+        verify_inclusion, verify_inclusion_with_hasher, verify_inclusion_with_options,
+    };
+
+    #[test]
+    fn test_tree_root_matches_get_merkle_root() {
+        let accounts = fixed_accounts();
+        let tree = AlphabeticMerkleTree::new(&accounts).unwrap();
+
+        assert_eq!(tree.root(), get_merkle_root(&accounts).unwrap());
+        assert_eq!(tree.leaves_len(), 5);
+        assert_eq!(tree.depth(), 3);
+    }
+
+    #[test]
+    fn test_tree_from_empty_accounts() {
+        let result = AlphabeticMerkleTree::<Keccak256, AccountWithBalance>::new(&[]);
+        assert!(matches!(result, Err(Error::EmptyAccountsListError)));
+    }
+
+    #[test]
+    fn test_tree_proof_of_inclusion() {
+        let accounts = fixed_accounts();
+        let tree = AlphabeticMerkleTree::new(&accounts).unwrap();
+
+        for (index, account) in accounts.iter().enumerate() {
+            assert_eq!(tree.index_of(account), Some(index));
+            let proof_bytes = tree.generate_proof_of_inclusion(account).unwrap();
+            assert!(
+                verify_inclusion(tree.root(), account, &proof_bytes, index, tree.leaves_len())
+                    .unwrap()
+            );
+        }
+
+        let missing = AccountWithBalance::new("47ac0Fb4F2D84898e4D9E7b4DaB3C24507a6D503", "3");
+        let result = tree.generate_proof_of_inclusion(&missing);
+        assert!(matches!(result, Err(Error::AccountNotFoundError)));
+    }
+
+    #[test]
+    fn test_tree_find_adjacents() {
+        let tree = AlphabeticMerkleTree::new(&fixed_accounts()).unwrap();
+
+        let first = AccountWithBalance::new("0000000000000000000000000000000000000001", "1");
+        let middle = AccountWithBalance::new("A7A93fd0a276fc1C0197a5B5623eD117786eeD06", "4");
+        let last = AccountWithBalance::new("FF54284f345afc66a98fbB0a0Afe71e0F007B948", "1");
+
+        assert_eq!(tree.find_adjacents(&first).unwrap(), (None, Some(0)));
+        assert_eq!(tree.find_adjacents(&middle).unwrap(), (Some(2), Some(3)));
+        assert_eq!(tree.find_adjacents(&last).unwrap(), (Some(4), None));
+
+        let existing = fixed_accounts()[2];
+        assert!(matches!(
+            tree.find_adjacents(&existing),
+            Err(Error::AccountAlreadyExistsError)
+        ));
+    }
+
+    #[test]
+    fn test_tree_ordered_by_address() {
+        let accounts = fixed_accounts();
+        let options = TreeOptions {
+            ordering: OrderingMode::Address,
+            ..Default::default()
+        };
+        let tree = AlphabeticMerkleTree::with_options(&accounts, options).unwrap();
+
+        // Unique addresses sort the same way in both modes
+        assert_eq!(tree.root(), get_merkle_root(&accounts).unwrap());
+        assert_eq!(tree.accounts(), &accounts[..]);
+
+        let mut duplicated = accounts.clone();
+        duplicated.push(AccountWithBalance::new(
+            "47ac0Fb4F2D84898e4D9E7b4DaB3C24507a6D503",
+            "3",
+        ));
+        let result = AlphabeticMerkleTree::with_options(&duplicated, options);
+        let Err(Error::DuplicateAddressError(duplicates)) = result else {
+            panic!("duplicates are rejected")
+        };
+        assert_eq!(duplicates[0].positions, vec![0, 5]);
+        assert_eq!(
+            duplicates[0].key_as::<Address>(),
+            Some(&duplicated[0].address)
+        );
+        assert!(duplicates[0].key_as::<U256>().is_none());
+    }
+
+    #[test]
+    fn test_address_proof_requires_address_ordering() {
+        let accounts = fixed_accounts();
+        let tree = AlphabeticMerkleTree::new(&accounts).unwrap();
+        let result = tree.generate_address_proof(accounts[0].address);
+        assert!(matches!(result, Err(Error::AddressOrderingRequiredError)));
+    }
+
+    #[test]
+    fn test_tree_with_commutative_hashing() {
+        let accounts = fixed_accounts();
+        let options = TreeOptions {
+            hash_mode: HashMode::Commutative,
+            ..Default::default()
+        };
+        let tree = AlphabeticMerkleTree::with_options(&accounts, options).unwrap();
+        assert_ne!(tree.root(), get_merkle_root(&accounts).unwrap());
+
+        for (index, account) in accounts.iter().enumerate() {
+            let proof = tree.generate_proof_hashes(account).unwrap();
+            assert!(verify_proof(tree.root(), account.generate_hash(), &proof));
+
+            let proof_bytes = tree.generate_proof_of_inclusion(account).unwrap();
+            assert!(verify_inclusion_with_options(
+                options,
+                tree.root(),
+                account,
+                &proof_bytes,
+                index,
+                tree.leaves_len()
+            )
+            .unwrap());
+        }
+
+        let absent = AccountWithBalance::new("A7A93fd0a276fc1C0197a5B5623eD117786eeD06", "4");
+        let result = tree.generate_proof_of_absense(&absent);
+        assert!(matches!(result, Err(Error::OrderedHashingRequiredError)));
+
+        let multi_proof = tree
+            .generate_commutative_multi_proof(&[accounts[3], accounts[1]])
+            .unwrap();
+        assert_eq!(
+            multi_proof.leaves,
+            vec![accounts[1].generate_hash(), accounts[3].generate_hash()]
+        );
+        assert!(verify_multi_proof(tree.root(), &multi_proof).unwrap());
+    }
+
+    fn assert_tree_proofs_verify<H: MerkleHasher>() {
+        let accounts = fixed_accounts();
+        let tree =
+            AlphabeticMerkleTree::<H>::with_hasher(&accounts, TreeOptions::default()).unwrap();
+
+        let leaves: Vec<[u8; 32]> = accounts
+            .iter()
+            .map(|x| x.leaf_hash_with_hasher::<H>(LeafEncoding::Packed))
+            .collect();
+        let left = H::hash_pair(
+            &H::hash_pair(&leaves[0], &leaves[1]),
+            &H::hash_pair(&leaves[2], &leaves[3]),
+        );
+        assert_eq!(tree.root(), H::hash_pair(&left, &leaves[4]));
+
+        for (index, account) in accounts.iter().enumerate() {
+            let proof_bytes = tree.generate_proof_of_inclusion(account).unwrap();
+            assert!(verify_inclusion_with_hasher::<H>(
+                TreeOptions::default(),
+                tree.root(),
+                account,
+                &proof_bytes,
+                index,
+                tree.leaves_len()
+            )
+            .unwrap());
+        }
+
+        let absent = AccountWithBalance::new("A7A93fd0a276fc1C0197a5B5623eD117786eeD06", "4");
+        let absence_proof = tree.generate_proof_of_absense(&absent).unwrap();
+        assert!(absence_proof
+            .verify_with_hasher::<H>(tree.root(), &absent)
+            .unwrap());
+
+        let batch_proof = tree.generate_batch_proof(&accounts[1..4]).unwrap();
+        assert!(batch_proof.verify_with_hasher::<H>(tree.root()).unwrap());
+    }
+
+    #[test]
+    fn test_tree_with_keccak256_hasher() {
+        assert_tree_proofs_verify::<Keccak256>();
+        let accounts = fixed_accounts();
+        let tree =
+            AlphabeticMerkleTree::<Keccak256>::with_hasher(&accounts, TreeOptions::default())
+                .unwrap();
+        assert_eq!(tree.root(), get_merkle_root(&accounts).unwrap());
+    }
+
+    #[cfg(feature = "sha256")]
+    #[test]
+    fn test_tree_with_sha256_hasher() {
+        assert_tree_proofs_verify::<crate::hasher::Sha256>();
+    }
+
+    #[cfg(feature = "blake3")]
+    #[test]
+    fn test_tree_with_blake3_hasher() {
+        assert_tree_proofs_verify::<crate::hasher::Blake3>();
+    }
+
+    #[cfg(feature = "poseidon")]
+    #[test]
+    fn test_tree_with_poseidon_hasher() {
+        assert_tree_proofs_verify::<crate::hasher::Poseidon>();
+    }
+
+    #[test]
+    fn test_commutative_tree_with_other_hasher() {
+        #[derive(Clone)]
+        struct Reversed;
+
+        impl MerkleHasher for Reversed {
+            const ID: u8 = u8::MAX;
+
+            fn hash(data: &[u8]) -> [u8; 32] {
+                let mut hash = Keccak256::hash(data);
+                hash.reverse();
+                hash
+            }
+        }
+
+        let accounts = fixed_accounts();
+        let keccak_tree =
+            AlphabeticMerkleTree::with_options(&accounts, TreeOptions::standard()).unwrap();
+        let tree =
+            AlphabeticMerkleTree::<Reversed>::with_hasher(&accounts, TreeOptions::standard())
+                .unwrap();
+        assert_ne!(tree.root(), keccak_tree.root());
+
+        let multi_proof = tree
+            .generate_commutative_multi_proof(&[accounts[0], accounts[2]])
+            .unwrap();
+        assert!(verify_multi_proof_with_hasher::<Reversed>(tree.root(), &multi_proof).unwrap());
+        assert!(!verify_multi_proof(tree.root(), &multi_proof).unwrap());
+    }
+
+    #[test]
+    fn test_mutations_match_rebuild() {
+        let accounts = random_accounts(40, 11);
+        let new_accounts = random_accounts(5, 12);
+        for options in [
+            TreeOptions::default(),
+            TreeOptions {
+                ordering: OrderingMode::Address,
+                ..Default::default()
+            },
+            TreeOptions::standard(),
+        ] {
+            let mut tree = AlphabeticMerkleTree::with_options(&accounts, options).unwrap();
+            let mut expected = accounts.clone();
+
+            for account in new_accounts.iter() {
+                let index = tree.insert(*account).unwrap();
+                assert_eq!(tree.accounts()[index], *account);
+                expected.push(*account);
+            }
+            for (position, account) in accounts.iter().enumerate().step_by(7) {
+                let balance = U256::from(position);
+                tree.update_balance(account.address, balance).unwrap();
+                expected[position].balance = balance;
+            }
+            for account in [accounts[0], accounts[39], new_accounts[2]] {
+                assert_eq!(
+                    tree.remove(account.address).unwrap().address,
+                    account.address
+                );
+                expected.retain(|x| x.address != account.address);
+            }
+
+            let rebuilt = AlphabeticMerkleTree::with_options(&expected, options).unwrap();
+            assert_eq!(tree.root(), rebuilt.root());
+            assert_eq!(tree.accounts(), rebuilt.accounts());
+            assert_eq!(tree.depth(), rebuilt.depth());
+
+            let account = tree.accounts()[17];
+            let proof = tree.generate_proof_of_inclusion(&account).unwrap();
+            assert_eq!(
+                proof,
+                rebuilt.generate_proof_of_inclusion(&account).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_mutation_errors() {
+        let accounts = fixed_accounts();
+        let mut tree = AlphabeticMerkleTree::new(&accounts).unwrap();
+        let root = tree.root();
+
+        assert!(matches!(
+            tree.insert(accounts[1]),
+            Err(Error::AccountAlreadyExistsError)
+        ));
+        let missing: Address = "0000000000000000000000000000000000000001".parse().unwrap();
+        assert!(matches!(
+            tree.update_balance(missing, U256::one()),
+            Err(Error::AccountNotFoundError)
+        ));
+        assert!(matches!(
+            tree.remove(missing),
+            Err(Error::AccountNotFoundError)
+        ));
+
+        // With packed ordering an address can have several leaves
+        let second_leaf = AccountWithBalance {
+            balance: U256::from(7),
+            ..accounts[1]
+        };
+        tree.insert(second_leaf).unwrap();
+        assert!(matches!(
+            tree.update_balance(accounts[1].address, U256::one()),
+            Err(Error::DuplicateAddressError(_))
+        ));
+        let options = TreeOptions {
+            ordering: OrderingMode::Address,
+            ..Default::default()
+        };
+        let mut address_tree = AlphabeticMerkleTree::with_options(&accounts, options).unwrap();
+        assert!(matches!(
+            address_tree.insert(second_leaf),
+            Err(Error::AccountAlreadyExistsError)
+        ));
+
+        let mut single = AlphabeticMerkleTree::new(&accounts[..1]).unwrap();
+        assert!(matches!(
+            single.remove(accounts[0].address),
+            Err(Error::EmptyAccountsListError)
+        ));
+        assert_eq!(single.accounts(), &accounts[..1]);
+
+        for account in accounts[1..].iter() {
+            single.insert(*account).unwrap();
+        }
+        assert_eq!(single.root(), root);
+    }
+
+    #[test]
+    fn test_commutative_multi_proof_requires_commutative_hashing() {
+        let accounts = fixed_accounts();
+        let tree = AlphabeticMerkleTree::new(&accounts).unwrap();
+        let result = tree.generate_commutative_multi_proof(&accounts);
+        assert!(matches!(
+            result,
+            Err(Error::CommutativeHashingRequiredError)
+        ));
+    }
+}
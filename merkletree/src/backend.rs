@@ -0,0 +1,338 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::absence_proof::AbsenceProof;
+use crate::account_with_balance::AccountWithBalance;
+use crate::alphabetic_merkle_tree::{AlphabeticMerkleTree, TreeOptions};
+use crate::hasher::{Keccak256, MerkleHasher};
+use crate::indexed_merkle_tree::{IndexedLeafProof, IndexedMerkleTree};
+use crate::merkle_tree::Error;
+use crate::sparse_merkle_tree::{SparseMerkleProof, SparseMerkleTree};
+
+/// Root, inclusion and absence queries shared by the tree implementations, so
+/// callers can pick one per snapshot
+pub trait MerkleBackend: Sized {
+    type InclusionProof;
+    type AbsenceProof;
+
+    fn from_accounts(accounts: &[AccountWithBalance]) -> Result<Self, Error>;
+
+    fn root(&self) -> [u8; 32];
+
+    fn generate_proof_of_inclusion(
+        &self,
+        account: &AccountWithBalance,
+    ) -> Result<Self::InclusionProof, Error>;
+
+    fn generate_proof_of_absense(
+        &self,
+        account: &AccountWithBalance,
+    ) -> Result<Self::AbsenceProof, Error>;
+}
+
+impl<H: MerkleHasher> MerkleBackend for AlphabeticMerkleTree<H> {
+    /// Serialized sibling hashes
+    type InclusionProof = Vec<u8>;
+    type AbsenceProof = AbsenceProof;
+
+    fn from_accounts(accounts: &[AccountWithBalance]) -> Result<Self, Error> {
+        Self::with_hasher(accounts, TreeOptions::default())
+    }
+
+    fn root(&self) -> [u8; 32] {
+        AlphabeticMerkleTree::root(self)
+    }
+
+    fn generate_proof_of_inclusion(&self, account: &AccountWithBalance) -> Result<Vec<u8>, Error> {
+        AlphabeticMerkleTree::generate_proof_of_inclusion(self, account)
+    }
+
+    fn generate_proof_of_absense(
+        &self,
+        account: &AccountWithBalance,
+    ) -> Result<AbsenceProof, Error> {
+        AlphabeticMerkleTree::generate_proof_of_absense(self, account)
+    }
+}
+
+impl<H: MerkleHasher> MerkleBackend for SparseMerkleTree<H> {
+    type InclusionProof = SparseMerkleProof;
+    type AbsenceProof = SparseMerkleProof;
+
+    fn from_accounts(accounts: &[AccountWithBalance]) -> Result<Self, Error> {
+        Self::with_hasher(accounts)
+    }
+
+    fn root(&self) -> [u8; 32] {
+        SparseMerkleTree::root(self)
+    }
+
+    fn generate_proof_of_inclusion(
+        &self,
+        account: &AccountWithBalance,
+    ) -> Result<SparseMerkleProof, Error> {
+        SparseMerkleTree::generate_proof_of_inclusion(self, account)
+    }
+
+    fn generate_proof_of_absense(
+        &self,
+        account: &AccountWithBalance,
+    ) -> Result<SparseMerkleProof, Error> {
+        SparseMerkleTree::generate_proof_of_absense(self, account)
+    }
+}
+
+impl<H: MerkleHasher> MerkleBackend for IndexedMerkleTree<H> {
+    type InclusionProof = IndexedLeafProof;
+    type AbsenceProof = IndexedLeafProof;
+
+    fn from_accounts(accounts: &[AccountWithBalance]) -> Result<Self, Error> {
+        Self::with_hasher(accounts)
+    }
+
+    fn root(&self) -> [u8; 32] {
+        IndexedMerkleTree::root(self)
+    }
+
+    fn generate_proof_of_inclusion(
+        &self,
+        account: &AccountWithBalance,
+    ) -> Result<IndexedLeafProof, Error> {
+        IndexedMerkleTree::generate_proof_of_inclusion(self, account)
+    }
+
+    fn generate_proof_of_absense(
+        &self,
+        account: &AccountWithBalance,
+    ) -> Result<IndexedLeafProof, Error> {
+        IndexedMerkleTree::generate_proof_of_absense(self, account)
+    }
+}
+
+/// Tree implementation of a snapshot, stored by name with it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendKind {
+    #[default]
+    Alphabetic,
+    Sparse,
+    Indexed,
+}
+
+impl BackendKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BackendKind::Alphabetic => "alphabetic",
+            BackendKind::Sparse => "sparse",
+            BackendKind::Indexed => "indexed",
+        }
+    }
+}
+
+impl fmt::Display for BackendKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for BackendKind {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self, Error> {
+        match name {
+            "alphabetic" => Ok(BackendKind::Alphabetic),
+            "sparse" => Ok(BackendKind::Sparse),
+            "indexed" => Ok(BackendKind::Indexed),
+            _ => Err(Error::UnknownBackendError(name.to_string())),
+        }
+    }
+}
+
+/// Proof of any backend, tagged with its type when serialized
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BackendProof {
+    /// Serialized sibling hashes of an [`AlphabeticMerkleTree`] leaf, with the
+    /// position needed to verify them
+    AlphabeticInclusion {
+        index: usize,
+        leaf_count: usize,
+        proof: Vec<u8>,
+    },
+    AlphabeticAbsence(AbsenceProof),
+    Sparse(SparseMerkleProof),
+    Indexed(IndexedLeafProof),
+}
+
+/// Tree of a backend chosen at runtime, e.g. the one of a proposal.
+/// [`MerkleBackend`] has a proof type per backend, this wraps them all in
+/// [`BackendProof`].
+pub enum AnyMerkleTree<H: MerkleHasher = Keccak256> {
+    Alphabetic(AlphabeticMerkleTree<H>),
+    Sparse(SparseMerkleTree<H>),
+    Indexed(IndexedMerkleTree<H>),
+}
+
+impl AnyMerkleTree {
+    pub fn new(backend: BackendKind, accounts: &[AccountWithBalance]) -> Result<Self, Error> {
+        Self::with_hasher(backend, accounts)
+    }
+}
+
+impl<H: MerkleHasher> AnyMerkleTree<H> {
+    pub fn with_hasher(
+        backend: BackendKind,
+        accounts: &[AccountWithBalance],
+    ) -> Result<Self, Error> {
+        Ok(match backend {
+            BackendKind::Alphabetic => Self::Alphabetic(MerkleBackend::from_accounts(accounts)?),
+            BackendKind::Sparse => Self::Sparse(MerkleBackend::from_accounts(accounts)?),
+            BackendKind::Indexed => Self::Indexed(MerkleBackend::from_accounts(accounts)?),
+        })
+    }
+
+    pub fn backend(&self) -> BackendKind {
+        match self {
+            Self::Alphabetic(_) => BackendKind::Alphabetic,
+            Self::Sparse(_) => BackendKind::Sparse,
+            Self::Indexed(_) => BackendKind::Indexed,
+        }
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        match self {
+            Self::Alphabetic(tree) => MerkleBackend::root(tree),
+            Self::Sparse(tree) => MerkleBackend::root(tree),
+            Self::Indexed(tree) => MerkleBackend::root(tree),
+        }
+    }
+
+    pub fn generate_proof_of_inclusion(
+        &self,
+        account: &AccountWithBalance,
+    ) -> Result<BackendProof, Error> {
+        Ok(match self {
+            Self::Alphabetic(tree) => BackendProof::AlphabeticInclusion {
+                index: tree.index_of(account).ok_or(Error::AccountNotFoundError)?,
+                leaf_count: tree.leaves_len(),
+                proof: MerkleBackend::generate_proof_of_inclusion(tree, account)?,
+            },
+            Self::Sparse(tree) => {
+                BackendProof::Sparse(MerkleBackend::generate_proof_of_inclusion(tree, account)?)
+            }
+            Self::Indexed(tree) => {
+                BackendProof::Indexed(MerkleBackend::generate_proof_of_inclusion(tree, account)?)
+            }
+        })
+    }
+
+    pub fn generate_proof_of_absense(
+        &self,
+        account: &AccountWithBalance,
+    ) -> Result<BackendProof, Error> {
+        Ok(match self {
+            Self::Alphabetic(tree) => BackendProof::AlphabeticAbsence(
+                MerkleBackend::generate_proof_of_absense(tree, account)?,
+            ),
+            Self::Sparse(tree) => {
+                BackendProof::Sparse(MerkleBackend::generate_proof_of_absense(tree, account)?)
+            }
+            Self::Indexed(tree) => {
+                BackendProof::Indexed(MerkleBackend::generate_proof_of_absense(tree, account)?)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::merkle_tree::get_merkle_root;
+    use crate::test_utils::fixed_accounts;
+    use crate::verification::verify_inclusion;
+
+    fn root_of<B: MerkleBackend>(accounts: &[AccountWithBalance]) -> [u8; 32] {
+        B::from_accounts(accounts).unwrap().root()
+    }
+
+    #[test]
+    fn test_backends() {
+        let accounts = fixed_accounts();
+        assert_eq!(
+            root_of::<AlphabeticMerkleTree>(&accounts),
+            get_merkle_root(&accounts).unwrap()
+        );
+        assert_eq!(
+            root_of::<SparseMerkleTree>(&accounts),
+            SparseMerkleTree::new(&accounts).unwrap().root()
+        );
+
+        let absent = AccountWithBalance::new("A7A93fd0a276fc1C0197a5B5623eD117786eeD06", "4");
+        let tree = <SparseMerkleTree as MerkleBackend>::from_accounts(&accounts).unwrap();
+        let proof = MerkleBackend::generate_proof_of_absense(&tree, &absent).unwrap();
+        assert!(proof.verify_absence(tree.root(), &absent).unwrap());
+
+        let tree = <IndexedMerkleTree as MerkleBackend>::from_accounts(&accounts).unwrap();
+        let proof = MerkleBackend::generate_proof_of_absense(&tree, &absent).unwrap();
+        assert!(proof.verify_absence(tree.root(), &absent).unwrap());
+    }
+
+    #[test]
+    fn test_any_merkle_tree() {
+        let accounts = fixed_accounts();
+        let absent = AccountWithBalance::new("A7A93fd0a276fc1C0197a5B5623eD117786eeD06", "4");
+        for backend in [
+            BackendKind::Alphabetic,
+            BackendKind::Sparse,
+            BackendKind::Indexed,
+        ] {
+            assert_eq!(backend.as_str().parse::<BackendKind>().unwrap(), backend);
+            let tree = AnyMerkleTree::new(backend, &accounts).unwrap();
+            assert_eq!(tree.backend(), backend);
+
+            let inclusion = tree.generate_proof_of_inclusion(&accounts[0]).unwrap();
+            let absence = tree.generate_proof_of_absense(&absent).unwrap();
+            let verified = match (&tree, &inclusion, &absence) {
+                (
+                    _,
+                    BackendProof::AlphabeticInclusion {
+                        index,
+                        leaf_count,
+                        proof,
+                    },
+                    BackendProof::AlphabeticAbsence(absence),
+                ) => {
+                    verify_inclusion(tree.root(), &accounts[0], proof, *index, *leaf_count).unwrap()
+                        && absence.verify(tree.root(), &absent).unwrap()
+                }
+                (_, BackendProof::Sparse(inclusion), BackendProof::Sparse(absence)) => {
+                    inclusion
+                        .verify_inclusion(tree.root(), &accounts[0])
+                        .unwrap()
+                        && absence.verify_absence(tree.root(), &absent).unwrap()
+                }
+                (_, BackendProof::Indexed(inclusion), BackendProof::Indexed(absence)) => {
+                    inclusion
+                        .verify_inclusion(tree.root(), &accounts[0])
+                        .unwrap()
+                        && absence.verify_absence(tree.root(), &absent).unwrap()
+                }
+                _ => false,
+            };
+            assert!(verified);
+
+            // Proofs round-trip through JSON with their type
+            for proof in [inclusion, absence] {
+                let json = serde_json::to_string(&proof).unwrap();
+                assert_eq!(serde_json::from_str::<BackendProof>(&json).unwrap(), proof);
+            }
+        }
+        assert!(matches!(
+            "patricia".parse::<BackendKind>(),
+            Err(Error::UnknownBackendError(_))
+        ));
+    }
+}
@@ -0,0 +1,220 @@
+use rs_merkle::MerkleProof;
+use serde::{Deserialize, Serialize};
+
+use crate::account_with_balance::LeafEncoding;
+use crate::alphabetic_merkle_tree::HashMode;
+use crate::commutative_merkle_tree::{verify_multi_proof_with_hasher, MultiProof};
+use crate::hasher::{Keccak256, MerkleHasher, RsMerkleHasher};
+use crate::leaf::Leaf;
+use crate::merkle_tree::Error;
+use crate::verification::expected_multi_proof_len;
+
+/// Proof that several leaves are in the tree. Sibling hashes shared by the
+/// paths of the leaves are included once, so it is smaller than the single
+/// proofs of every leaf.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BatchProof {
+    /// Leaf indices in ascending order. With commutative hashing they are not
+    /// checked by the proof.
+    pub indices: Vec<usize>,
+    /// Hashes of the proven leaves, in the same order as `indices`
+    pub leaf_hashes: Vec<[u8; 32]>,
+    pub proof: Vec<[u8; 32]>,
+    /// `multiProofVerify` flags, only used with commutative hashing
+    #[serde(default)]
+    pub proof_flags: Vec<bool>,
+    pub leaf_count: usize,
+    #[serde(default)]
+    pub hash_mode: HashMode,
+}
+
+impl BatchProof {
+    /// Size of the sibling hashes in bytes
+    pub fn proof_size(&self) -> usize {
+        self.proof.len() * 32
+    }
+
+    pub fn verify(&self, root: [u8; 32]) -> Result<bool, Error> {
+        self.verify_with_hasher::<Keccak256>(root)
+    }
+
+    /// Same as [`BatchProof::verify`] for a tree hashed with `H`
+    pub fn verify_with_hasher<H: MerkleHasher>(&self, root: [u8; 32]) -> Result<bool, Error> {
+        if self.leaf_count == 0 || self.indices.is_empty() {
+            return Err(Error::EmptyAccountsListError);
+        }
+        if self.indices.len() != self.leaf_hashes.len() {
+            return Err(Error::MerkleProofParsingError);
+        }
+        if self.indices.windows(2).any(|x| x[0] >= x[1]) {
+            return Err(Error::MerkleProofParsingError);
+        }
+        if self.indices[self.indices.len() - 1] >= self.leaf_count {
+            return Err(Error::LeafIndexOutOfRangeError);
+        }
+
+        match self.hash_mode {
+            HashMode::Ordered => {
+                if self.proof.len() != expected_multi_proof_len(&self.indices, self.leaf_count) {
+                    return Err(Error::MerkleProofParsingError);
+                }
+                let proof_root = MerkleProof::<RsMerkleHasher<H>>::new(self.proof.clone())
+                    .root(&self.indices, &self.leaf_hashes, self.leaf_count)
+                    .map_err(|_| Error::MerkleProofParsingError)?;
+                Ok(proof_root == root)
+            }
+            HashMode::Commutative => {
+                let multi_proof = MultiProof {
+                    leaves: self.leaf_hashes.clone(),
+                    proof: self.proof.clone(),
+                    proof_flags: self.proof_flags.clone(),
+                };
+                verify_multi_proof_with_hasher::<H>(root, &multi_proof)
+            }
+        }
+    }
+
+    /// Checks that the proof is for exactly these accounts and that all of
+    /// them are in the tree
+    pub fn verify_accounts(
+        &self,
+        root: [u8; 32],
+        accounts: &[impl Leaf],
+        leaf_encoding: LeafEncoding,
+    ) -> Result<bool, Error> {
+        self.verify_accounts_with_hasher::<Keccak256>(root, accounts, leaf_encoding)
+    }
+
+    pub fn verify_accounts_with_hasher<H: MerkleHasher>(
+        &self,
+        root: [u8; 32],
+        accounts: &[impl Leaf],
+        leaf_encoding: LeafEncoding,
+    ) -> Result<bool, Error> {
+        let mut account_hashes: Vec<[u8; 32]> = accounts
+            .iter()
+            .map(|x| x.leaf_hash_with_hasher::<H>(leaf_encoding))
+            .collect();
+        account_hashes.sort_unstable();
+        account_hashes.dedup();
+        let mut leaf_hashes = self.leaf_hashes.clone();
+        leaf_hashes.sort_unstable();
+
+        Ok(account_hashes == leaf_hashes && self.verify_with_hasher::<H>(root)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::account_with_balance::AccountWithBalance;
+    use crate::alphabetic_merkle_tree::{AlphabeticMerkleTree, TreeOptions};
+    use crate::merkle_tree::generate_batch_proof;
+    use crate::test_utils::{fixed_accounts, random_accounts};
+
+    #[test]
+    fn test_batch_proof() {
+        let accounts = fixed_accounts();
+        let tree = AlphabeticMerkleTree::new(&accounts).unwrap();
+
+        let proof =
+            generate_batch_proof(&accounts, &[accounts[4], accounts[0], accounts[4]]).unwrap();
+        assert_eq!(proof.indices, vec![0, 4]);
+        assert_eq!(
+            proof.leaf_hashes,
+            vec![accounts[0].generate_hash(), accounts[4].generate_hash()]
+        );
+        assert!(proof.verify(tree.root()).unwrap());
+        assert!(proof
+            .verify_accounts(
+                tree.root(),
+                &[accounts[0], accounts[4]],
+                LeafEncoding::Packed
+            )
+            .unwrap());
+        assert!(!proof
+            .verify_accounts(
+                tree.root(),
+                &[accounts[0], accounts[3]],
+                LeafEncoding::Packed
+            )
+            .unwrap());
+    }
+
+    #[test]
+    fn test_batch_proof_all_subsets() {
+        let accounts = fixed_accounts();
+        for options in [TreeOptions::default(), TreeOptions::standard()] {
+            let tree = AlphabeticMerkleTree::with_options(&accounts, options).unwrap();
+            for subset in 1..(1 << accounts.len()) {
+                let batch: Vec<AccountWithBalance> = (0..accounts.len())
+                    .filter(|x| subset & (1 << x) != 0)
+                    .map(|x| accounts[x])
+                    .collect();
+                let proof = tree.generate_batch_proof(&batch).unwrap();
+                assert!(proof
+                    .verify_accounts(tree.root(), &batch, options.leaf_encoding)
+                    .unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn test_batch_proof_is_smaller_than_single_proofs() {
+        let accounts = random_accounts(1000, 0);
+        let tree = AlphabeticMerkleTree::new(&accounts).unwrap();
+        let batch: Vec<AccountWithBalance> = accounts[100..200].to_vec();
+
+        let proof = tree.generate_batch_proof(&batch).unwrap();
+        let single_proofs_size: usize = batch
+            .iter()
+            .map(|x| tree.generate_proof_of_inclusion(x).unwrap().len())
+            .sum();
+        assert!(proof.proof_size() < single_proofs_size / 10);
+        assert!(proof.verify(tree.root()).unwrap());
+    }
+
+    #[test]
+    fn test_batch_proof_of_missing_account() {
+        let accounts = fixed_accounts();
+        let missing = AccountWithBalance::new("0000000000000000000000000000000000000001", "1");
+        let result = generate_batch_proof(&accounts, &[accounts[0], missing]);
+        assert!(matches!(result, Err(Error::AccountNotFoundError)));
+    }
+
+    #[test]
+    fn test_malformed_batch_proof() {
+        let accounts = fixed_accounts();
+        let tree = AlphabeticMerkleTree::new(&accounts).unwrap();
+        let proof = tree
+            .generate_batch_proof(&[accounts[1], accounts[2]])
+            .unwrap();
+
+        let mut extra_hash = proof.clone();
+        extra_hash.proof.push(accounts[0].generate_hash());
+        assert!(matches!(
+            extra_hash.verify(tree.root()),
+            Err(Error::MerkleProofParsingError)
+        ));
+
+        let mut unsorted = proof.clone();
+        unsorted.indices.reverse();
+        unsorted.leaf_hashes.reverse();
+        assert!(matches!(
+            unsorted.verify(tree.root()),
+            Err(Error::MerkleProofParsingError)
+        ));
+
+        let mut out_of_range = proof.clone();
+        out_of_range.indices[1] = 5;
+        assert!(matches!(
+            out_of_range.verify(tree.root()),
+            Err(Error::LeafIndexOutOfRangeError)
+        ));
+
+        let mut wrong_index = proof;
+        wrong_index.indices = vec![1, 3];
+        assert!(!wrong_index.verify(tree.root()).unwrap());
+    }
+}
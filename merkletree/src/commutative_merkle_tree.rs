@@ -0,0 +1,478 @@
+use std::marker::PhantomData;
+
+use serde::{Deserialize, Serialize};
+
+use crate::hasher::{Keccak256, MerkleHasher};
+use crate::merkle_tree::Error;
+use crate::parallel;
+
+/// Hashes two nodes in sorted order, like OpenZeppelin's `MerkleProof` library
+pub fn hash_pair(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    hash_pair_with_hasher::<Keccak256>(a, b)
+}
+
+/// Same as [`hash_pair`] with the given hash function
+pub fn hash_pair_with_hasher<H: MerkleHasher>(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    if a <= b {
+        H::hash_pair(a, b)
+    } else {
+        H::hash_pair(b, a)
+    }
+}
+
+fn parent_index(index: usize) -> usize {
+    (index - 1) / 2
+}
+
+fn sibling_index(index: usize) -> usize {
+    if index.is_multiple_of(2) {
+        index - 1
+    } else {
+        index + 1
+    }
+}
+
+/// Proof for several leaves in the format of OpenZeppelin's `multiProofVerify`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MultiProof {
+    pub leaves: Vec<[u8; 32]>,
+    pub proof: Vec<[u8; 32]>,
+    pub proof_flags: Vec<bool>,
+}
+
+/// Merkle tree with commutative pair hashing, laid out like OpenZeppelin's
+/// `@openzeppelin/merkle-tree`: a complete binary tree stored as an array with
+/// the root at 0 and leaf `i` at position `len - 1 - i`. Every inner node has
+/// two children, which is what `multiProofVerify` expects.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommutativeMerkleTree<H = Keccak256> {
+    tree: Vec<[u8; 32]>,
+    leaf_count: usize,
+    hasher: PhantomData<H>,
+}
+
+impl CommutativeMerkleTree {
+    pub fn from_leaves(leaves: &[[u8; 32]]) -> Result<Self, Error> {
+        Self::from_leaves_with_hasher(leaves)
+    }
+}
+
+impl<H: MerkleHasher> CommutativeMerkleTree<H> {
+    pub fn from_leaves_with_hasher(leaves: &[[u8; 32]]) -> Result<Self, Error> {
+        if leaves.is_empty() {
+            return Err(Error::EmptyAccountsListError);
+        }
+        let mut tree = vec![[0u8; 32]; 2 * leaves.len() - 1];
+        let tree_len = tree.len();
+        for (index, leaf) in leaves.iter().enumerate() {
+            tree[tree_len - 1 - index] = *leaf;
+        }
+        // Nodes at the same depth are contiguous and their children are all
+        // further in the array, so a whole level can be hashed at once
+        let mut level_end = tree_len - leaves.len();
+        while level_end > 0 {
+            let level_start = (1 << level_end.ilog2()) - 1;
+            let (nodes, children) = tree.split_at_mut(level_end);
+            let parents = parallel::map_chunks(
+                &children[2 * level_start + 1 - level_end..level_end + 1],
+                2,
+                |pair| hash_pair_with_hasher::<H>(&pair[0], &pair[1]),
+            );
+            nodes[level_start..].copy_from_slice(&parents);
+            level_end = level_start;
+        }
+        Ok(Self {
+            tree,
+            leaf_count: leaves.len(),
+            hasher: PhantomData,
+        })
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.tree[0]
+    }
+
+    pub fn leaves_len(&self) -> usize {
+        self.leaf_count
+    }
+
+    pub fn depth(&self) -> usize {
+        proof_len(0, self.leaf_count)
+    }
+
+    /// Nodes in array order, root first
+    pub fn nodes(&self) -> &[[u8; 32]] {
+        &self.tree
+    }
+
+    fn tree_index(&self, leaf_index: usize) -> usize {
+        self.tree.len() - 1 - leaf_index
+    }
+
+    /// Leaves in leaf order
+    pub fn leaves(&self) -> Vec<[u8; 32]> {
+        self.tree[self.tree.len() - self.leaf_count..]
+            .iter()
+            .rev()
+            .copied()
+            .collect()
+    }
+
+    /// Replaces a leaf, recomputing only its path to the root
+    pub fn update_leaf(&mut self, leaf_index: usize, leaf: [u8; 32]) -> Result<(), Error> {
+        if leaf_index >= self.leaf_count {
+            return Err(Error::LeafIndexOutOfRangeError);
+        }
+        let mut index = self.tree_index(leaf_index);
+        self.tree[index] = leaf;
+        while index > 0 {
+            let node =
+                hash_pair_with_hasher::<H>(&self.tree[index], &self.tree[sibling_index(index)]);
+            index = parent_index(index);
+            self.tree[index] = node;
+        }
+        Ok(())
+    }
+
+    /// Adds a leaf. Every leaf moves in the array layout, so the tree is rebuilt
+    /// from its leaves.
+    pub fn insert_leaf(&mut self, leaf_index: usize, leaf: [u8; 32]) -> Result<(), Error> {
+        if leaf_index > self.leaf_count {
+            return Err(Error::LeafIndexOutOfRangeError);
+        }
+        let mut leaves = self.leaves();
+        leaves.insert(leaf_index, leaf);
+        *self = Self::from_leaves_with_hasher(&leaves)?;
+        Ok(())
+    }
+
+    /// Removes a leaf, rebuilding the tree. The last leaf can't be removed.
+    pub fn remove_leaf(&mut self, leaf_index: usize) -> Result<[u8; 32], Error> {
+        if leaf_index >= self.leaf_count {
+            return Err(Error::LeafIndexOutOfRangeError);
+        }
+        if self.leaf_count == 1 {
+            return Err(Error::EmptyAccountsListError);
+        }
+        let mut leaves = self.leaves();
+        let leaf = leaves.remove(leaf_index);
+        *self = Self::from_leaves_with_hasher(&leaves)?;
+        Ok(leaf)
+    }
+
+    pub fn proof(&self, leaf_index: usize) -> Result<Vec<[u8; 32]>, Error> {
+        if leaf_index >= self.leaf_count {
+            return Err(Error::LeafIndexOutOfRangeError);
+        }
+        let mut index = self.tree_index(leaf_index);
+        let mut proof = vec![];
+        while index > 0 {
+            proof.push(self.tree[sibling_index(index)]);
+            index = parent_index(index);
+        }
+        Ok(proof)
+    }
+
+    /// Proof for several leaves. Leaves in the proof are in ascending leaf order.
+    pub fn multi_proof(&self, leaf_indices: &[usize]) -> Result<MultiProof, Error> {
+        let mut leaf_indices = leaf_indices.to_vec();
+        leaf_indices.sort_unstable();
+        leaf_indices.dedup();
+        if leaf_indices.iter().any(|index| *index >= self.leaf_count) {
+            return Err(Error::LeafIndexOutOfRangeError);
+        }
+
+        // Tree indices in descending order, so deeper nodes are processed first
+        let indices: Vec<usize> = leaf_indices.iter().map(|x| self.tree_index(*x)).collect();
+        let mut stack: std::collections::VecDeque<usize> = indices.iter().copied().collect();
+        let mut proof = vec![];
+        let mut proof_flags = vec![];
+
+        while let Some(&index) = stack.front() {
+            if index == 0 {
+                break;
+            }
+            stack.pop_front();
+            let sibling = sibling_index(index);
+            if stack.front() == Some(&sibling) {
+                proof_flags.push(true);
+                stack.pop_front();
+            } else {
+                proof_flags.push(false);
+                proof.push(self.tree[sibling]);
+            }
+            stack.push_back(parent_index(index));
+        }
+        if indices.is_empty() {
+            proof.push(self.tree[0]);
+        }
+
+        Ok(MultiProof {
+            leaves: indices.iter().map(|x| self.tree[*x]).collect(),
+            proof,
+            proof_flags,
+        })
+    }
+}
+
+/// Number of siblings in the proof of a leaf of a tree with `leaf_count` leaves
+pub fn proof_len(leaf_index: usize, leaf_count: usize) -> usize {
+    let tree_index = 2 * leaf_count - 2 - leaf_index;
+    (tree_index + 1).ilog2() as usize
+}
+
+/// Root obtained from a leaf and its proof, as `MerkleProof.processProof`
+pub fn process_proof(leaf: [u8; 32], proof: &[[u8; 32]]) -> [u8; 32] {
+    process_proof_with_hasher::<Keccak256>(leaf, proof)
+}
+
+pub fn process_proof_with_hasher<H: MerkleHasher>(leaf: [u8; 32], proof: &[[u8; 32]]) -> [u8; 32] {
+    proof.iter().fold(leaf, |node, sibling| {
+        hash_pair_with_hasher::<H>(&node, sibling)
+    })
+}
+
+pub fn verify_proof(root: [u8; 32], leaf: [u8; 32], proof: &[[u8; 32]]) -> bool {
+    verify_proof_with_hasher::<Keccak256>(root, leaf, proof)
+}
+
+pub fn verify_proof_with_hasher<H: MerkleHasher>(
+    root: [u8; 32],
+    leaf: [u8; 32],
+    proof: &[[u8; 32]],
+) -> bool {
+    process_proof_with_hasher::<H>(leaf, proof) == root
+}
+
+/// Root obtained from a multiproof, as `MerkleProof.processMultiProof`. Proofs
+/// the Solidity library would revert on are rejected with a parsing error.
+pub fn process_multi_proof(multi_proof: &MultiProof) -> Result<[u8; 32], Error> {
+    process_multi_proof_with_hasher::<Keccak256>(multi_proof)
+}
+
+pub fn process_multi_proof_with_hasher<H: MerkleHasher>(
+    multi_proof: &MultiProof,
+) -> Result<[u8; 32], Error> {
+    let MultiProof {
+        leaves,
+        proof,
+        proof_flags,
+    } = multi_proof;
+    if leaves.len() + proof.len() != proof_flags.len() + 1 {
+        return Err(Error::MerkleProofParsingError);
+    }
+
+    let mut hashes: Vec<[u8; 32]> = Vec::with_capacity(proof_flags.len());
+    let mut leaf_pos = 0;
+    let mut hash_pos = 0;
+    let mut proof_pos = 0;
+    let mut next_node = |hashes: &Vec<[u8; 32]>| {
+        if leaf_pos < leaves.len() {
+            leaf_pos += 1;
+            Ok(leaves[leaf_pos - 1])
+        } else {
+            hash_pos += 1;
+            hashes
+                .get(hash_pos - 1)
+                .copied()
+                .ok_or(Error::MerkleProofParsingError)
+        }
+    };
+    for flag in proof_flags {
+        let a = next_node(&hashes)?;
+        let b = if *flag {
+            next_node(&hashes)?
+        } else {
+            proof_pos += 1;
+            *proof
+                .get(proof_pos - 1)
+                .ok_or(Error::MerkleProofParsingError)?
+        };
+        hashes.push(hash_pair_with_hasher::<H>(&a, &b));
+    }
+
+    if let Some(root) = hashes.last() {
+        if proof_pos != proof.len() {
+            return Err(Error::MerkleProofParsingError);
+        }
+        Ok(*root)
+    } else if let Some(leaf) = leaves.first() {
+        Ok(*leaf)
+    } else {
+        Ok(proof[0])
+    }
+}
+
+pub fn verify_multi_proof(root: [u8; 32], multi_proof: &MultiProof) -> Result<bool, Error> {
+    verify_multi_proof_with_hasher::<Keccak256>(root, multi_proof)
+}
+
+pub fn verify_multi_proof_with_hasher<H: MerkleHasher>(
+    root: [u8; 32],
+    multi_proof: &MultiProof,
+) -> Result<bool, Error> {
+    Ok(process_multi_proof_with_hasher::<H>(multi_proof)? == root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ethers::utils::{hex, keccak256};
+
+    use crate::test_utils::fixed_accounts;
+
+    fn hash(hex_str: &str) -> [u8; 32] {
+        hex::decode(hex_str).unwrap().try_into().unwrap()
+    }
+
+    fn fixed_tree() -> CommutativeMerkleTree {
+        let leaves: Vec<[u8; 32]> = fixed_accounts().iter().map(|x| x.generate_hash()).collect();
+        CommutativeMerkleTree::from_leaves(&leaves).unwrap()
+    }
+
+    #[test]
+    fn test_hash_pair_is_commutative() {
+        let a = fixed_accounts()[0].generate_hash();
+        let b = fixed_accounts()[1].generate_hash();
+        let (left, right) = if a < b { (a, b) } else { (b, a) };
+        assert_eq!(hash_pair(&a, &b), hash_pair(&b, &a));
+        assert_eq!(hash_pair(&a, &b), keccak256([left, right].concat()));
+    }
+
+    #[test]
+    fn test_commutative_root() {
+        let tree = fixed_tree();
+
+        assert_eq!(tree.leaves_len(), 5);
+        assert_eq!(tree.depth(), 3);
+        assert_eq!(tree.nodes().len(), 9);
+        //Manually calculated root hash
+        assert_eq!(
+            tree.root(),
+            hash("2730f9b1706b5d1ecec9572e03219a02646fdf834e9351f9a000d4c772a1ae05")
+        );
+    }
+
+    #[test]
+    fn test_commutative_proofs() {
+        let tree = fixed_tree();
+        let leaves: Vec<[u8; 32]> = fixed_accounts().iter().map(|x| x.generate_hash()).collect();
+
+        //Manually calculated proofs
+        let expected_proofs = [
+            vec![
+                "90ded3a4d0f218e07618f81fcf76b5198d4cd5607e15318d85189ac4d9b22b6d",
+                "e0e6356685523ed6f21c8fca424d47501d9049e5a5d6239d921d866f4a1ae281",
+                "2aabe1fe8e37b15e4136e4b359658dfd3ff23741b03c99ff05e403777ace3230",
+            ],
+            vec![
+                "e1f57beaed1a18459227ef6e8706052ac49223120578ce1bab98c09eb9eb4c72",
+                "e0e6356685523ed6f21c8fca424d47501d9049e5a5d6239d921d866f4a1ae281",
+                "2aabe1fe8e37b15e4136e4b359658dfd3ff23741b03c99ff05e403777ace3230",
+            ],
+            vec![
+                "771def266542083afd79664d7b1163a450ca3caeaef254533140a7633b574925",
+                "069fd26efba57d264314c405fb3a1f44eaa73314111a0dd5738a206b3d72b911",
+            ],
+            vec![
+                "98499df905a0788f937655abd5170e0a5152d473140de10d2309f43f5d3b3003",
+                "069fd26efba57d264314c405fb3a1f44eaa73314111a0dd5738a206b3d72b911",
+            ],
+            vec![
+                "629ba62f06bbf2c8077561aab497822ec7ada0aabcd7ae01fa44e157aab35c95",
+                "2aabe1fe8e37b15e4136e4b359658dfd3ff23741b03c99ff05e403777ace3230",
+            ],
+        ];
+
+        for (index, expected) in expected_proofs.iter().enumerate() {
+            let proof = tree.proof(index).unwrap();
+            let expected: Vec<[u8; 32]> = expected.iter().map(|x| hash(x)).collect();
+            assert_eq!(proof, expected);
+            assert_eq!(proof.len(), proof_len(index, 5));
+            assert!(verify_proof(tree.root(), leaves[index], &proof));
+            assert!(!verify_proof(tree.root(), leaves[(index + 1) % 5], &proof));
+        }
+        assert!(matches!(
+            tree.proof(5),
+            Err(Error::LeafIndexOutOfRangeError)
+        ));
+    }
+
+    #[test]
+    fn test_commutative_multi_proof() {
+        let tree = fixed_tree();
+        let multi_proof = tree.multi_proof(&[4, 0, 2]).unwrap();
+
+        //Manually calculated multiproof
+        assert_eq!(
+            multi_proof,
+            MultiProof {
+                leaves: vec![
+                    hash("e1f57beaed1a18459227ef6e8706052ac49223120578ce1bab98c09eb9eb4c72"),
+                    hash("98499df905a0788f937655abd5170e0a5152d473140de10d2309f43f5d3b3003"),
+                    hash("e0e6356685523ed6f21c8fca424d47501d9049e5a5d6239d921d866f4a1ae281"),
+                ],
+                proof: vec![
+                    hash("90ded3a4d0f218e07618f81fcf76b5198d4cd5607e15318d85189ac4d9b22b6d"),
+                    hash("771def266542083afd79664d7b1163a450ca3caeaef254533140a7633b574925"),
+                ],
+                proof_flags: vec![false, false, true, true],
+            }
+        );
+        assert!(verify_multi_proof(tree.root(), &multi_proof).unwrap());
+    }
+
+    #[test]
+    fn test_commutative_multi_proof_all_subsets() {
+        let tree = fixed_tree();
+        for subset in 1..(1 << 5) {
+            let indices: Vec<usize> = (0..5).filter(|x| subset & (1 << x) != 0).collect();
+            let multi_proof = tree.multi_proof(&indices).unwrap();
+            assert_eq!(multi_proof.leaves.len(), indices.len());
+            assert!(verify_multi_proof(tree.root(), &multi_proof).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_commutative_multi_proof_malformed() {
+        let tree = fixed_tree();
+        let mut multi_proof = tree.multi_proof(&[0, 2]).unwrap();
+        multi_proof.proof.pop();
+        assert!(matches!(
+            verify_multi_proof(tree.root(), &multi_proof),
+            Err(Error::MerkleProofParsingError)
+        ));
+
+        let mut multi_proof = tree.multi_proof(&[0, 2]).unwrap();
+        multi_proof.leaves.swap(0, 1);
+        assert!(!verify_multi_proof(tree.root(), &multi_proof).unwrap());
+    }
+
+    #[test]
+    fn test_single_leaf_tree() {
+        let leaf = fixed_accounts()[0].generate_hash();
+        let tree = CommutativeMerkleTree::from_leaves(&[leaf]).unwrap();
+        assert_eq!(tree.root(), leaf);
+        assert!(tree.proof(0).unwrap().is_empty());
+        assert!(verify_multi_proof(leaf, &tree.multi_proof(&[0]).unwrap()).unwrap());
+    }
+
+    #[test]
+    fn test_update_leaf_matches_rebuild() {
+        let mut leaves: Vec<[u8; 32]> =
+            fixed_accounts().iter().map(|x| x.generate_hash()).collect();
+        let mut tree = fixed_tree();
+        for index in [0, 3, 4] {
+            leaves[index] = [index as u8; 32];
+            tree.update_leaf(index, leaves[index]).unwrap();
+            assert_eq!(tree, CommutativeMerkleTree::from_leaves(&leaves).unwrap());
+        }
+        assert_eq!(tree.leaves(), leaves);
+
+        tree.insert_leaf(2, [9u8; 32]).unwrap();
+        assert_eq!(tree.leaves()[2], [9u8; 32]);
+        assert_eq!(tree.remove_leaf(2).unwrap(), [9u8; 32]);
+        assert_eq!(tree, CommutativeMerkleTree::from_leaves(&leaves).unwrap());
+    }
+}
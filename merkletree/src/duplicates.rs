@@ -0,0 +1,320 @@
+use std::any::Any;
+use std::borrow::Cow;
+use std::fmt;
+
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+
+use crate::leaf::Leaf;
+use crate::merkle_tree::Error;
+
+/// What to do with several leaves of the same address, or of the same
+/// [`Leaf::key`] for other leaves
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum DuplicatePolicy {
+    /// Leaves are used as given: with packed ordering every balance of an
+    /// address is its own leaf, address ordering fails on duplicates
+    #[default]
+    Allow,
+    /// Fails with every duplicated address and its positions
+    Reject,
+    /// One leaf with the sum of the balances
+    Sum,
+    /// The leaf with the largest balance
+    KeepMax,
+    /// The leaf that comes last in the input
+    KeepLast,
+}
+
+/// Key appearing more than once, with its positions in the input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Duplicate<K = Address> {
+    pub key: K,
+    pub positions: Vec<usize>,
+}
+
+/// Key of any leaf type, as kept in errors
+pub trait DuplicateKey: Any + fmt::Debug + Send + Sync {}
+
+impl<K: Any + fmt::Debug + Send + Sync> DuplicateKey for K {}
+
+impl<K: DuplicateKey> Duplicate<K> {
+    pub(crate) fn boxed(self) -> Duplicate<Box<dyn DuplicateKey>> {
+        Duplicate {
+            key: Box::new(self.key),
+            positions: self.positions,
+        }
+    }
+}
+
+impl Duplicate<Box<dyn DuplicateKey>> {
+    /// The key, if the leaves have keys of type `K`
+    pub fn key_as<K: Any>(&self) -> Option<&K> {
+        (&*self.key as &dyn Any).downcast_ref()
+    }
+}
+
+impl<K: fmt::Debug> fmt::Display for Duplicate<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} at {:?}", self.key, self.positions)
+    }
+}
+
+/// Runs of positions sharing a key, in key order
+fn key_groups<L: Leaf>(accounts: &[L]) -> Vec<Vec<usize>> {
+    let mut positions: Vec<usize> = (0..accounts.len()).collect();
+    // Stable, so positions of a key stay in input order
+    positions.sort_by_key(|position| accounts[*position].key());
+    positions
+        .chunk_by(|a, b| accounts[*a].key() == accounts[*b].key())
+        .map(|group| group.to_vec())
+        .collect()
+}
+
+/// Every key appearing more than once, in key order
+pub fn find_duplicates<L: Leaf>(accounts: &[L]) -> Vec<Duplicate<L::Key>> {
+    key_groups(accounts)
+        .into_iter()
+        .filter(|positions| positions.len() > 1)
+        .map(|positions| Duplicate {
+            key: accounts[positions[0]].key(),
+            positions,
+        })
+        .collect()
+}
+
+/// Error with every key of the leaves appearing more than once
+pub(crate) fn duplicates_error<L: Leaf>(accounts: &[L]) -> Error {
+    Error::DuplicateAddressError(
+        find_duplicates(accounts)
+            .into_iter()
+            .map(Duplicate::boxed)
+            .collect(),
+    )
+}
+
+/// Merges a leaf with a later one of the same key
+pub(crate) fn merge<L: Leaf>(earlier: &L, later: &L, policy: DuplicatePolicy) -> Result<L, Error> {
+    let balances = || {
+        earlier
+            .balance()
+            .zip(later.balance())
+            .ok_or(Error::UnsupportedDuplicatePolicyError)
+    };
+    match policy {
+        DuplicatePolicy::Allow | DuplicatePolicy::Reject => Err(Error::AccountAlreadyExistsError),
+        DuplicatePolicy::Sum => {
+            let (earlier_balance, later_balance) = balances()?;
+            let balance = earlier_balance
+                .checked_add(later_balance)
+                .ok_or(Error::BalanceSumOverflowError)?;
+            Ok(later.with_balance(balance))
+        }
+        DuplicatePolicy::KeepMax => {
+            let (earlier_balance, later_balance) = balances()?;
+            Ok(if earlier_balance > later_balance {
+                *earlier
+            } else {
+                *later
+            })
+        }
+        DuplicatePolicy::KeepLast => Ok(*later),
+    }
+}
+
+/// Applies the policy, leaving one leaf per key unless duplicates are allowed.
+/// Merged leaves are returned in key order.
+pub fn resolve_duplicates<L: Leaf>(
+    accounts: &[L],
+    policy: DuplicatePolicy,
+) -> Result<Cow<'_, [L]>, Error> {
+    match policy {
+        DuplicatePolicy::Allow => Ok(Cow::Borrowed(accounts)),
+        DuplicatePolicy::Reject => {
+            if key_groups(accounts).iter().any(|x| x.len() > 1) {
+                return Err(duplicates_error(accounts));
+            }
+            Ok(Cow::Borrowed(accounts))
+        }
+        _ => Ok(Cow::Owned(
+            key_groups(accounts)
+                .into_iter()
+                .map(|positions| {
+                    positions[1..]
+                        .iter()
+                        .try_fold(accounts[positions[0]], |merged, position| {
+                            merge(&merged, &accounts[*position], policy)
+                        })
+                })
+                .collect::<Result<Vec<L>, Error>>()?,
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ethers::types::U256;
+
+    use crate::account_with_balance::AccountWithBalance;
+    use crate::alphabetic_merkle_tree::{AlphabeticMerkleTree, OrderingMode, TreeOptions};
+    use crate::leaf::TokenOwner;
+    use crate::test_utils::fixed_accounts;
+
+    /// The fixed accounts with the second one repeated twice and the fourth
+    /// one repeated once
+    fn duplicated_accounts() -> Vec<AccountWithBalance> {
+        let accounts = fixed_accounts();
+        let mut duplicated = accounts.clone();
+        duplicated.push(AccountWithBalance {
+            balance: U256::from(50),
+            ..accounts[1]
+        });
+        duplicated.push(AccountWithBalance {
+            balance: U256::from(5),
+            ..accounts[3]
+        });
+        duplicated.push(AccountWithBalance {
+            balance: U256::from(7),
+            ..accounts[1]
+        });
+        duplicated
+    }
+
+    fn balance_of(tree: &AlphabeticMerkleTree, account: &AccountWithBalance) -> U256 {
+        let index = tree.index_of_address(account.address).unwrap();
+        tree.accounts()[index].balance
+    }
+
+    #[test]
+    fn test_find_duplicates() {
+        let accounts = duplicated_accounts();
+        assert!(find_duplicates(&fixed_accounts()).is_empty());
+        assert_eq!(
+            find_duplicates(&accounts),
+            vec![
+                Duplicate {
+                    key: accounts[1].address,
+                    positions: vec![1, 5, 7],
+                },
+                Duplicate {
+                    key: accounts[3].address,
+                    positions: vec![3, 6],
+                },
+            ]
+        );
+
+        let options = TreeOptions {
+            duplicate_policy: DuplicatePolicy::Reject,
+            ..Default::default()
+        };
+        let Err(Error::DuplicateAddressError(duplicates)) =
+            AlphabeticMerkleTree::with_options(&accounts, options)
+        else {
+            panic!("duplicates are rejected")
+        };
+        assert_eq!(duplicates.len(), 2);
+        assert_eq!(duplicates[0].positions, vec![1, 5, 7]);
+        assert_eq!(
+            duplicates[1].to_string(),
+            format!("{:?} at [3, 6]", accounts[3].address)
+        );
+    }
+
+    #[test]
+    fn test_merge_policies() {
+        let accounts = duplicated_accounts();
+        let fixed = fixed_accounts();
+        for (policy, balances) in [
+            (DuplicatePolicy::Sum, [U256::from(157), U256::from(15)]),
+            (DuplicatePolicy::KeepMax, [U256::from(100), U256::from(10)]),
+            (DuplicatePolicy::KeepLast, [U256::from(7), U256::from(5)]),
+        ] {
+            for ordering in [OrderingMode::Packed, OrderingMode::Address] {
+                let options = TreeOptions {
+                    ordering,
+                    duplicate_policy: policy,
+                    ..Default::default()
+                };
+                let tree = AlphabeticMerkleTree::with_options(&accounts, options).unwrap();
+                assert_eq!(tree.options().duplicate_policy, policy);
+                assert_eq!(tree.leaves_len(), 5);
+                assert_eq!(balance_of(&tree, &fixed[1]), balances[0]);
+                assert_eq!(balance_of(&tree, &fixed[3]), balances[1]);
+                assert_eq!(balance_of(&tree, &fixed[0]), fixed[0].balance);
+            }
+        }
+
+        // Without a policy every balance of an address is its own leaf
+        let tree = AlphabeticMerkleTree::new(&accounts).unwrap();
+        assert_eq!(tree.leaves_len(), accounts.len());
+    }
+
+    #[test]
+    fn test_insert_follows_policy() {
+        let accounts = fixed_accounts();
+        let duplicated = duplicated_accounts();
+        for policy in [
+            DuplicatePolicy::Sum,
+            DuplicatePolicy::KeepMax,
+            DuplicatePolicy::KeepLast,
+        ] {
+            let options = TreeOptions {
+                duplicate_policy: policy,
+                ..Default::default()
+            };
+            let mut tree = AlphabeticMerkleTree::with_options(&accounts, options).unwrap();
+            for account in duplicated[accounts.len()..].iter() {
+                tree.insert(*account).unwrap();
+            }
+            let rebuilt = AlphabeticMerkleTree::with_options(&duplicated, options).unwrap();
+            assert_eq!(tree.root(), rebuilt.root());
+        }
+
+        let options = TreeOptions {
+            duplicate_policy: DuplicatePolicy::Reject,
+            ..Default::default()
+        };
+        let mut tree = AlphabeticMerkleTree::with_options(&accounts, options).unwrap();
+        assert!(matches!(
+            tree.insert(duplicated[5]),
+            Err(Error::AccountAlreadyExistsError)
+        ));
+    }
+
+    #[test]
+    fn test_merge_errors() {
+        let mut accounts = fixed_accounts();
+        accounts.push(AccountWithBalance {
+            balance: U256::MAX,
+            ..accounts[0]
+        });
+        let options = TreeOptions {
+            duplicate_policy: DuplicatePolicy::Sum,
+            ..Default::default()
+        };
+        let result = AlphabeticMerkleTree::with_options(&accounts, options);
+        assert!(matches!(result, Err(Error::BalanceSumOverflowError)));
+
+        // Token owners have no balance to add up, but can keep the last owner
+        let owner = TokenOwner {
+            token_id: U256::one(),
+            owner: accounts[0].address,
+        };
+        let tokens = [
+            owner,
+            TokenOwner {
+                owner: accounts[1].address,
+                ..owner
+            },
+        ];
+        let result = resolve_duplicates(&tokens, DuplicatePolicy::Sum);
+        assert!(matches!(
+            result,
+            Err(Error::UnsupportedDuplicatePolicyError)
+        ));
+        let resolved = resolve_duplicates(&tokens, DuplicatePolicy::KeepLast).unwrap();
+        assert_eq!(resolved[..], tokens[1..]);
+    }
+}
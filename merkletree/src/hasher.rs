@@ -0,0 +1,199 @@
+use std::marker::PhantomData;
+
+use ethers::utils::keccak256;
+
+/// Hash function used for leaves and inner nodes of the tree
+pub trait MerkleHasher: Clone {
+    /// Byte recorded in tree files, so a file is only opened with the hash
+    /// function it was written with. 0 to 3 are taken by the hashers of this
+    /// crate.
+    const ID: u8;
+
+    fn hash(data: &[u8]) -> [u8; 32];
+
+    /// Parent of two nodes, `hash(left ++ right)` unless the hash function has
+    /// a cheaper way to combine two digests
+    fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+        let mut concatenated = [0u8; 64];
+        concatenated[..32].copy_from_slice(left);
+        concatenated[32..].copy_from_slice(right);
+        Self::hash(&concatenated)
+    }
+}
+
+/// Keccak-256, the hash function of the EVM and the default of the tree
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Keccak256;
+
+impl MerkleHasher for Keccak256 {
+    const ID: u8 = 0;
+
+    fn hash(data: &[u8]) -> [u8; 32] {
+        keccak256(data)
+    }
+}
+
+#[cfg(feature = "sha256")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Sha256;
+
+#[cfg(feature = "sha256")]
+impl MerkleHasher for Sha256 {
+    const ID: u8 = 1;
+
+    fn hash(data: &[u8]) -> [u8; 32] {
+        use sha2::Digest;
+        sha2::Sha256::digest(data).into()
+    }
+}
+
+#[cfg(feature = "blake3")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Blake3;
+
+#[cfg(feature = "blake3")]
+impl MerkleHasher for Blake3 {
+    const ID: u8 = 2;
+
+    fn hash(data: &[u8]) -> [u8; 32] {
+        blake3::hash(data).into()
+    }
+}
+
+/// Circom-compatible Poseidon over the BN254 scalar field.
+///
+/// Inner nodes are `poseidon(left, right)` with both digests read as big-endian
+/// field elements. Byte strings are hashed as their length followed by 31-byte
+/// big-endian chunks, chaining through the first input when they don't fit in
+/// a single permutation.
+#[cfg(feature = "poseidon")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Poseidon;
+
+#[cfg(feature = "poseidon")]
+impl Poseidon {
+    /// Largest number of inputs of the circom parameters
+    const MAX_INPUTS: usize = 12;
+
+    fn hash_elements(inputs: &[ark_bn254::Fr]) -> ark_bn254::Fr {
+        use light_poseidon::PoseidonHasher;
+
+        light_poseidon::Poseidon::<ark_bn254::Fr>::new_circom(inputs.len())
+            .and_then(|mut poseidon| poseidon.hash(inputs))
+            .expect("number of inputs is within the circom parameters")
+    }
+
+    fn to_bytes(element: ark_bn254::Fr) -> [u8; 32] {
+        use ark_ff::{BigInteger, PrimeField};
+
+        let mut bytes = [0u8; 32];
+        let element_bytes = element.into_bigint().to_bytes_be();
+        bytes[32 - element_bytes.len()..].copy_from_slice(&element_bytes);
+        bytes
+    }
+}
+
+#[cfg(feature = "poseidon")]
+impl MerkleHasher for Poseidon {
+    const ID: u8 = 3;
+
+    fn hash(data: &[u8]) -> [u8; 32] {
+        use ark_ff::PrimeField;
+
+        let mut inputs = vec![ark_bn254::Fr::from(data.len() as u64)];
+        inputs.extend(data.chunks(31).map(ark_bn254::Fr::from_be_bytes_mod_order));
+
+        let first_len = inputs.len().min(Self::MAX_INPUTS);
+        let mut state = Self::hash_elements(&inputs[..first_len]);
+        for chunk in inputs[first_len..].chunks(Self::MAX_INPUTS - 1) {
+            let mut chained = vec![state];
+            chained.extend_from_slice(chunk);
+            state = Self::hash_elements(&chained);
+        }
+        Self::to_bytes(state)
+    }
+
+    fn hash_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+        use ark_ff::PrimeField;
+
+        Self::to_bytes(Self::hash_elements(&[
+            ark_bn254::Fr::from_be_bytes_mod_order(left),
+            ark_bn254::Fr::from_be_bytes_mod_order(right),
+        ]))
+    }
+}
+
+/// Adapter running a [`MerkleHasher`] inside `rs_merkle`
+#[derive(Clone)]
+pub(crate) struct RsMerkleHasher<H>(PhantomData<H>);
+
+impl<H: MerkleHasher> rs_merkle::Hasher for RsMerkleHasher<H> {
+    type Hash = [u8; 32];
+
+    fn hash(data: &[u8]) -> [u8; 32] {
+        H::hash(data)
+    }
+
+    fn concat_and_hash(left: &[u8; 32], right: Option<&[u8; 32]>) -> [u8; 32] {
+        match right {
+            Some(right) => H::hash_pair(left, right),
+            None => *left,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keccak256_hash_pair() {
+        let left = Keccak256::hash(b"left");
+        let right = Keccak256::hash(b"right");
+        assert_eq!(
+            Keccak256::hash_pair(&left, &right),
+            keccak256([left, right].concat())
+        );
+    }
+
+    #[cfg(feature = "sha256")]
+    #[test]
+    fn test_sha256() {
+        assert_eq!(
+            ethers::utils::hex::encode(Sha256::hash(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[cfg(feature = "blake3")]
+    #[test]
+    fn test_blake3() {
+        assert_eq!(
+            ethers::utils::hex::encode(Blake3::hash(b"abc")),
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+    }
+
+    #[cfg(feature = "poseidon")]
+    #[test]
+    fn test_poseidon_hash_pair() {
+        // poseidon([1, 2]) from circomlib
+        let mut left = [0u8; 32];
+        left[31] = 1;
+        let mut right = [0u8; 32];
+        right[31] = 2;
+        assert_eq!(
+            ethers::utils::hex::encode(Poseidon::hash_pair(&left, &right)),
+            "115cc0f5e7d690413df64c6b9662e9cf2a3617f2743245519e19607a4417189a"
+        );
+    }
+
+    #[cfg(feature = "poseidon")]
+    #[test]
+    fn test_poseidon_hash_of_long_input() {
+        // More chunks than inputs of a single permutation
+        let data = [7u8; 500];
+        assert_ne!(Poseidon::hash(&data), Poseidon::hash(&data[..499]));
+        assert_ne!(Poseidon::hash(&[0]), Poseidon::hash(&[0, 0]));
+    }
+}
@@ -0,0 +1,396 @@
+use ethers::types::{Address, U256};
+use serde::{Deserialize, Serialize};
+
+use crate::account_with_balance::AccountWithBalance;
+use crate::duplicates::duplicates_error;
+use crate::hasher::{Keccak256, MerkleHasher};
+use crate::merkle_tree::Error;
+use crate::ordered_merkle_tree::OrderedMerkleTree;
+use crate::verification::ordered_proof_root;
+
+/// Leaf of an [`IndexedMerkleTree`]: an account and the address of the next
+/// leaf, zero for the last one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct IndexedLeaf {
+    pub account: AccountWithBalance,
+    pub next_address: Address,
+}
+
+impl IndexedLeaf {
+    /// `keccak256(abi.encodePacked(address, uint256 balance, next_address))`.
+    /// The 72 bytes hashed can't be mistaken for the 64 bytes of two nodes.
+    pub fn hash(&self) -> [u8; 32] {
+        self.hash_with_hasher::<Keccak256>()
+    }
+
+    pub fn hash_with_hasher<H: MerkleHasher>(&self) -> [u8; 32] {
+        let mut preimage = [0u8; 72];
+        preimage[..52].copy_from_slice(&self.account.to_record());
+        preimage[52..].copy_from_slice(self.next_address.as_bytes());
+        H::hash(&preimage)
+    }
+
+    /// Whether the address falls in the gap between this leaf and the next one
+    pub fn covers(&self, address: Address) -> bool {
+        self.account.address < address
+            && (self.next_address.is_zero() || address < self.next_address)
+    }
+}
+
+/// Leaf of an [`IndexedMerkleTree`] with its position and inclusion proof.
+///
+/// It proves the account of the leaf is included, and that every address in
+/// the gap after it is absent. Addresses before the first leaf are proven
+/// absent by the leaf at index 0.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct IndexedLeafProof {
+    pub leaf: IndexedLeaf,
+    pub index: usize,
+    pub proof: Vec<u8>,
+    pub leaf_count: usize,
+}
+
+impl IndexedLeafProof {
+    /// Checks the leaf is at `index` of the tree with the given root
+    pub fn verify(&self, root: [u8; 32]) -> Result<bool, Error> {
+        self.verify_with_hasher::<Keccak256>(root)
+    }
+
+    pub fn verify_with_hasher<H: MerkleHasher>(&self, root: [u8; 32]) -> Result<bool, Error> {
+        let merkle_root = ordered_proof_root::<H>(
+            self.leaf.hash_with_hasher::<H>(),
+            &self.proof,
+            self.index,
+            self.leaf_count,
+        )?;
+        Ok(bind_leaf_count::<H>(merkle_root, self.leaf_count) == root)
+    }
+
+    pub fn verify_inclusion(
+        &self,
+        root: [u8; 32],
+        account: &AccountWithBalance,
+    ) -> Result<bool, Error> {
+        self.verify_inclusion_with_hasher::<Keccak256>(root, account)
+    }
+
+    pub fn verify_inclusion_with_hasher<H: MerkleHasher>(
+        &self,
+        root: [u8; 32],
+        account: &AccountWithBalance,
+    ) -> Result<bool, Error> {
+        Ok(self.leaf.account == *account && self.verify_with_hasher::<H>(root)?)
+    }
+
+    /// Checks the account is not in the tree: either its address has another
+    /// balance, or the address falls in a gap of the linked list
+    pub fn verify_absence(
+        &self,
+        root: [u8; 32],
+        account: &AccountWithBalance,
+    ) -> Result<bool, Error> {
+        self.verify_absence_with_hasher::<Keccak256>(root, account)
+    }
+
+    pub fn verify_absence_with_hasher<H: MerkleHasher>(
+        &self,
+        root: [u8; 32],
+        account: &AccountWithBalance,
+    ) -> Result<bool, Error> {
+        let leaf_account = self.leaf.account;
+        let absent = if leaf_account.address == account.address {
+            leaf_account.balance != account.balance
+        } else {
+            self.leaf.covers(account.address)
+                || (self.index == 0 && account.address < leaf_account.address)
+        };
+        Ok(absent && self.verify_with_hasher::<H>(root)?)
+    }
+}
+
+/// `H(merkle_root ++ uint256(leaf_count))`, so a proof can't claim another
+/// tree size and move a node to a position where it reads as a leaf
+fn bind_leaf_count<H: MerkleHasher>(merkle_root: [u8; 32], leaf_count: usize) -> [u8; 32] {
+    let mut count = [0u8; 32];
+    U256::from(leaf_count).to_big_endian(&mut count);
+    H::hash_pair(&merkle_root, &count)
+}
+
+/// Merkle tree over accounts ordered by address where every leaf links to the
+/// next address, so a single leaf proves an address is absent. The root
+/// commits to the number of leaves.
+pub struct IndexedMerkleTree<H: MerkleHasher = Keccak256> {
+    leaves: Vec<IndexedLeaf>,
+    merkle_tree: OrderedMerkleTree<H>,
+}
+
+impl IndexedMerkleTree {
+    pub fn new(accounts: &[AccountWithBalance]) -> Result<Self, Error> {
+        Self::with_hasher(accounts)
+    }
+}
+
+impl<H: MerkleHasher> IndexedMerkleTree<H> {
+    /// Fails if any address appears more than once
+    pub fn with_hasher(accounts: &[AccountWithBalance]) -> Result<Self, Error> {
+        let mut sorted_accounts = accounts.to_vec();
+        sorted_accounts.sort_unstable_by_key(|x| x.address);
+        if sorted_accounts
+            .windows(2)
+            .any(|x| x[0].address == x[1].address)
+        {
+            return Err(duplicates_error(accounts));
+        }
+        let accounts = sorted_accounts;
+
+        let leaves: Vec<IndexedLeaf> = accounts
+            .iter()
+            .enumerate()
+            .map(|(index, account)| IndexedLeaf {
+                account: *account,
+                next_address: accounts
+                    .get(index + 1)
+                    .map(|next| next.address)
+                    .unwrap_or_default(),
+            })
+            .collect();
+        let leaf_hashes: Vec<[u8; 32]> = leaves.iter().map(|x| x.hash_with_hasher::<H>()).collect();
+        let merkle_tree = OrderedMerkleTree::from_leaves_with_hasher(&leaf_hashes)?;
+
+        Ok(Self {
+            leaves,
+            merkle_tree,
+        })
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        bind_leaf_count::<H>(self.merkle_tree.root(), self.leaves_len())
+    }
+
+    pub fn leaves_len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    /// Leaves in address order
+    pub fn leaves(&self) -> &[IndexedLeaf] {
+        &self.leaves
+    }
+
+    fn leaf_proof(&self, index: usize) -> IndexedLeafProof {
+        IndexedLeafProof {
+            leaf: self.leaves[index],
+            index,
+            proof: self
+                .merkle_tree
+                .proof(&[index])
+                .expect("leaf index comes from the tree")
+                .concat(),
+            leaf_count: self.leaves_len(),
+        }
+    }
+
+    /// Leaf with the address, or the leaf whose gap covers it. Addresses
+    /// before the first leaf give the first leaf.
+    fn low_leaf_index(&self, address: Address) -> usize {
+        let next_index = self
+            .leaves
+            .partition_point(|x| x.account.address <= address);
+        next_index.saturating_sub(1)
+    }
+
+    pub fn generate_proof_of_inclusion(
+        &self,
+        account: &AccountWithBalance,
+    ) -> Result<IndexedLeafProof, Error> {
+        let index = self.low_leaf_index(account.address);
+        if self.leaves[index].account != *account {
+            return Err(Error::AccountNotFoundError);
+        }
+        Ok(self.leaf_proof(index))
+    }
+
+    pub fn generate_proof_of_absense(
+        &self,
+        account: &AccountWithBalance,
+    ) -> Result<IndexedLeafProof, Error> {
+        let index = self.low_leaf_index(account.address);
+        if self.leaves[index].account == *account {
+            return Err(Error::AccountAlreadyExistsError);
+        }
+        Ok(self.leaf_proof(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ethers::types::U256;
+
+    use crate::test_utils::{fixed_accounts, random_accounts};
+
+    fn account(address: &str) -> AccountWithBalance {
+        AccountWithBalance::new(address, "1")
+    }
+
+    #[test]
+    fn test_leaves_link_to_next_address() {
+        let accounts = fixed_accounts();
+        let tree = IndexedMerkleTree::new(&accounts).unwrap();
+
+        for (leaf, next) in tree.leaves().iter().zip(accounts[1..].iter()) {
+            assert_eq!(leaf.next_address, next.address);
+        }
+        assert!(tree.leaves()[4].next_address.is_zero());
+    }
+
+    #[test]
+    fn test_proof_of_inclusion() {
+        let accounts = fixed_accounts();
+        let tree = IndexedMerkleTree::new(&accounts).unwrap();
+
+        for (index, account) in accounts.iter().enumerate() {
+            let proof = tree.generate_proof_of_inclusion(account).unwrap();
+            assert_eq!(proof.index, index);
+            assert!(proof.verify_inclusion(tree.root(), account).unwrap());
+            assert!(!proof.verify_absence(tree.root(), account).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_proof_of_absence_in_first_gap() {
+        let tree = IndexedMerkleTree::new(&fixed_accounts()).unwrap();
+        let absent = account("0000000000000000000000000000000000000001");
+
+        let proof = tree.generate_proof_of_absense(&absent).unwrap();
+        assert_eq!(proof.index, 0);
+        assert!(proof.verify_absence(tree.root(), &absent).unwrap());
+    }
+
+    #[test]
+    fn test_proof_of_absence_in_middle_gap() {
+        let tree = IndexedMerkleTree::new(&fixed_accounts()).unwrap();
+        let absent = account("A7A93fd0a276fc1C0197a5B5623eD117786eeD07");
+
+        let proof = tree.generate_proof_of_absense(&absent).unwrap();
+        assert_eq!(proof.index, 2);
+        assert!(proof.leaf.covers(absent.address));
+        assert!(proof.verify_absence(tree.root(), &absent).unwrap());
+    }
+
+    #[test]
+    fn test_proof_of_absence_in_last_gap() {
+        let tree = IndexedMerkleTree::new(&fixed_accounts()).unwrap();
+        let absent = account("FF54284f345afc66a98fbB0a0Afe71e0F007B948");
+
+        let proof = tree.generate_proof_of_absense(&absent).unwrap();
+        assert_eq!(proof.index, 4);
+        assert!(proof.verify_absence(tree.root(), &absent).unwrap());
+    }
+
+    #[test]
+    fn test_proof_of_absence_of_other_balance() {
+        let accounts = fixed_accounts();
+        let tree = IndexedMerkleTree::new(&accounts).unwrap();
+        let absent = AccountWithBalance {
+            balance: U256::from(4),
+            ..accounts[2]
+        };
+
+        let proof = tree.generate_proof_of_absense(&absent).unwrap();
+        assert_eq!(proof.leaf.account, accounts[2]);
+        assert!(proof.verify_absence(tree.root(), &absent).unwrap());
+        assert!(matches!(
+            tree.generate_proof_of_absense(&accounts[2]),
+            Err(Error::AccountAlreadyExistsError)
+        ));
+    }
+
+    #[test]
+    fn test_absence_proof_outside_the_gap() {
+        let accounts = fixed_accounts();
+        let tree = IndexedMerkleTree::new(&accounts).unwrap();
+
+        // The gap after the third leaf ends at the fourth address
+        let proof = tree.generate_proof_of_inclusion(&accounts[2]).unwrap();
+        let beyond_gap = account("F977814e90dA44bFA03b6295A0616a897441aceD");
+        assert!(!proof.verify_absence(tree.root(), &beyond_gap).unwrap());
+
+        // Only the first leaf covers addresses before it
+        let before_first = account("0000000000000000000000000000000000000001");
+        assert!(!proof.verify_absence(tree.root(), &before_first).unwrap());
+        let mut moved = proof.clone();
+        moved.index = 0;
+        assert!(!moved.verify_absence(tree.root(), &before_first).unwrap());
+
+        // A leaf with a forged next address doesn't match the root
+        let mut forged = proof;
+        forged.leaf.next_address = Address::zero();
+        assert!(!forged.verify_absence(tree.root(), &beyond_gap).unwrap());
+    }
+
+    #[test]
+    fn test_duplicate_address() {
+        let accounts = fixed_accounts();
+        let duplicate = AccountWithBalance {
+            balance: U256::from(7),
+            ..accounts[0]
+        };
+        let result = IndexedMerkleTree::new(&[accounts[0], accounts[1], duplicate]);
+        let Err(Error::DuplicateAddressError(duplicates)) = result else {
+            panic!("duplicates are rejected")
+        };
+        assert_eq!(duplicates[0].positions, vec![0, 2]);
+    }
+
+    #[test]
+    fn test_internal_node_is_not_a_leaf() {
+        let accounts = random_accounts(64, 6);
+        let tree = IndexedMerkleTree::new(&accounts).unwrap();
+        let leaf_hashes: Vec<[u8; 32]> = tree.leaves().iter().map(IndexedLeaf::hash).collect();
+
+        for (index, children) in leaf_hashes.chunks(2).enumerate() {
+            // The children of a node read as a leaf with a 24-byte balance,
+            // proven at the position of the node among the 32 parents
+            let preimage = children.concat();
+            let forged = IndexedLeafProof {
+                leaf: IndexedLeaf {
+                    account: AccountWithBalance {
+                        address: Address::from_slice(&preimage[..20]),
+                        balance: U256::from_big_endian(&preimage[20..44]),
+                    },
+                    next_address: Address::from_slice(&preimage[44..]),
+                },
+                index,
+                proof: tree.leaf_proof(2 * index).proof[32..].to_vec(),
+                leaf_count: 32,
+            };
+            assert!(!forged.verify(tree.root()).unwrap());
+            for account in &accounts {
+                assert!(!forged.verify_absence(tree.root(), account).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn test_leaf_count_is_bound_to_root() {
+        let accounts = fixed_accounts();
+        let tree = IndexedMerkleTree::new(&accounts).unwrap();
+
+        // The last of five leaves is promoted twice, so its proof also fits
+        // the last of three leaves
+        let proof = tree.generate_proof_of_inclusion(&accounts[4]).unwrap();
+        let mut moved = proof.clone();
+        moved.index = 2;
+        moved.leaf_count = 3;
+        let merkle_root = ordered_proof_root::<Keccak256>(
+            moved.leaf.hash(),
+            &moved.proof,
+            moved.index,
+            moved.leaf_count,
+        );
+        assert_eq!(merkle_root.unwrap(), tree.merkle_tree.root());
+        assert!(proof.verify(tree.root()).unwrap());
+        assert!(!moved.verify(tree.root()).unwrap());
+    }
+}
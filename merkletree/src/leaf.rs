@@ -0,0 +1,394 @@
+use std::fmt::Debug;
+
+use ethers::{
+    abi::{encode, encode_packed, Token},
+    types::{Address, U256},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::account_with_balance::{AccountWithBalance, LeafEncoding, SortKey};
+use crate::hasher::{Keccak256, MerkleHasher};
+
+/// Value stored in a leaf of the tree.
+///
+/// Leaves are sorted by `Ord`, and `sort_key` gives the same order with a
+/// fixed size key that is cheaper to compare. Ordering by key with
+/// [`crate::alphabetic_merkle_tree::OrderingMode::Address`] requires `Ord` to
+/// compare keys first.
+pub trait Leaf: Copy + Ord + Debug + Send + Sync {
+    type SortKey: Ord + Copy + Send + Sync;
+    /// What identifies a leaf when every key appears once, e.g. an address
+    type Key: Ord + Copy + Debug + Send + Sync + 'static;
+
+    fn sort_key(&self) -> Self::SortKey;
+
+    fn key(&self) -> Self::Key;
+
+    /// `encode_packed` of the fields, hashed into packed leaves. It must never
+    /// be 64 bytes long, or the two children of an internal node could be
+    /// passed off as a leaf, so hashing such a leaf panics.
+    fn packed(&self) -> Vec<u8>;
+
+    /// `abi.encode` of the fields, hashed twice into standard leaves
+    fn abi_encoded(&self) -> Vec<u8>;
+
+    /// Amount added up or compared when merging leaves of the same key
+    fn balance(&self) -> Option<U256> {
+        None
+    }
+
+    /// Same leaf with another balance, for leaves that have one
+    fn with_balance(&self, _balance: U256) -> Self {
+        *self
+    }
+
+    fn leaf_hash(&self, leaf_encoding: LeafEncoding) -> [u8; 32] {
+        self.leaf_hash_with_hasher::<Keccak256>(leaf_encoding)
+    }
+
+    /// Leaf hash with the given hash function in place of Keccak-256
+    fn leaf_hash_with_hasher<H: MerkleHasher>(&self, leaf_encoding: LeafEncoding) -> [u8; 32] {
+        match leaf_encoding {
+            LeafEncoding::Packed => {
+                let packed = self.packed();
+                assert_ne!(packed.len(), 64, "packed leaves can't be 64 bytes long");
+                H::hash(&packed)
+            }
+            LeafEncoding::Standard => H::hash(&H::hash(&self.abi_encoded())),
+        }
+    }
+}
+
+impl Leaf for AccountWithBalance {
+    type SortKey = SortKey;
+    type Key = Address;
+
+    fn sort_key(&self) -> SortKey {
+        AccountWithBalance::sort_key(self)
+    }
+
+    fn key(&self) -> Address {
+        self.address
+    }
+
+    fn packed(&self) -> Vec<u8> {
+        AccountWithBalance::packed(self)
+    }
+
+    fn abi_encoded(&self) -> Vec<u8> {
+        AccountWithBalance::abi_encoded(self)
+    }
+
+    fn balance(&self) -> Option<U256> {
+        Some(self.balance)
+    }
+
+    fn with_balance(&self, balance: U256) -> Self {
+        Self { balance, ..*self }
+    }
+}
+
+/// `(address, uint256, address delegatee)` leaf of an account whose voting
+/// power is delegated. Sorted like [`AccountWithBalance`], then by delegatee.
+///
+/// Packed like `abi.encodePacked(address, uint256, address)`, with the balance
+/// on 32 bytes so the leaf is always 72 bytes long.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DelegatedAccount {
+    pub address: Address,
+    pub balance: U256,
+    pub delegatee: Address,
+}
+
+impl DelegatedAccount {
+    fn account(&self) -> AccountWithBalance {
+        AccountWithBalance {
+            address: self.address,
+            balance: self.balance,
+        }
+    }
+}
+
+impl Leaf for DelegatedAccount {
+    type SortKey = (SortKey, Address);
+    type Key = Address;
+
+    fn sort_key(&self) -> Self::SortKey {
+        (self.account().sort_key(), self.delegatee)
+    }
+
+    fn key(&self) -> Address {
+        self.address
+    }
+
+    fn packed(&self) -> Vec<u8> {
+        let mut packed = self.account().to_record().to_vec();
+        packed.extend_from_slice(self.delegatee.as_bytes());
+        packed
+    }
+
+    fn abi_encoded(&self) -> Vec<u8> {
+        encode(&[
+            Token::Address(self.address),
+            Token::Uint(self.balance),
+            Token::Address(self.delegatee),
+        ])
+    }
+
+    fn balance(&self) -> Option<U256> {
+        Some(self.balance)
+    }
+
+    fn with_balance(&self, balance: U256) -> Self {
+        Self { balance, ..*self }
+    }
+}
+
+/// `(uint256 tokenId, address owner)` leaf of an NFT. Sorted by token id as a
+/// number, then by owner, and keyed by token id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct TokenOwner {
+    pub token_id: U256,
+    pub owner: Address,
+}
+
+impl Leaf for TokenOwner {
+    type SortKey = (U256, Address);
+    type Key = U256;
+
+    fn sort_key(&self) -> Self::SortKey {
+        (self.token_id, self.owner)
+    }
+
+    fn key(&self) -> U256 {
+        self.token_id
+    }
+
+    fn packed(&self) -> Vec<u8> {
+        encode_packed(&[Token::Uint(self.token_id), Token::Address(self.owner)]).unwrap()
+    }
+
+    fn abi_encoded(&self) -> Vec<u8> {
+        encode(&[Token::Uint(self.token_id), Token::Address(self.owner)])
+    }
+}
+
+macro_rules! impl_ord_by_sort_key {
+    ($leaf:ty) => {
+        impl Ord for $leaf {
+            fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+                self.sort_key().cmp(&other.sort_key())
+            }
+        }
+
+        impl PartialOrd for $leaf {
+            fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+    };
+}
+
+impl_ord_by_sort_key!(DelegatedAccount);
+impl_ord_by_sort_key!(TokenOwner);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ethers::utils::keccak256;
+
+    use crate::alphabetic_merkle_tree::{AlphabeticMerkleTree, OrderingMode, TreeOptions};
+    use crate::merkle_tree::{get_merkle_root, Error};
+    use crate::test_utils::{fixed_accounts, random_accounts, shuffled};
+    use crate::verification::verify_inclusion_with_options;
+
+    fn delegated_accounts() -> Vec<DelegatedAccount> {
+        let delegatee = fixed_accounts()[0].address;
+        random_accounts(20, 4)
+            .iter()
+            .map(|x| DelegatedAccount {
+                address: x.address,
+                balance: x.balance,
+                delegatee,
+            })
+            .collect()
+    }
+
+    fn token_owners() -> Vec<TokenOwner> {
+        random_accounts(20, 5)
+            .iter()
+            .enumerate()
+            .map(|(index, x)| TokenOwner {
+                token_id: U256::from(1000 - 7 * index),
+                owner: x.address,
+            })
+            .collect()
+    }
+
+    fn assert_leaf_tree<L: Leaf>(leaves: &[L], absent: L) {
+        for options in [TreeOptions::default(), TreeOptions::standard()] {
+            let tree = AlphabeticMerkleTree::with_options(leaves, options).unwrap();
+            let shuffled_tree =
+                AlphabeticMerkleTree::with_options(&shuffled_leaves(leaves), options).unwrap();
+            assert_eq!(tree.root(), shuffled_tree.root());
+            assert!(tree.accounts().windows(2).all(|x| x[0] < x[1]));
+
+            for (index, leaf) in tree.accounts().iter().enumerate() {
+                let proof = tree.generate_proof_of_inclusion(leaf).unwrap();
+                assert!(verify_inclusion_with_options(
+                    options,
+                    tree.root(),
+                    leaf,
+                    &proof,
+                    index,
+                    tree.leaves_len()
+                )
+                .unwrap());
+            }
+        }
+
+        let tree = AlphabeticMerkleTree::with_options(leaves, TreeOptions::default()).unwrap();
+        let proof = tree.generate_proof_of_absense(&absent).unwrap();
+        assert!(proof.verify(tree.root(), &absent).unwrap());
+        assert!(!proof.verify(tree.root(), &tree.accounts()[0]).unwrap());
+    }
+
+    fn shuffled_leaves<L: Leaf>(leaves: &[L]) -> Vec<L> {
+        let mut leaves = leaves.to_vec();
+        let len = leaves.len();
+        leaves.reverse();
+        leaves.rotate_left(len / 3);
+        leaves
+    }
+
+    #[test]
+    fn test_account_with_balance_leaf() {
+        let accounts = fixed_accounts();
+        let account = accounts[0];
+        assert_eq!(
+            Leaf::leaf_hash(&account, LeafEncoding::Packed),
+            account.generate_hash()
+        );
+        assert_eq!(
+            Leaf::leaf_hash(&account, LeafEncoding::Standard),
+            account.generate_standard_hash()
+        );
+        assert_eq!(
+            get_merkle_root(&shuffled(&accounts, 3)).unwrap(),
+            get_merkle_root(&accounts).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_delegated_account_leaf() {
+        let leaves = delegated_accounts();
+        let leaf = leaves[0];
+        let packed = encode_packed(&[
+            Token::Address(leaf.address),
+            Token::FixedBytes(leaf.abi_encoded()[32..64].to_vec()),
+            Token::Address(leaf.delegatee),
+        ])
+        .unwrap();
+        assert_eq!(leaf.leaf_hash(LeafEncoding::Packed), keccak256(packed));
+
+        let absent = DelegatedAccount {
+            delegatee: Address::zero(),
+            ..leaves[3]
+        };
+        assert_leaf_tree(&leaves, absent);
+    }
+
+    #[test]
+    fn test_token_owner_leaf() {
+        let leaves = token_owners();
+        let absent = TokenOwner {
+            token_id: U256::from(999),
+            owner: Address::zero(),
+        };
+        assert_leaf_tree(&leaves, absent);
+
+        // Token ids sort as numbers, not as their packed bytes
+        let tree = AlphabeticMerkleTree::new(&leaves).unwrap();
+        assert_eq!(tree.accounts()[0].token_id, U256::from(1000 - 7 * 19));
+    }
+
+    #[test]
+    fn test_key_proofs() {
+        let leaves = token_owners();
+        let options = TreeOptions {
+            ordering: OrderingMode::Address,
+            ..Default::default()
+        };
+        let tree = AlphabeticMerkleTree::with_options(&leaves, options).unwrap();
+
+        let proof = tree.generate_key_proof(leaves[2].token_id).unwrap();
+        assert!(proof.verify(tree.root(), leaves[2].token_id).unwrap());
+        assert!(!proof.verify(tree.root(), leaves[3].token_id).unwrap());
+
+        let proof = tree.generate_key_proof(U256::from(999)).unwrap();
+        assert!(proof.verify(tree.root(), U256::from(999)).unwrap());
+
+        let mut duplicated = leaves.clone();
+        duplicated.push(TokenOwner {
+            owner: Address::zero(),
+            ..leaves[0]
+        });
+        let result = AlphabeticMerkleTree::with_options(&duplicated, options);
+        assert!(matches!(result, Err(Error::DuplicateAddressError(_))));
+    }
+
+    #[test]
+    fn test_packed_leaves_are_not_node_sized() {
+        // Balances of every length, from zero to 32 bytes
+        for balance in (0..=256).map(|bits| U256::MAX >> bits) {
+            let account = AccountWithBalance {
+                address: Address::repeat_byte(1),
+                balance,
+            };
+            let delegated = DelegatedAccount {
+                address: account.address,
+                balance,
+                delegatee: Address::repeat_byte(2),
+            };
+            let token = TokenOwner {
+                token_id: balance,
+                owner: account.address,
+            };
+            assert_ne!(Leaf::packed(&account).len(), 64);
+            assert_eq!(delegated.packed().len(), 72);
+            assert_ne!(token.packed().len(), 64);
+        }
+    }
+
+    /// Leaf whose packed encoding is as long as the children of a node
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    struct NodeSized([u8; 32], [u8; 32]);
+
+    impl Leaf for NodeSized {
+        type SortKey = Self;
+        type Key = Self;
+
+        fn sort_key(&self) -> Self {
+            *self
+        }
+
+        fn key(&self) -> Self {
+            *self
+        }
+
+        fn packed(&self) -> Vec<u8> {
+            [self.0, self.1].concat()
+        }
+
+        fn abi_encoded(&self) -> Vec<u8> {
+            self.packed()
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "packed leaves can't be 64 bytes long")]
+    fn test_node_sized_leaf_is_rejected() {
+        NodeSized([1; 32], [2; 32]).leaf_hash(LeafEncoding::Packed);
+    }
+}
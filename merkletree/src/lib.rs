@@ -1,2 +1,27 @@
+pub mod absence_proof;
 pub mod account_with_balance;
+pub mod address_proof;
+pub mod alphabetic_merkle_tree;
+pub mod backend;
+pub mod batch_proof;
+pub mod commutative_merkle_tree;
+pub mod duplicates;
+pub mod hasher;
+pub mod indexed_merkle_tree;
+pub mod leaf;
+pub mod merkle_sum_tree;
 pub mod merkle_tree;
+pub mod ordered_merkle_tree;
+mod parallel;
+pub mod range_proof;
+pub mod sibling_path_proof;
+pub mod snapshot_diff;
+pub mod solidity_verifier;
+pub mod sparse_merkle_tree;
+pub mod standard_merkle_tree;
+pub mod streaming_merkle_tree;
+pub mod tree_file;
+pub mod verification;
+
+#[cfg(test)]
+mod test_utils;
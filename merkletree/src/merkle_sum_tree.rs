@@ -0,0 +1,277 @@
+use std::marker::PhantomData;
+
+use ethers::types::U256;
+use serde::{Deserialize, Serialize};
+
+use crate::account_with_balance::AccountWithBalance;
+use crate::hasher::{Keccak256, MerkleHasher};
+use crate::merkle_tree::{order_accounts, Error};
+use crate::verification::expected_proof_len;
+
+/// Node of a [`MerkleSumTree`]: a hash and the sum of the balances below it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SumNode {
+    pub hash: [u8; 32],
+    pub sum: U256,
+}
+
+impl SumNode {
+    pub fn leaf<H: MerkleHasher>(account: &AccountWithBalance) -> Self {
+        Self {
+            hash: H::hash(&account.packed()),
+            sum: account.balance,
+        }
+    }
+
+    /// `hash(left.hash ++ left.sum ++ right.hash ++ right.sum)` with sums as
+    /// 32-byte big-endian integers. Fails if the sum overflows.
+    pub fn parent<H: MerkleHasher>(left: &SumNode, right: &SumNode) -> Result<Self, Error> {
+        let sum = left
+            .sum
+            .checked_add(right.sum)
+            .ok_or(Error::BalanceSumOverflowError)?;
+        let mut data = [0u8; 128];
+        data[..32].copy_from_slice(&left.hash);
+        left.sum.to_big_endian(&mut data[32..64]);
+        data[64..96].copy_from_slice(&right.hash);
+        right.sum.to_big_endian(&mut data[96..]);
+        Ok(Self {
+            hash: H::hash(&data),
+            sum,
+        })
+    }
+}
+
+/// Inclusion proof of a [`MerkleSumTree`]. The siblings carry their sums, so
+/// verifying the proof also gives the total supply at the root.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MerkleSumProof {
+    pub account: AccountWithBalance,
+    pub index: usize,
+    pub siblings: Vec<SumNode>,
+    pub leaf_count: usize,
+}
+
+impl MerkleSumProof {
+    /// Root obtained from the account and its siblings
+    pub fn compute_root(&self) -> Result<SumNode, Error> {
+        self.compute_root_with_hasher::<Keccak256>()
+    }
+
+    pub fn compute_root_with_hasher<H: MerkleHasher>(&self) -> Result<SumNode, Error> {
+        if self.leaf_count == 0 {
+            return Err(Error::EmptyAccountsListError);
+        }
+        if self.index >= self.leaf_count {
+            return Err(Error::LeafIndexOutOfRangeError);
+        }
+        if self.siblings.len() != expected_proof_len(self.index, self.leaf_count) {
+            return Err(Error::MerkleProofParsingError);
+        }
+
+        let mut node = SumNode::leaf::<H>(&self.account);
+        let mut index = self.index;
+        let mut layer_len = self.leaf_count;
+        let mut siblings = self.siblings.iter();
+        while layer_len > 1 {
+            // The last node of an uneven layer is promoted without a sibling
+            if index ^ 1 < layer_len {
+                let sibling = siblings.next().expect("proof length was checked");
+                node = if index.is_multiple_of(2) {
+                    SumNode::parent::<H>(&node, sibling)?
+                } else {
+                    SumNode::parent::<H>(sibling, &node)?
+                };
+            }
+            index /= 2;
+            layer_len = layer_len.div_ceil(2);
+        }
+        Ok(node)
+    }
+
+    /// Checks the account is in the tree with the given root and total supply
+    pub fn verify(&self, root: SumNode) -> Result<bool, Error> {
+        self.verify_with_hasher::<Keccak256>(root)
+    }
+
+    pub fn verify_with_hasher<H: MerkleHasher>(&self, root: SumNode) -> Result<bool, Error> {
+        Ok(self.compute_root_with_hasher::<H>()? == root)
+    }
+}
+
+/// Merkle tree over alphabetically ordered accounts where every node also
+/// commits to the sum of the balances below it, so the root commits to the
+/// total supply
+pub struct MerkleSumTree<H = Keccak256> {
+    accounts: Vec<AccountWithBalance>,
+    /// Layers from the leaves up to the root
+    layers: Vec<Vec<SumNode>>,
+    hasher: PhantomData<H>,
+}
+
+impl MerkleSumTree {
+    pub fn new(accounts: &[AccountWithBalance]) -> Result<Self, Error> {
+        Self::with_hasher(accounts)
+    }
+}
+
+impl<H: MerkleHasher> MerkleSumTree<H> {
+    /// Fails if the total supply doesn't fit in a `U256`
+    pub fn with_hasher(accounts: &[AccountWithBalance]) -> Result<Self, Error> {
+        if accounts.is_empty() {
+            return Err(Error::EmptyAccountsListError);
+        }
+        let accounts = order_accounts(accounts);
+
+        let mut layers = vec![accounts.iter().map(SumNode::leaf::<H>).collect::<Vec<_>>()];
+        while let Some(layer) = layers.last().filter(|layer| layer.len() > 1) {
+            let parents = layer
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => SumNode::parent::<H>(left, right),
+                    [node] => Ok(*node),
+                    _ => unreachable!("chunks have one or two nodes"),
+                })
+                .collect::<Result<Vec<SumNode>, Error>>()?;
+            layers.push(parents);
+        }
+
+        Ok(Self {
+            accounts,
+            layers,
+            hasher: PhantomData,
+        })
+    }
+
+    pub fn root(&self) -> SumNode {
+        self.layers[self.layers.len() - 1][0]
+    }
+
+    pub fn total_supply(&self) -> U256 {
+        self.root().sum
+    }
+
+    pub fn leaves_len(&self) -> usize {
+        self.accounts.len()
+    }
+
+    /// Accounts in leaf order
+    pub fn accounts(&self) -> &[AccountWithBalance] {
+        &self.accounts
+    }
+
+    pub fn generate_proof(&self, account: &AccountWithBalance) -> Result<MerkleSumProof, Error> {
+        let index = self
+            .accounts
+            .binary_search(account)
+            .map_err(|_| Error::AccountNotFoundError)?;
+
+        let mut siblings = vec![];
+        let mut position = index;
+        for layer in &self.layers[..self.layers.len() - 1] {
+            if let Some(sibling) = layer.get(position ^ 1) {
+                siblings.push(*sibling);
+            }
+            position /= 2;
+        }
+
+        Ok(MerkleSumProof {
+            account: *account,
+            index,
+            siblings,
+            leaf_count: self.leaves_len(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_utils::{fixed_accounts, random_accounts};
+
+    #[test]
+    fn test_root_commits_to_total_supply() {
+        let accounts = fixed_accounts();
+        let tree = MerkleSumTree::new(&accounts).unwrap();
+        assert_eq!(tree.total_supply(), U256::from(116));
+
+        let leaves: Vec<SumNode> = accounts.iter().map(SumNode::leaf::<Keccak256>).collect();
+        let parent = |a: &SumNode, b: &SumNode| SumNode::parent::<Keccak256>(a, b).unwrap();
+        let left = parent(
+            &parent(&leaves[0], &leaves[1]),
+            &parent(&leaves[2], &leaves[3]),
+        );
+        assert_eq!(tree.root(), parent(&left, &leaves[4]));
+    }
+
+    #[test]
+    fn test_proof_gives_balance_and_total_supply() {
+        let accounts = random_accounts(37, 9);
+        let tree = MerkleSumTree::new(&accounts).unwrap();
+        let total = accounts
+            .iter()
+            .fold(U256::zero(), |total, x| total + x.balance);
+
+        for account in accounts.iter() {
+            let proof = tree.generate_proof(account).unwrap();
+            assert_eq!(proof.compute_root().unwrap().sum, total);
+            assert!(proof.verify(tree.root()).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_tampered_proof() {
+        let accounts = fixed_accounts();
+        let tree = MerkleSumTree::new(&accounts).unwrap();
+        let proof = tree.generate_proof(&accounts[1]).unwrap();
+
+        let mut inflated = proof.clone();
+        inflated.account.balance += U256::one();
+        assert!(!inflated.verify(tree.root()).unwrap());
+
+        // Moving balance from a sibling keeps the total but not the hash
+        let mut moved = proof.clone();
+        moved.account.balance += U256::one();
+        moved.siblings[1].sum -= U256::one();
+        assert_eq!(moved.compute_root().unwrap().sum, tree.total_supply());
+        assert!(!moved.verify(tree.root()).unwrap());
+
+        let mut wrong_total = tree.root();
+        wrong_total.sum += U256::one();
+        assert!(!proof.verify(wrong_total).unwrap());
+
+        let mut truncated = proof;
+        truncated.siblings.pop();
+        assert!(matches!(
+            truncated.verify(tree.root()),
+            Err(Error::MerkleProofParsingError)
+        ));
+    }
+
+    #[test]
+    fn test_balance_overflow() {
+        let accounts = [
+            AccountWithBalance {
+                balance: U256::MAX,
+                ..fixed_accounts()[0]
+            },
+            fixed_accounts()[1],
+        ];
+        assert!(matches!(
+            MerkleSumTree::new(&accounts),
+            Err(Error::BalanceSumOverflowError)
+        ));
+
+        let proof = MerkleSumProof {
+            account: accounts[0],
+            index: 0,
+            siblings: vec![SumNode::leaf::<Keccak256>(&accounts[1])],
+            leaf_count: 2,
+        };
+        assert!(matches!(
+            proof.compute_root(),
+            Err(Error::BalanceSumOverflowError)
+        ));
+    }
+}
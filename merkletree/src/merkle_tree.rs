@@ -1,19 +1,13 @@
-use ethers::utils::keccak256;
-use rs_merkle::{Hasher, MerkleTree};
+use ethers::types::Address;
 use thiserror::Error;
 
-use crate::account_with_balance::AccountWithBalance;
-
-#[derive(Clone)]
-struct Keccak256Algorithm {}
-
-impl Hasher for Keccak256Algorithm {
-    type Hash = [u8; 32];
-
-    fn hash(data: &[u8]) -> [u8; 32] {
-        keccak256(data)
-    }
-}
+use crate::absence_proof::AbsenceProof;
+use crate::address_proof::AddressProof;
+use crate::alphabetic_merkle_tree::{AlphabeticMerkleTree, OrderingMode, TreeOptions};
+use crate::batch_proof::BatchProof;
+use crate::duplicates::{Duplicate, DuplicateKey};
+use crate::leaf::Leaf;
+use crate::parallel;
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -27,111 +21,102 @@ pub enum Error {
     MerkleTreeRootError,
     #[error("Error parsing proof")]
     MerkleProofParsingError,
+    #[error("Leaf index out of range")]
+    LeafIndexOutOfRangeError,
+    #[error("Tree is not ordered by address")]
+    AddressOrderingRequiredError,
+    #[error("Tree leaves are sorted by hash, not by account")]
+    AccountOrderingRequiredError,
+    #[error("Tree does not use commutative hashing")]
+    CommutativeHashingRequiredError,
+    #[error("Absence proofs require ordered hashing")]
+    OrderedHashingRequiredError,
+    #[error("Unknown tree backend {0}")]
+    UnknownBackendError(String),
+    #[error("Tree is not compatible with StandardMerkleTree")]
+    StandardTreeRequiredError,
+    #[error("Invalid StandardMerkleTree dump")]
+    InvalidTreeDumpError,
+    #[error("Sum of balances overflows")]
+    BalanceSumOverflowError,
+    #[error("Invalid account on line {0}: {1}")]
+    InvalidAccountRowError(usize, #[source] Box<Error>),
+    #[error("Invalid or corrupted tree file")]
+    InvalidTreeFileError,
+    #[error("Tree file was written with hasher {0}, not {1}")]
+    TreeFileHasherMismatchError(u8, u8),
+    #[error("I/O error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("Invalid address {0}")]
+    InvalidAddressError(String),
+    #[error("Invalid balance {0}")]
+    InvalidBalanceError(String),
+    #[error("Balance {0} is too large to parse as a uint256")]
+    BalanceOutOfRangeError(String),
+    #[error(
+        "Addresses appear more than once: {}",
+        .0.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+    )]
+    DuplicateAddressError(Vec<Duplicate<Box<dyn DuplicateKey>>>),
+    #[error("Duplicate policy not supported")]
+    UnsupportedDuplicatePolicyError,
+    #[error("{0} addresses but {1} balances")]
+    AccountsLengthMismatchError(usize, usize),
 }
 
-fn order_accounts(accounts: &[AccountWithBalance]) -> Vec<AccountWithBalance> {
-    let mut accounts = accounts.to_owned();
-    accounts.sort();
-    accounts
+pub(crate) fn order_accounts<L: Leaf>(accounts: &[L]) -> Vec<L> {
+    let mut keyed_accounts = parallel::map(accounts, |x| (x.sort_key(), *x));
+    parallel::sort_unstable_by(&mut keyed_accounts, |a, b| a.0.cmp(&b.0));
+    keyed_accounts.into_iter().map(|x| x.1).collect()
 }
 
-fn create_merkle_tree(accounts: &[AccountWithBalance]) -> MerkleTree<Keccak256Algorithm> {
-    let ordered_accounts = order_accounts(accounts);
-    let leaves: Vec<[u8; 32]> = ordered_accounts.iter().map(|x| x.generate_hash()).collect();
-    MerkleTree::<Keccak256Algorithm>::from_leaves(&leaves)
+pub fn get_merkle_root<L: Leaf>(accounts: &[L]) -> Result<[u8; 32], Error> {
+    let merkle_tree = AlphabeticMerkleTree::new(accounts)?;
+    Ok(merkle_tree.root())
 }
 
-pub fn get_merkle_root(accounts: &[AccountWithBalance]) -> Result<[u8; 32], Error> {
-    if accounts.is_empty() {
-        return Err(Error::EmptyAccountsListError);
-    }
-    let ordered_accounts = order_accounts(accounts);
-    let merkle_tree = create_merkle_tree(&ordered_accounts);
-    merkle_tree.root().ok_or(Error::MerkleTreeRootError)
+pub fn generate_proof_of_inclusion<L: Leaf>(accounts: &[L], account: L) -> Result<Vec<u8>, Error> {
+    let merkle_tree = AlphabeticMerkleTree::new(accounts)?;
+    merkle_tree.generate_proof_of_inclusion(&account)
 }
 
-pub fn generate_proof_of_inclusion(
-    accounts: &Vec<AccountWithBalance>,
-    account: AccountWithBalance,
-) -> Result<Vec<u8>, Error> {
-    if accounts.is_empty() {
-        return Err(Error::EmptyAccountsListError);
-    }
-    let ordered_accounts = order_accounts(accounts);
-    let merkle_tree = create_merkle_tree(&ordered_accounts);
-
-    let index = accounts.iter().position(|x| x.packed() == account.packed());
-
-    match index {
-        None => Err(Error::AccountNotFoundError),
-        Some(index) => Ok(merkle_tree.proof(&[index]).to_bytes()),
-    }
+pub fn generate_proof_of_absense<L: Leaf>(
+    accounts: &[L],
+    account: L,
+) -> Result<AbsenceProof<L>, Error> {
+    let merkle_tree = AlphabeticMerkleTree::new(accounts)?;
+    merkle_tree.generate_proof_of_absense(&account)
 }
 
-fn find_adjacents(
-    accounts: &Vec<AccountWithBalance>,
-    account: &AccountWithBalance,
-) -> (Option<usize>, Option<usize>) {
-    let previous_index = accounts.iter().position(|x| x.gt(account));
-    match previous_index {
-        Some(0) => {
-            if account.lt(&accounts[0]) {
-                (None, Some(0))
-            } else {
-                (Some(0), Some(1))
-            }
-        }
-        Some(_) => (
-            Some(previous_index.unwrap() - 1),
-            Some(previous_index.unwrap()),
-        ),
-        None => (Some(accounts.len() - 1), None),
-    }
+pub fn generate_batch_proof<L: Leaf>(
+    accounts: &[L],
+    accounts_to_prove: &[L],
+) -> Result<BatchProof, Error> {
+    let merkle_tree = AlphabeticMerkleTree::new(accounts)?;
+    merkle_tree.generate_batch_proof(accounts_to_prove)
 }
 
-pub fn generate_proof_of_absense(
-    accounts: &Vec<AccountWithBalance>,
-    account: AccountWithBalance,
-) -> Result<(Option<Vec<u8>>, Option<Vec<u8>>), Error> {
-    if accounts.is_empty() {
-        return Err(Error::EmptyAccountsListError);
-    }
-    let ordered_accounts = order_accounts(accounts);
-    let merkle_tree = create_merkle_tree(&ordered_accounts);
-    let exist = accounts.iter().position(|x| x.eq(&account));
-    if exist.is_some() {
-        return Err(Error::AccountAlreadyExistsError);
+/// Proves the balance of an address, or that no leaf has that address.
+/// Fails if any address appears more than once.
+pub fn generate_address_proof<L: Leaf<Key = Address>>(
+    accounts: &[L],
+    address: Address,
+) -> Result<AddressProof<L>, Error> {
+    let options = TreeOptions {
+        ordering: OrderingMode::Address,
+        ..Default::default()
     };
-
-    let (previous_index_opt, next_index_opt) = find_adjacents(accounts, &account);
-
-    let left_proof: Option<Vec<u8>> =
-        previous_index_opt.map(|previous_index| merkle_tree.proof(&[previous_index]).to_bytes());
-
-    let right_proof: Option<Vec<u8>> =
-        next_index_opt.map(|next_index| merkle_tree.proof(&[next_index]).to_bytes());
-
-    Ok((left_proof, right_proof))
+    let merkle_tree = AlphabeticMerkleTree::with_options(accounts, options)?;
+    merkle_tree.generate_address_proof(address)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    use rs_merkle::MerkleProof;
-
-    fn fixed_accounts() -> Vec<AccountWithBalance> {
-        let mut accounts = [
-            AccountWithBalance::new("F977814e90dA44bFA03b6295A0616a897441aceC", "1"),
-            AccountWithBalance::new("47ac0Fb4F2D84898e4D9E7b4DaB3C24507a6D503", "2"),
-            AccountWithBalance::new("A7A93fd0a276fc1C0197a5B5623eD117786eeD06", "3"),
-            AccountWithBalance::new("cEe284F754E854890e311e3280b767F80797180d", "10"),
-            AccountWithBalance::new("5754284f345afc66a98fbB0a0Afe71e0F007B949", "100"),
-        ]
-        .to_vec();
-        accounts.sort();
-        accounts
-    }
+    use crate::account_with_balance::AccountWithBalance;
+    use crate::test_utils::{fixed_accounts, random_accounts, shuffled};
+    use crate::verification::verify_inclusion;
 
     #[test]
     fn test_account_sorting() {
@@ -162,10 +147,8 @@ mod tests {
     fn test_create_merkle_tree() {
         let accounts = fixed_accounts();
 
-        let merkle_tree = create_merkle_tree(&accounts);
-        let root = merkle_tree
-            .root()
-            .expect(Error::MerkleTreeRootError.to_string().as_str());
+        let merkle_tree = AlphabeticMerkleTree::new(&accounts).unwrap();
+        let root = merkle_tree.root();
 
         assert_eq!(merkle_tree.depth(), 3);
         assert_eq!(merkle_tree.leaves_len(), 5);
@@ -184,25 +167,20 @@ mod tests {
     fn test_generate_proof_of_inclusion() {
         let accounts = fixed_accounts();
         let account = accounts[1];
-        let indices_to_prove = vec![1];
+        let index_to_prove = 1;
 
         let proof_bytes = generate_proof_of_inclusion(&accounts, account).unwrap();
 
-        let merkle_tree = create_merkle_tree(&accounts);
-        let merkle_root = merkle_tree
-            .root()
-            .expect(Error::MerkleTreeRootError.to_string().as_str());
-        let leave_to_prove = account.generate_hash();
+        let merkle_root = get_merkle_root(&accounts).unwrap();
 
-        let proof = MerkleProof::<Keccak256Algorithm>::try_from(proof_bytes)
-            .expect(Error::MerkleProofParsingError.to_string().as_str());
-
-        assert!(proof.verify(
+        assert!(verify_inclusion(
             merkle_root,
-            &indices_to_prove,
-            &[leave_to_prove],
+            &account,
+            &proof_bytes,
+            index_to_prove,
             accounts.len()
-        ));
+        )
+        .unwrap());
     }
 
     #[test]
@@ -217,37 +195,55 @@ mod tests {
     fn test_generate_proof_of_absense() {
         let accounts = fixed_accounts();
         let account = AccountWithBalance::new("FF54284f345afc66a98fbB0a0Afe71e0F007B948", "1");
-        let proofs = generate_proof_of_absense(&accounts, account).unwrap();
-
-        let merkle_tree = create_merkle_tree(&accounts);
-        let merkle_root = merkle_tree.root().expect("Couldn't get the merkle root");
-
-        let adjacents_indexes = find_adjacents(&accounts, &account);
-
-        if proofs.0.is_some() {
-            let leave_index = adjacents_indexes.0.unwrap();
-            let leave_to_prove = accounts[leave_index].generate_hash();
-            let proof_l = MerkleProof::<Keccak256Algorithm>::try_from(proofs.0.unwrap())
-                .expect(Error::MerkleProofParsingError.to_string().as_str());
-            assert!(proof_l.verify(
-                merkle_root,
-                &[leave_index],
-                &[leave_to_prove],
-                accounts.len()
-            ));
+        let proof = generate_proof_of_absense(&accounts, account).unwrap();
+
+        let merkle_tree = AlphabeticMerkleTree::new(&accounts).unwrap();
+        let merkle_root = merkle_tree.root();
+
+        let adjacents_indexes = merkle_tree.find_adjacents(&account).unwrap();
+        assert_eq!(proof.left.as_ref().map(|x| x.index), adjacents_indexes.0);
+        assert_eq!(proof.right.as_ref().map(|x| x.index), adjacents_indexes.1);
+
+        assert!(proof.verify(merkle_root, &account).unwrap());
+    }
+
+    #[test]
+    fn test_proofs_from_shuffled_accounts() {
+        let sorted_accounts = fixed_accounts();
+        let merkle_root = get_merkle_root(&sorted_accounts).unwrap();
+
+        for seed in 0..10 {
+            let accounts = shuffled(&sorted_accounts, seed);
+            assert_eq!(get_merkle_root(&accounts).unwrap(), merkle_root);
+
+            for (index, account) in sorted_accounts.iter().enumerate() {
+                let proof_bytes = generate_proof_of_inclusion(&accounts, *account).unwrap();
+                assert!(verify_inclusion(
+                    merkle_root,
+                    account,
+                    &proof_bytes,
+                    index,
+                    accounts.len()
+                )
+                .unwrap());
+            }
         }
+    }
 
-        if proofs.1.is_some() {
-            let leave_index = adjacents_indexes.1.unwrap();
-            let leave_to_prove = accounts[leave_index].generate_hash();
-            let proof_r = MerkleProof::<Keccak256Algorithm>::try_from(proofs.1.unwrap())
-                .expect(Error::MerkleProofParsingError.to_string().as_str());
-            assert!(proof_r.verify(
-                merkle_root,
-                &[leave_index],
-                &[leave_to_prove],
-                accounts.len()
-            ));
+    #[test]
+    fn test_absense_proofs_from_shuffled_accounts() {
+        let sorted_accounts = random_accounts(64, 0);
+        let absent_accounts = random_accounts(16, 1);
+        let merkle_root = get_merkle_root(&sorted_accounts).unwrap();
+
+        for seed in 0..4 {
+            let accounts = shuffled(&sorted_accounts, seed);
+            assert_eq!(get_merkle_root(&accounts).unwrap(), merkle_root);
+
+            for account in absent_accounts.iter() {
+                let proof = generate_proof_of_absense(&accounts, *account).unwrap();
+                assert!(proof.verify(merkle_root, account).unwrap());
+            }
         }
     }
 }
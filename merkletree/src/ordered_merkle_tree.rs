@@ -0,0 +1,253 @@
+use std::marker::PhantomData;
+
+use crate::hasher::{Keccak256, MerkleHasher};
+use crate::merkle_tree::Error;
+use crate::parallel;
+
+/// Merkle tree hashing `left ++ right` with the last node of an uneven layer
+/// promoted as is. Nodes and proofs are the same as `rs_merkle`, but every
+/// layer is kept and hashed in parallel with the `parallel` feature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderedMerkleTree<H = Keccak256> {
+    /// Layers from the leaves up to the root
+    layers: Vec<Vec<[u8; 32]>>,
+    hasher: PhantomData<H>,
+}
+
+impl OrderedMerkleTree {
+    pub fn from_leaves(leaves: &[[u8; 32]]) -> Result<Self, Error> {
+        Self::from_leaves_with_hasher(leaves)
+    }
+}
+
+impl<H: MerkleHasher> OrderedMerkleTree<H> {
+    pub fn from_leaves_with_hasher(leaves: &[[u8; 32]]) -> Result<Self, Error> {
+        if leaves.is_empty() {
+            return Err(Error::EmptyAccountsListError);
+        }
+        let mut merkle_tree = Self {
+            layers: vec![leaves.to_vec()],
+            hasher: PhantomData,
+        };
+        merkle_tree.rehash_from(0);
+        Ok(merkle_tree)
+    }
+
+    fn parent(children: &[[u8; 32]]) -> [u8; 32] {
+        match children {
+            [left, right] => H::hash_pair(left, right),
+            [node] => *node,
+            _ => unreachable!("nodes have one or two children"),
+        }
+    }
+
+    /// Recomputes every node to the right of the leaf at `position`, which is
+    /// what shifting leaves by an insertion or removal changes
+    fn rehash_from(&mut self, position: usize) {
+        let mut position = position;
+        let mut level = 0;
+        while self.layers[level].len() > 1 {
+            position /= 2;
+            let parents =
+                parallel::map_chunks(&self.layers[level][2 * position..], 2, Self::parent);
+            if level + 1 == self.layers.len() {
+                self.layers.push(vec![]);
+            }
+            let parent_layer = &mut self.layers[level + 1];
+            parent_layer.truncate(position);
+            parent_layer.extend(parents);
+            level += 1;
+        }
+        self.layers.truncate(level + 1);
+    }
+
+    /// Replaces a leaf, recomputing only its path to the root
+    pub fn update_leaf(&mut self, index: usize, leaf: [u8; 32]) -> Result<(), Error> {
+        if index >= self.leaves_len() {
+            return Err(Error::LeafIndexOutOfRangeError);
+        }
+        self.layers[0][index] = leaf;
+        let mut index = index;
+        for level in 0..self.depth() {
+            index /= 2;
+            let children = &self.layers[level];
+            let node = Self::parent(&children[2 * index..(2 * index + 2).min(children.len())]);
+            self.layers[level + 1][index] = node;
+        }
+        Ok(())
+    }
+
+    pub fn insert_leaf(&mut self, index: usize, leaf: [u8; 32]) -> Result<(), Error> {
+        if index > self.leaves_len() {
+            return Err(Error::LeafIndexOutOfRangeError);
+        }
+        self.layers[0].insert(index, leaf);
+        self.rehash_from(index);
+        Ok(())
+    }
+
+    /// Removes a leaf. The last leaf of the tree can't be removed.
+    pub fn remove_leaf(&mut self, index: usize) -> Result<[u8; 32], Error> {
+        if index >= self.leaves_len() {
+            return Err(Error::LeafIndexOutOfRangeError);
+        }
+        if self.leaves_len() == 1 {
+            return Err(Error::EmptyAccountsListError);
+        }
+        let leaf = self.layers[0].remove(index);
+        self.rehash_from(index);
+        Ok(leaf)
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.layers[self.layers.len() - 1][0]
+    }
+
+    pub fn leaves_len(&self) -> usize {
+        self.layers[0].len()
+    }
+
+    /// Number of layers above the leaves, the length of the longest proof
+    pub fn depth(&self) -> usize {
+        self.layers.len() - 1
+    }
+
+    pub fn leaves(&self) -> &[[u8; 32]] {
+        &self.layers[0]
+    }
+
+    /// Layers from the leaves up to the root
+    pub fn layers(&self) -> &[Vec<[u8; 32]>] {
+        &self.layers
+    }
+
+    /// Sibling hashes proving sorted, deduplicated leaf indices, bottom layer
+    /// first and in ascending order within a layer, as `rs_merkle` expects
+    pub fn proof(&self, leaf_indices: &[usize]) -> Result<Vec<[u8; 32]>, Error> {
+        if leaf_indices.iter().any(|index| *index >= self.leaves_len()) {
+            return Err(Error::LeafIndexOutOfRangeError);
+        }
+        let mut indices = leaf_indices.to_vec();
+        let mut proof = vec![];
+        for layer in &self.layers[..self.depth()] {
+            for (position, index) in indices.iter().enumerate() {
+                let sibling = index ^ 1;
+                let proven = if sibling < *index {
+                    position > 0 && indices[position - 1] == sibling
+                } else {
+                    indices.get(position + 1) == Some(&sibling)
+                };
+                if sibling < layer.len() && !proven {
+                    proof.push(layer[sibling]);
+                }
+            }
+            indices = indices.iter().map(|index| index / 2).collect();
+            indices.dedup();
+        }
+        Ok(proof)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rs_merkle::MerkleTree;
+
+    use crate::hasher::RsMerkleHasher;
+    use crate::test_utils::random_accounts;
+    use crate::verification::expected_proof_len;
+
+    #[test]
+    fn test_matches_rs_merkle() {
+        for count in [1, 2, 5, 8, 13, 100] {
+            let leaves: Vec<[u8; 32]> = random_accounts(count, count as u64)
+                .iter()
+                .map(|x| x.generate_hash())
+                .collect();
+            let tree = OrderedMerkleTree::from_leaves(&leaves).unwrap();
+            let rs_tree = MerkleTree::<RsMerkleHasher<Keccak256>>::from_leaves(&leaves);
+
+            assert_eq!(Some(tree.root()), rs_tree.root());
+            assert_eq!(
+                tree.depth(),
+                (0..count)
+                    .map(|index| expected_proof_len(index, count))
+                    .max()
+                    .unwrap()
+            );
+            for index in 0..count {
+                assert_eq!(
+                    tree.proof(&[index]).unwrap(),
+                    rs_tree.proof(&[index]).proof_hashes()
+                );
+            }
+            let indices: Vec<usize> = (0..count).step_by(3).collect();
+            assert_eq!(
+                tree.proof(&indices).unwrap(),
+                rs_tree.proof(&indices).proof_hashes()
+            );
+        }
+    }
+
+    #[test]
+    fn test_mutations_match_rebuild() {
+        let mut leaves: Vec<[u8; 32]> = random_accounts(13, 1)
+            .iter()
+            .map(|x| x.generate_hash())
+            .collect();
+        let mut tree = OrderedMerkleTree::from_leaves(&leaves).unwrap();
+
+        let operations: [(&str, usize); 9] = [
+            ("update", 0),
+            ("update", 12),
+            ("insert", 13),
+            ("insert", 0),
+            ("insert", 7),
+            ("remove", 15),
+            ("remove", 0),
+            ("remove", 5),
+            ("update", 6),
+        ];
+        for (step, (operation, index)) in operations.into_iter().enumerate() {
+            let leaf = [step as u8; 32];
+            match operation {
+                "update" => {
+                    tree.update_leaf(index, leaf).unwrap();
+                    leaves[index] = leaf;
+                }
+                "insert" => {
+                    tree.insert_leaf(index, leaf).unwrap();
+                    leaves.insert(index, leaf);
+                }
+                _ => {
+                    assert_eq!(tree.remove_leaf(index).unwrap(), leaves.remove(index));
+                }
+            }
+            assert_eq!(tree, OrderedMerkleTree::from_leaves(&leaves).unwrap());
+        }
+
+        // Shrinking to a single leaf drops the layers above it
+        let mut tree = OrderedMerkleTree::from_leaves(&leaves[..2]).unwrap();
+        tree.remove_leaf(1).unwrap();
+        assert_eq!(tree.depth(), 0);
+        assert_eq!(tree.root(), leaves[0]);
+        assert!(matches!(
+            tree.remove_leaf(0),
+            Err(Error::EmptyAccountsListError)
+        ));
+    }
+
+    #[test]
+    fn test_proof_out_of_range() {
+        let tree = OrderedMerkleTree::from_leaves(&[[1u8; 32], [2u8; 32]]).unwrap();
+        assert!(matches!(
+            tree.proof(&[2]),
+            Err(Error::LeafIndexOutOfRangeError)
+        ));
+        assert!(matches!(
+            OrderedMerkleTree::from_leaves(&[]),
+            Err(Error::EmptyAccountsListError)
+        ));
+    }
+}
@@ -0,0 +1,65 @@
+//! Helpers running on the rayon thread pool with the `parallel` feature and
+//! sequentially otherwise
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// Below this many items the work is not worth splitting across threads
+#[cfg(feature = "parallel")]
+const MIN_ITEMS_PER_TASK: usize = 1024;
+
+pub(crate) fn map<T, U>(items: &[T], f: impl Fn(&T) -> U + Sync + Send) -> Vec<U>
+where
+    T: Sync,
+    U: Send,
+{
+    #[cfg(feature = "parallel")]
+    {
+        items
+            .par_iter()
+            .with_min_len(MIN_ITEMS_PER_TASK)
+            .map(f)
+            .collect()
+    }
+    #[cfg(not(feature = "parallel"))]
+    {
+        items.iter().map(f).collect()
+    }
+}
+
+pub(crate) fn map_chunks<T, U>(
+    items: &[T],
+    chunk_size: usize,
+    f: impl Fn(&[T]) -> U + Sync + Send,
+) -> Vec<U>
+where
+    T: Sync,
+    U: Send,
+{
+    #[cfg(feature = "parallel")]
+    {
+        items
+            .par_chunks(chunk_size)
+            .with_min_len(MIN_ITEMS_PER_TASK)
+            .map(f)
+            .collect()
+    }
+    #[cfg(not(feature = "parallel"))]
+    {
+        items.chunks(chunk_size).map(f).collect()
+    }
+}
+
+pub(crate) fn sort_unstable_by<T: Send>(
+    items: &mut [T],
+    compare: impl Fn(&T, &T) -> std::cmp::Ordering + Sync,
+) {
+    #[cfg(feature = "parallel")]
+    {
+        items.par_sort_unstable_by(compare)
+    }
+    #[cfg(not(feature = "parallel"))]
+    {
+        items.sort_unstable_by(compare)
+    }
+}
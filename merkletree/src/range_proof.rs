@@ -0,0 +1,352 @@
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+
+use crate::account_with_balance::{AccountWithBalance, LeafEncoding};
+use crate::alphabetic_merkle_tree::HashMode;
+use crate::batch_proof::BatchProof;
+use crate::hasher::{Keccak256, MerkleHasher};
+use crate::leaf::Leaf;
+use crate::merkle_tree::Error;
+
+/// Leading hex digits of an address, e.g. `0x47a`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AddressPrefix {
+    nibbles: Vec<u8>,
+}
+
+impl AddressPrefix {
+    pub fn len(&self) -> usize {
+        self.nibbles.len()
+    }
+
+    /// The empty prefix, matched by every address
+    pub fn is_empty(&self) -> bool {
+        self.nibbles.is_empty()
+    }
+
+    /// How the address sorts relative to the addresses with the prefix
+    pub fn compare(&self, address: &Address) -> Ordering {
+        let bytes = address.as_bytes();
+        self.nibbles
+            .iter()
+            .enumerate()
+            .map(|(position, nibble)| {
+                let byte = bytes[position / 2];
+                let address_nibble = if position.is_multiple_of(2) {
+                    byte >> 4
+                } else {
+                    byte & 0xf
+                };
+                address_nibble.cmp(nibble)
+            })
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(Ordering::Equal)
+    }
+
+    pub fn matches(&self, address: &Address) -> bool {
+        self.compare(address).is_eq()
+    }
+}
+
+/// Parses up to 40 hex digits, with or without the `0x` prefix
+impl FromStr for AddressPrefix {
+    type Err = Error;
+
+    fn from_str(prefix: &str) -> Result<Self, Error> {
+        let digits = prefix
+            .strip_prefix("0x")
+            .or_else(|| prefix.strip_prefix("0X"))
+            .unwrap_or(prefix);
+        if digits.len() > 40 {
+            return Err(Error::InvalidAddressError(prefix.to_string()));
+        }
+        let nibbles = digits
+            .chars()
+            .map(|x| x.to_digit(16).map(|nibble| nibble as u8))
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(|| Error::InvalidAddressError(prefix.to_string()))?;
+        Ok(Self { nibbles })
+    }
+}
+
+/// Proof of the consecutive leaves `[start, start + accounts.len())`.
+///
+/// The sibling hashes are an `rs_merkle` multiproof, shared by all the leaves
+/// of the range. Prefix proofs also prove the leaves right before and after
+/// the range, so no account with the prefix can be left out.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RangeProof<L = AccountWithBalance> {
+    pub start: usize,
+    pub accounts: Vec<L>,
+    pub before: Option<L>,
+    pub after: Option<L>,
+    pub proof: Vec<[u8; 32]>,
+    pub leaf_count: usize,
+    #[serde(default)]
+    pub leaf_encoding: LeafEncoding,
+}
+
+impl<L: Leaf> RangeProof<L> {
+    /// Index after the last leaf of the range, `None` if it overflows
+    pub fn end(&self) -> Option<usize> {
+        self.start.checked_add(self.accounts.len())
+    }
+
+    /// Proven leaves in order, with the neighbours of the range
+    fn leaves(&self) -> impl Iterator<Item = &L> {
+        self.before
+            .iter()
+            .chain(self.accounts.iter())
+            .chain(self.after.iter())
+    }
+
+    /// Checks that the accounts are the leaves `[start, end)` of the tree
+    pub fn verify(&self, root: [u8; 32]) -> Result<bool, Error> {
+        self.verify_with_hasher::<Keccak256>(root)
+    }
+
+    pub fn verify_with_hasher<H: MerkleHasher>(&self, root: [u8; 32]) -> Result<bool, Error> {
+        if self.end().is_none_or(|end| end > self.leaf_count) {
+            return Err(Error::LeafIndexOutOfRangeError);
+        }
+        if self.leaf_count == 0 {
+            return Err(Error::EmptyAccountsListError);
+        }
+        if self.before.is_some() && self.start == 0 {
+            return Err(Error::LeafIndexOutOfRangeError);
+        }
+        let first_index = self.start - self.before.is_some() as usize;
+        let last_index = first_index
+            .checked_add(self.leaves().count())
+            .ok_or(Error::LeafIndexOutOfRangeError)?;
+        let indices: Vec<usize> = (first_index..last_index).collect();
+        if indices.is_empty() {
+            return Err(Error::EmptyAccountsListError);
+        }
+        // Leaves are strictly sorted, so out of order accounts can't be in the tree
+        let leaves: Vec<&L> = self.leaves().collect();
+        if leaves.windows(2).any(|x| x[0] >= x[1]) {
+            return Ok(false);
+        }
+
+        BatchProof {
+            leaf_hashes: leaves
+                .iter()
+                .map(|x| x.leaf_hash_with_hasher::<H>(self.leaf_encoding))
+                .collect(),
+            indices,
+            proof: self.proof.clone(),
+            proof_flags: vec![],
+            leaf_count: self.leaf_count,
+            hash_mode: HashMode::Ordered,
+        }
+        .verify_with_hasher::<H>(root)
+    }
+}
+
+impl<L: Leaf<Key = Address>> RangeProof<L> {
+    /// Checks that the accounts are every leaf of the tree whose address has
+    /// the prefix
+    pub fn verify_prefix(&self, root: [u8; 32], prefix: &AddressPrefix) -> Result<bool, Error> {
+        self.verify_prefix_with_hasher::<Keccak256>(root, prefix)
+    }
+
+    pub fn verify_prefix_with_hasher<H: MerkleHasher>(
+        &self,
+        root: [u8; 32],
+        prefix: &AddressPrefix,
+    ) -> Result<bool, Error> {
+        let bounded_before = match &self.before {
+            Some(before) => prefix.compare(&before.key()).is_lt(),
+            None => self.start == 0,
+        };
+        let bounded_after = match &self.after {
+            Some(after) => prefix.compare(&after.key()).is_gt(),
+            None => self.end() == Some(self.leaf_count),
+        };
+        Ok(bounded_before
+            && bounded_after
+            && self.accounts.iter().all(|x| prefix.matches(&x.key()))
+            && self.verify_with_hasher::<H>(root)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::alphabetic_merkle_tree::{AlphabeticMerkleTree, OrderingMode, TreeOptions};
+    use crate::test_utils::{
+        fixed_accounts, node_as_delegation, random_accounts, random_delegations,
+    };
+
+    #[test]
+    fn test_address_prefix() {
+        let address: Address = "0x47ac0fb4f2d84898e4d9e7b4dab3c24507a6d503"
+            .parse()
+            .unwrap();
+        for matching in ["", "0x", "4", "0x47A", "47ac0", &format!("{address:?}")] {
+            assert!(matching.parse::<AddressPrefix>().unwrap().matches(&address));
+        }
+        let prefix: AddressPrefix = "47b".parse().unwrap();
+        assert_eq!(prefix.len(), 3);
+        assert_eq!(prefix.compare(&address), Ordering::Less);
+        let prefix: AddressPrefix = "0x3f".parse().unwrap();
+        assert_eq!(prefix.compare(&address), Ordering::Greater);
+
+        for invalid in ["0xg", "47ac ", &format!("{address:?}0")] {
+            assert!(matches!(
+                invalid.parse::<AddressPrefix>(),
+                Err(Error::InvalidAddressError(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_range_proofs() {
+        let accounts = random_accounts(37, 8);
+        let tree = AlphabeticMerkleTree::new(&accounts).unwrap();
+        for start in 0..accounts.len() {
+            for end in [start + 1, start + 2, start + 10, accounts.len()] {
+                let end = end.min(accounts.len());
+                let proof = tree.generate_range_proof(start..end).unwrap();
+                assert_eq!(proof.accounts, &accounts[start..end]);
+                assert!(proof.verify(tree.root()).unwrap());
+            }
+        }
+
+        let proof = tree.generate_range_proof(10..20).unwrap();
+        let mut shifted = proof.clone();
+        shifted.start = 11;
+        assert!(!matches!(shifted.verify(tree.root()), Ok(true)));
+        let mut swapped = proof.clone();
+        swapped.accounts.swap(2, 3);
+        assert!(!swapped.verify(tree.root()).unwrap());
+        let mut truncated = proof.clone();
+        truncated.accounts.pop();
+        assert!(!matches!(truncated.verify(tree.root()), Ok(true)));
+
+        // Ranges past the last leaf are rejected without overflowing
+        for start in [30, usize::MAX - 5, usize::MAX] {
+            let mut overflowing = proof.clone();
+            overflowing.start = start;
+            assert!(matches!(
+                overflowing.verify(tree.root()),
+                Err(Error::LeafIndexOutOfRangeError)
+            ));
+            assert!(matches!(
+                overflowing.verify_prefix(tree.root(), &AddressPrefix::default()),
+                Err(Error::LeafIndexOutOfRangeError) | Ok(false)
+            ));
+        }
+
+        for range in [0..0, 5..38] {
+            assert!(tree.generate_range_proof(range).is_err());
+        }
+        let commutative = AlphabeticMerkleTree::with_options(&accounts, TreeOptions::standard());
+        assert!(matches!(
+            commutative.unwrap().generate_range_proof(0..2),
+            Err(Error::OrderedHashingRequiredError)
+        ));
+    }
+
+    #[test]
+    fn test_prefix_proofs() {
+        let accounts = random_accounts(300, 9);
+        let address_options = TreeOptions {
+            ordering: OrderingMode::Address,
+            ..Default::default()
+        };
+        for options in [TreeOptions::default(), address_options] {
+            let tree = AlphabeticMerkleTree::with_options(&accounts, options).unwrap();
+            for nibble in 0..16 {
+                for prefix in [
+                    format!("{nibble:x}"),
+                    format!("{nibble:x}7"),
+                    format!("{nibble:x}7f"),
+                ] {
+                    let prefix: AddressPrefix = prefix.parse().unwrap();
+                    let expected: Vec<AccountWithBalance> = accounts
+                        .iter()
+                        .filter(|x| prefix.matches(&x.address))
+                        .copied()
+                        .collect();
+                    let proof = tree.generate_prefix_proof(&prefix).unwrap();
+                    assert_eq!(proof.accounts, expected);
+                    assert!(proof.verify_prefix(tree.root(), &prefix).unwrap());
+                }
+            }
+
+            let prefix = AddressPrefix::default();
+            let proof = tree.generate_prefix_proof(&prefix).unwrap();
+            assert_eq!(proof.accounts, accounts);
+            assert!(proof.verify_prefix(tree.root(), &prefix).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_incomplete_prefix_proofs() {
+        let accounts = fixed_accounts();
+        let tree = AlphabeticMerkleTree::new(&accounts).unwrap();
+        let prefix: AddressPrefix = "0xa7".parse().unwrap();
+        let proof = tree.generate_prefix_proof(&prefix).unwrap();
+        assert_eq!(proof.accounts, &accounts[2..3]);
+        assert!(proof.verify_prefix(tree.root(), &prefix).unwrap());
+
+        // Without its neighbours the range could be missing accounts
+        let page = tree.generate_range_proof(2..3).unwrap();
+        assert!(!page.verify_prefix(tree.root(), &prefix).unwrap());
+
+        // The neighbours must not have the prefix
+        let every_address = AddressPrefix::default();
+        assert!(!proof.verify_prefix(tree.root(), &every_address).unwrap());
+
+        // A prefix without accounts is proven by the leaves around it
+        let missing: AddressPrefix = "0xb".parse().unwrap();
+        let proof = tree.generate_prefix_proof(&missing).unwrap();
+        assert!(proof.accounts.is_empty());
+        assert_eq!(
+            (proof.before, proof.after),
+            (Some(accounts[2]), Some(accounts[3]))
+        );
+        assert!(proof.verify_prefix(tree.root(), &missing).unwrap());
+        assert!(!proof.verify_prefix(tree.root(), &prefix).unwrap());
+    }
+
+    #[test]
+    fn test_internal_nodes_are_not_neighbours() {
+        let tree = AlphabeticMerkleTree::new(&random_delegations(64, 8)).unwrap();
+        let leaves = tree.accounts();
+        let leaf_hashes: Vec<[u8; 32]> = leaves
+            .iter()
+            .map(|x| x.leaf_hash(LeafEncoding::Packed))
+            .collect();
+        let node =
+            |index: usize| node_as_delegation(&leaf_hashes[2 * index], &leaf_hashes[2 * index + 1]);
+
+        // Sibling nodes posing as consecutive leaves of the tree of the 32
+        // parents of the leaves, around an empty range
+        for index in (0..32).step_by(2) {
+            let proof = RangeProof {
+                start: index + 1,
+                accounts: vec![],
+                before: Some(node(index)),
+                after: Some(node(index + 1)),
+                proof: tree.generate_proof_hashes(&leaves[2 * index]).unwrap()[2..].to_vec(),
+                leaf_count: 32,
+                leaf_encoding: LeafEncoding::Packed,
+            };
+            assert!(!matches!(proof.verify(tree.root()), Ok(true)));
+            for leaf in leaves {
+                let prefix: AddressPrefix = format!("{:?}", leaf.address).parse().unwrap();
+                assert!(!matches!(
+                    proof.verify_prefix(tree.root(), &prefix),
+                    Ok(true)
+                ));
+            }
+        }
+    }
+}
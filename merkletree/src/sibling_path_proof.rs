@@ -0,0 +1,300 @@
+use ethers::abi::{encode, Token};
+use serde::{Deserialize, Serialize};
+
+use crate::account_with_balance::LeafEncoding;
+use crate::alphabetic_merkle_tree::HashMode;
+use crate::commutative_merkle_tree;
+use crate::hasher::{Keccak256, MerkleHasher};
+use crate::leaf::Leaf;
+use crate::merkle_tree::Error;
+use crate::verification::{
+    expected_proof_len, proof_hashes_from_bytes, verify_leaf_inclusion_with_hasher,
+};
+
+/// Inclusion proof of a single leaf as its sibling hashes in order from the
+/// leaf up, with the position of the leaf. Converts losslessly to and from the
+/// serialized proofs of [`crate::alphabetic_merkle_tree::AlphabeticMerkleTree`].
+///
+/// Deserialized proofs are checked by [`SiblingPathProof::new`].
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "SiblingPathProofFields")]
+pub struct SiblingPathProof {
+    pub siblings: Vec<[u8; 32]>,
+    pub index: usize,
+    pub leaf_count: usize,
+    #[serde(default)]
+    pub hash_mode: HashMode,
+}
+
+/// Unchecked fields of a deserialized [`SiblingPathProof`]
+#[derive(Deserialize)]
+struct SiblingPathProofFields {
+    siblings: Vec<[u8; 32]>,
+    index: usize,
+    leaf_count: usize,
+    #[serde(default)]
+    hash_mode: HashMode,
+}
+
+impl TryFrom<SiblingPathProofFields> for SiblingPathProof {
+    type Error = Error;
+
+    fn try_from(fields: SiblingPathProofFields) -> Result<Self, Error> {
+        Self::new(
+            fields.siblings,
+            fields.index,
+            fields.leaf_count,
+            fields.hash_mode,
+        )
+    }
+}
+
+impl SiblingPathProof {
+    /// Fails if the proof doesn't have as many siblings as the position requires
+    pub fn new(
+        siblings: Vec<[u8; 32]>,
+        index: usize,
+        leaf_count: usize,
+        hash_mode: HashMode,
+    ) -> Result<Self, Error> {
+        if leaf_count == 0 {
+            return Err(Error::EmptyAccountsListError);
+        }
+        if index >= leaf_count {
+            return Err(Error::LeafIndexOutOfRangeError);
+        }
+        let proof_len = match hash_mode {
+            HashMode::Ordered => expected_proof_len(index, leaf_count),
+            HashMode::Commutative => commutative_merkle_tree::proof_len(index, leaf_count),
+        };
+        if siblings.len() != proof_len {
+            return Err(Error::MerkleProofParsingError);
+        }
+        Ok(Self {
+            siblings,
+            index,
+            leaf_count,
+            hash_mode,
+        })
+    }
+
+    /// Parses a serialized proof of the leaf at `index`
+    pub fn from_bytes(
+        proof_bytes: &[u8],
+        index: usize,
+        leaf_count: usize,
+        hash_mode: HashMode,
+    ) -> Result<Self, Error> {
+        Self::new(
+            proof_hashes_from_bytes(proof_bytes)?,
+            index,
+            leaf_count,
+            hash_mode,
+        )
+    }
+
+    /// Concatenated sibling hashes, the format of `MerkleProof::to_bytes()`
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.siblings.concat()
+    }
+
+    /// Whether each sibling is the left node of its pair, in the order of
+    /// `siblings`. Commutative proofs verify without them.
+    pub fn directions(&self) -> Vec<bool> {
+        match self.hash_mode {
+            HashMode::Ordered => {
+                let mut index = self.index;
+                let mut layer_len = self.leaf_count;
+                let mut directions = vec![];
+                while layer_len > 1 {
+                    // The last node of an uneven layer is promoted without a sibling
+                    if index ^ 1 < layer_len {
+                        directions.push(!index.is_multiple_of(2));
+                    }
+                    index /= 2;
+                    layer_len = layer_len.div_ceil(2);
+                }
+                directions
+            }
+            HashMode::Commutative => {
+                // Array layout with the root at 0, where right children have
+                // even indices
+                let mut tree_index = 2 * self.leaf_count - 2 - self.index;
+                let mut directions = vec![];
+                while tree_index > 0 {
+                    directions.push(tree_index.is_multiple_of(2));
+                    tree_index = (tree_index - 1) / 2;
+                }
+                directions
+            }
+        }
+    }
+
+    /// Direction bits packed into an integer, bit `i` set when sibling `i` is
+    /// on the left
+    pub fn direction_bits(&self) -> u64 {
+        self.directions()
+            .iter()
+            .enumerate()
+            .fold(0, |bits, (position, is_left)| {
+                bits | ((*is_left as u64) << position)
+            })
+    }
+
+    /// `abi.encode(bytes32[])` of the siblings
+    pub fn abi_encoded_siblings(&self) -> Vec<u8> {
+        encode(&[Token::Array(
+            self.siblings
+                .iter()
+                .map(|sibling| Token::FixedBytes(sibling.to_vec()))
+                .collect(),
+        )])
+    }
+
+    /// Checks that `leaf_hash` is the leaf at `index` of the tree with the given root
+    pub fn verify(&self, root: [u8; 32], leaf_hash: [u8; 32]) -> Result<bool, Error> {
+        self.verify_with_hasher::<Keccak256>(root, leaf_hash)
+    }
+
+    pub fn verify_with_hasher<H: MerkleHasher>(
+        &self,
+        root: [u8; 32],
+        leaf_hash: [u8; 32],
+    ) -> Result<bool, Error> {
+        verify_leaf_inclusion_with_hasher::<H>(
+            self.hash_mode,
+            root,
+            leaf_hash,
+            &self.to_bytes(),
+            self.index,
+            self.leaf_count,
+        )
+    }
+
+    pub fn verify_account(
+        &self,
+        root: [u8; 32],
+        account: &impl Leaf,
+        leaf_encoding: LeafEncoding,
+    ) -> Result<bool, Error> {
+        self.verify(root, account.leaf_hash(leaf_encoding))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ethers::abi::{decode, ParamType};
+
+    use crate::alphabetic_merkle_tree::{AlphabeticMerkleTree, TreeOptions};
+    use crate::test_utils::{fixed_accounts, random_accounts};
+
+    #[test]
+    fn test_sibling_path_round_trip() {
+        let accounts = random_accounts(13, 3);
+        for options in [TreeOptions::default(), TreeOptions::standard()] {
+            let tree = AlphabeticMerkleTree::with_options(&accounts, options).unwrap();
+            for (index, account) in tree.accounts().iter().enumerate() {
+                let proof = tree.generate_sibling_path_proof(account).unwrap();
+                assert_eq!(proof.index, index);
+                assert!(proof
+                    .verify_account(tree.root(), account, options.leaf_encoding)
+                    .unwrap());
+
+                let proof_bytes = tree.generate_proof_of_inclusion(account).unwrap();
+                assert_eq!(proof.to_bytes(), proof_bytes);
+                let parsed = SiblingPathProof::from_bytes(
+                    &proof_bytes,
+                    index,
+                    accounts.len(),
+                    options.hash_mode,
+                )
+                .unwrap();
+                assert_eq!(parsed, proof);
+
+                let json = serde_json::to_string(&proof).unwrap();
+                assert_eq!(
+                    serde_json::from_str::<SiblingPathProof>(&json).unwrap(),
+                    proof
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_directions() {
+        let accounts = fixed_accounts();
+        let tree = AlphabeticMerkleTree::new(&accounts).unwrap();
+
+        let directions: Vec<Vec<bool>> = accounts
+            .iter()
+            .map(|x| tree.generate_sibling_path_proof(x).unwrap().directions())
+            .collect();
+        assert_eq!(directions[0], vec![false, false, false]);
+        assert_eq!(directions[3], vec![true, true, false]);
+        // The fifth leaf is promoted on the first two layers
+        assert_eq!(directions[4], vec![true]);
+
+        let proof = tree.generate_sibling_path_proof(&accounts[3]).unwrap();
+        assert_eq!(proof.direction_bits(), 0b011);
+
+        // Rebuilding the root from the directions alone
+        let mut node = accounts[3].generate_hash();
+        for (sibling, is_left) in proof.siblings.iter().zip(proof.directions()) {
+            node = if is_left {
+                Keccak256::hash_pair(sibling, &node)
+            } else {
+                Keccak256::hash_pair(&node, sibling)
+            };
+        }
+        assert_eq!(node, tree.root());
+    }
+
+    #[test]
+    fn test_abi_encoded_siblings() {
+        let accounts = fixed_accounts();
+        let tree = AlphabeticMerkleTree::new(&accounts).unwrap();
+        let proof = tree.generate_sibling_path_proof(&accounts[1]).unwrap();
+
+        let encoded = proof.abi_encoded_siblings();
+        assert_eq!(encoded.len(), 64 + 32 * proof.siblings.len());
+        let decoded = decode(
+            &[ParamType::Array(Box::new(ParamType::FixedBytes(32)))],
+            &encoded,
+        )
+        .unwrap();
+        assert_eq!(
+            decoded[0].clone().into_array().unwrap(),
+            proof
+                .siblings
+                .iter()
+                .map(|x| Token::FixedBytes(x.to_vec()))
+                .collect::<Vec<Token>>()
+        );
+    }
+
+    #[test]
+    fn test_malformed_sibling_path() {
+        let accounts = fixed_accounts();
+        let tree = AlphabeticMerkleTree::new(&accounts).unwrap();
+        let proof = tree.generate_proof_of_inclusion(&accounts[1]).unwrap();
+
+        let result = SiblingPathProof::from_bytes(&proof[32..], 1, 5, HashMode::Ordered);
+        assert!(matches!(result, Err(Error::MerkleProofParsingError)));
+        let result = SiblingPathProof::from_bytes(&proof[..40], 1, 5, HashMode::Ordered);
+        assert!(matches!(result, Err(Error::MerkleProofParsingError)));
+        let result = SiblingPathProof::from_bytes(&proof, 5, 5, HashMode::Ordered);
+        assert!(matches!(result, Err(Error::LeafIndexOutOfRangeError)));
+
+        // Deserialized proofs are checked as well, so their directions can't underflow
+        let proof = tree.generate_sibling_path_proof(&accounts[1]).unwrap();
+        let mut json = serde_json::to_value(&proof).unwrap();
+        json["index"] = serde_json::json!(9);
+        json["hash_mode"] = serde_json::json!("Commutative");
+        assert!(serde_json::from_value::<SiblingPathProof>(json).is_err());
+        let mut json = serde_json::to_value(&proof).unwrap();
+        json["leaf_count"] = serde_json::json!(0);
+        assert!(serde_json::from_value::<SiblingPathProof>(json).is_err());
+    }
+}
@@ -0,0 +1,198 @@
+use std::borrow::Cow;
+use std::cmp::Ordering;
+
+use ethers::types::{Address, U256};
+use serde::{Deserialize, Serialize};
+
+use crate::account_with_balance::AccountWithBalance;
+use crate::alphabetic_merkle_tree::{AlphabeticMerkleTree, OrderingMode};
+use crate::duplicates::duplicates_error;
+use crate::hasher::MerkleHasher;
+use crate::merkle_tree::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BalanceChange {
+    pub address: Address,
+    pub old_balance: U256,
+    pub new_balance: U256,
+}
+
+/// What changed between two snapshots, every list sorted by address
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SnapshotDiff {
+    pub old_root: [u8; 32],
+    pub new_root: [u8; 32],
+    pub added: Vec<AccountWithBalance>,
+    pub removed: Vec<AccountWithBalance>,
+    pub changed: Vec<BalanceChange>,
+}
+
+impl SnapshotDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Differences between two account sets, given in any order. Every address
+/// must appear at most once in each set.
+pub fn diff_snapshots(
+    old_accounts: &[AccountWithBalance],
+    new_accounts: &[AccountWithBalance],
+) -> Result<SnapshotDiff, Error> {
+    diff_trees(
+        &AlphabeticMerkleTree::new(old_accounts)?,
+        &AlphabeticMerkleTree::new(new_accounts)?,
+    )
+}
+
+/// Differences between the leaves of two trees, found in a single pass over
+/// both in leaf order
+pub fn diff_trees<H: MerkleHasher>(
+    old_tree: &AlphabeticMerkleTree<H>,
+    new_tree: &AlphabeticMerkleTree<H>,
+) -> Result<SnapshotDiff, Error> {
+    let old_accounts = accounts_by_address(old_tree)?;
+    let new_accounts = accounts_by_address(new_tree)?;
+    let mut diff = SnapshotDiff {
+        old_root: old_tree.root(),
+        new_root: new_tree.root(),
+        added: vec![],
+        removed: vec![],
+        changed: vec![],
+    };
+
+    // Both account orderings sort by address first
+    let (mut old_index, mut new_index) = (0, 0);
+    while old_index < old_accounts.len() || new_index < new_accounts.len() {
+        let ordering = match (old_accounts.get(old_index), new_accounts.get(new_index)) {
+            (Some(old), Some(new)) => old.address.cmp(&new.address),
+            (Some(_), None) => Ordering::Less,
+            (None, _) => Ordering::Greater,
+        };
+        match ordering {
+            Ordering::Less => {
+                diff.removed.push(old_accounts[old_index]);
+                old_index += 1;
+            }
+            Ordering::Greater => {
+                diff.added.push(new_accounts[new_index]);
+                new_index += 1;
+            }
+            Ordering::Equal => {
+                let (old, new) = (old_accounts[old_index], new_accounts[new_index]);
+                if old.balance != new.balance {
+                    diff.changed.push(BalanceChange {
+                        address: old.address,
+                        old_balance: old.balance,
+                        new_balance: new.balance,
+                    });
+                }
+                old_index += 1;
+                new_index += 1;
+            }
+        }
+    }
+    Ok(diff)
+}
+
+/// Leaves of the tree, sorted again if the tree sorts them by hash. Fails if
+/// an address has several leaves.
+fn accounts_by_address<H: MerkleHasher>(
+    tree: &AlphabeticMerkleTree<H>,
+) -> Result<Cow<'_, [AccountWithBalance]>, Error> {
+    let accounts = match tree.options().ordering {
+        OrderingMode::LeafHash => {
+            let mut accounts = tree.accounts().to_vec();
+            accounts.sort_unstable();
+            Cow::Owned(accounts)
+        }
+        _ => Cow::Borrowed(tree.accounts()),
+    };
+    if accounts.windows(2).any(|x| x[0].address == x[1].address) {
+        return Err(duplicates_error(tree.accounts()));
+    }
+    Ok(accounts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::alphabetic_merkle_tree::{OrderingMode, TreeOptions};
+    use crate::test_utils::{fixed_accounts, random_accounts, shuffled};
+
+    #[test]
+    fn test_diff_snapshots() {
+        let old_accounts = fixed_accounts();
+        let mut new_accounts = old_accounts[1..].to_vec();
+        new_accounts[1].balance = U256::from(30);
+        let added = AccountWithBalance::new("0000000000000000000000000000000000000001", "1");
+        new_accounts.push(added);
+
+        let diff = diff_snapshots(&old_accounts, &shuffled(&new_accounts, 1)).unwrap();
+        assert_eq!(
+            diff.old_root,
+            AlphabeticMerkleTree::new(&old_accounts).unwrap().root()
+        );
+        assert_eq!(
+            diff.new_root,
+            AlphabeticMerkleTree::new(&new_accounts).unwrap().root()
+        );
+        assert_eq!(diff.added, vec![added]);
+        assert_eq!(diff.removed, vec![old_accounts[0]]);
+        assert_eq!(
+            diff.changed,
+            vec![BalanceChange {
+                address: old_accounts[2].address,
+                old_balance: U256::from(3),
+                new_balance: U256::from(30),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_diff_of_same_snapshot() {
+        let accounts = random_accounts(50, 3);
+        let diff = diff_snapshots(&accounts, &shuffled(&accounts, 2)).unwrap();
+        assert!(diff.is_empty());
+        assert_eq!(diff.old_root, diff.new_root);
+    }
+
+    #[test]
+    fn test_diff_of_disjoint_snapshots() {
+        let old_accounts = random_accounts(20, 4);
+        let new_accounts = random_accounts(30, 5);
+        let options = TreeOptions {
+            ordering: OrderingMode::Address,
+            ..Default::default()
+        };
+        let diff = diff_trees(
+            &AlphabeticMerkleTree::with_options(&old_accounts, options).unwrap(),
+            &AlphabeticMerkleTree::with_options(&new_accounts, options).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(diff.removed, old_accounts);
+        assert_eq!(diff.added, new_accounts);
+        assert!(diff.changed.is_empty());
+
+        let reversed = diff_snapshots(&new_accounts, &old_accounts).unwrap();
+        assert_eq!(reversed.added, diff.removed);
+        assert_eq!(reversed.removed, diff.added);
+    }
+
+    #[test]
+    fn test_diff_with_duplicate_addresses() {
+        let accounts = fixed_accounts();
+        let mut duplicated = accounts.clone();
+        duplicated.push(AccountWithBalance {
+            balance: U256::from(7),
+            ..accounts[0]
+        });
+        let result = diff_snapshots(&accounts, &duplicated);
+        let Err(Error::DuplicateAddressError(duplicates)) = result else {
+            panic!("duplicates are rejected")
+        };
+        // Positions are leaf indices of the tree
+        assert_eq!(duplicates[0].positions, vec![0, 1]);
+    }
+}
@@ -0,0 +1,484 @@
+use ethers::types::{Address, H256, U256};
+use serde::{Deserialize, Serialize};
+
+use crate::absence_proof::LeafProof;
+use crate::account_with_balance::{AccountWithBalance, LeafEncoding};
+use crate::alphabetic_merkle_tree::{AlphabeticMerkleTree, HashMode, TreeOptions};
+use crate::merkle_tree::Error;
+use crate::verification::proof_hashes_from_bytes;
+
+const HEADER: &str = "// SPDX-License-Identifier: MIT
+// Generated by the merkletree crate, do not edit
+pragma solidity ^0.8.20;
+";
+
+const PACKED_LEAF_HASH: &str = "
+    /// @notice `keccak256(account ++ balance)` with the balance in as few bytes
+    /// as it fits, like `encode_packed` of ethers-rs
+    function leafHash(address account, uint256 balance) internal pure returns (bytes32 hash) {
+        uint256 length = byteLength(balance);
+        assembly {
+            let ptr := mload(0x40)
+            mstore(ptr, shl(96, account))
+            mstore(add(ptr, 20), shl(mul(8, sub(32, length)), balance))
+            hash := keccak256(ptr, add(20, length))
+        }
+    }
+";
+
+const STANDARD_LEAF_HASH: &str = "
+    /// @notice Leaf of OpenZeppelin's `StandardMerkleTree` with `[\"address\", \"uint256\"]`
+    function leafHash(address account, uint256 balance) internal pure returns (bytes32) {
+        return keccak256(bytes.concat(keccak256(abi.encode(account, balance))));
+    }
+";
+
+const BYTE_LENGTH: &str = "
+    /// @notice Number of bytes of `value` without its leading zero bytes
+    function byteLength(uint256 value) private pure returns (uint256 length) {
+        while (value != 0) {
+            length++;
+            value >>= 8;
+        }
+    }
+";
+
+const ORDERED_PROOF: &str = "
+    function hashPair(bytes32 left, bytes32 right) private pure returns (bytes32) {
+        return keccak256(abi.encodePacked(left, right));
+    }
+
+    /// @notice Root obtained from the leaf at `index` and its sibling hashes.
+    /// Reverts if the proof doesn't have as many hashes as the position requires.
+    function processProof(bytes32 leaf, bytes32[] memory proof, uint256 index, uint256 leafCount)
+        internal
+        pure
+        returns (bytes32 node)
+    {
+        if (index >= leafCount) revert LeafIndexOutOfRange();
+        node = leaf;
+        uint256 position;
+        for (uint256 layerLength = leafCount; layerLength > 1; layerLength = (layerLength + 1) / 2) {
+            // The last node of an uneven layer is promoted without a sibling
+            if ((index ^ 1) < layerLength) {
+                if (position == proof.length) revert InvalidProofLength();
+                bytes32 sibling = proof[position++];
+                node = index % 2 == 0 ? hashPair(node, sibling) : hashPair(sibling, node);
+            }
+            index /= 2;
+        }
+        if (position != proof.length) revert InvalidProofLength();
+    }
+
+    /// @notice Checks that the account of `leaf` is at `leaf.index` of the tree
+    function verifyInclusion(bytes32 root, uint256 leafCount, Leaf memory leaf) internal pure returns (bool) {
+        return processProof(leafHash(leaf.account, leaf.balance), leaf.proof, leaf.index, leafCount) == root;
+    }
+";
+
+const COMMUTATIVE_PROOF: &str = "
+    function hashPair(bytes32 a, bytes32 b) private pure returns (bytes32) {
+        return a < b ? keccak256(abi.encodePacked(a, b)) : keccak256(abi.encodePacked(b, a));
+    }
+
+    /// @notice Root obtained from a leaf and its proof, as `MerkleProof.processProof`
+    function processProof(bytes32 leaf, bytes32[] memory proof) internal pure returns (bytes32 node) {
+        node = leaf;
+        for (uint256 i = 0; i < proof.length; i++) {
+            node = hashPair(node, proof[i]);
+        }
+    }
+
+    /// @notice Checks that the account is a leaf of the tree. Proofs don't pin
+    /// the position of the leaf, so there are no absence proofs.
+    function verifyInclusion(bytes32 root, address account, uint256 balance, bytes32[] memory proof)
+        internal
+        pure
+        returns (bool)
+    {
+        return processProof(leafHash(account, balance), proof) == root;
+    }
+";
+
+const ABSENCE_PROOF: &str = "
+    /// @notice Whether `(a, aBalance)` sorts before `(b, bBalance)` in leaf order:
+    /// by address, then by the packed balance bytes, then by their length
+    function sortsBefore(address a, uint256 aBalance, address b, uint256 bBalance) private pure returns (bool) {
+        if (a != b) return a < b;
+        uint256 aLength = byteLength(aBalance);
+        uint256 bLength = byteLength(bBalance);
+        uint256 aKey = aBalance << (8 * (32 - aLength));
+        uint256 bKey = bBalance << (8 * (32 - bLength));
+        if (aKey != bKey) return aKey < bKey;
+        return aLength < bLength;
+    }
+
+    /// @notice Checks that the account is not a leaf of the tree: its neighbours
+    /// are in the tree, consecutive, and sort on both sides of it
+    function verifyAbsence(bytes32 root, AbsenceProof memory proof, address account, uint256 balance)
+        internal
+        pure
+        returns (bool)
+    {
+        if (proof.hasLeft && !sortsBefore(proof.left.account, proof.left.balance, account, balance)) return false;
+        if (proof.hasRight && !sortsBefore(account, balance, proof.right.account, proof.right.balance)) return false;
+        return verifyGap(root, proof);
+    }
+
+    /// @notice Checks that no leaf of the tree has the address, whatever its balance
+    function verifyAddressAbsence(bytes32 root, AbsenceProof memory proof, address account)
+        internal
+        pure
+        returns (bool)
+    {
+        if (proof.hasLeft && !(proof.left.account < account)) return false;
+        if (proof.hasRight && !(account < proof.right.account)) return false;
+        return verifyGap(root, proof);
+    }
+
+    function verifyGap(bytes32 root, AbsenceProof memory proof) private pure returns (bool) {
+        if (proof.hasLeft && !verifyInclusion(root, proof.leafCount, proof.left)) return false;
+        if (proof.hasRight && !verifyInclusion(root, proof.leafCount, proof.right)) return false;
+        if (proof.hasLeft && proof.hasRight) return proof.right.index == proof.left.index + 1;
+        if (proof.hasLeft) return proof.left.index == proof.leafCount - 1;
+        if (proof.hasRight) return proof.right.index == 0;
+        return false;
+    }
+";
+
+const ORDERED_TYPES: &str = "
+    error LeafIndexOutOfRange();
+    error InvalidProofLength();
+
+    /// @notice Leaf of the tree with its position and sibling hashes
+    struct Leaf {
+        address account;
+        uint256 balance;
+        uint256 index;
+        bytes32[] proof;
+    }
+
+    /// @notice Leaves surrounding an absent account. An account sorting before
+    /// the first or after the last leaf only has one neighbour.
+    struct AbsenceProof {
+        bool hasLeft;
+        Leaf left;
+        bool hasRight;
+        Leaf right;
+        uint256 leafCount;
+    }
+";
+
+/// Solidity library verifying proofs of trees built with the given options.
+///
+/// It has `leafHash` and `verifyInclusion` for both hash modes, and with
+/// ordered hashing `verifyAbsence` and `verifyAddressAbsence`. Only Keccak-256
+/// trees can be verified on-chain.
+pub fn generate_solidity_verifier(library_name: &str, options: TreeOptions) -> String {
+    let mut source = HEADER.to_string();
+    source.push_str(&format!(
+        "\n/// @notice Verifies proofs of an alphabetic merkle tree with {:?} leaves and {:?} hashing\nlibrary {} {{",
+        options.leaf_encoding, options.hash_mode, library_name
+    ));
+    if options.hash_mode == HashMode::Ordered {
+        source.push_str(ORDERED_TYPES);
+    }
+    source.push_str(match options.leaf_encoding {
+        LeafEncoding::Packed => PACKED_LEAF_HASH,
+        LeafEncoding::Standard => STANDARD_LEAF_HASH,
+    });
+    match options.hash_mode {
+        HashMode::Ordered => {
+            source.push_str(ORDERED_PROOF);
+            source.push_str(ABSENCE_PROOF);
+        }
+        HashMode::Commutative => source.push_str(COMMUTATIVE_PROOF),
+    }
+    if options.leaf_encoding == LeafEncoding::Packed || options.hash_mode == HashMode::Ordered {
+        source.push_str(BYTE_LENGTH);
+    }
+    source.push_str("}\n");
+    source
+}
+
+/// Leaf of the tree with its position and sibling hashes, as the `Leaf` struct
+/// of the Solidity verifier
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LeafVector {
+    pub account: Address,
+    pub balance: U256,
+    pub index: usize,
+    pub proof: Vec<H256>,
+}
+
+/// Account that is not in the tree with the neighbours proving it
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AbsenceVector {
+    pub account: Address,
+    pub balance: U256,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub left: Option<LeafVector>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub right: Option<LeafVector>,
+}
+
+/// Roots, leaves and proofs of a tree, read by the Foundry tests of the
+/// Solidity verifier with `vm.readFile` and the `vm.parseJson*` cheatcodes
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestVectors {
+    pub hash_mode: HashMode,
+    pub leaf_encoding: LeafEncoding,
+    pub root: H256,
+    pub leaf_count: usize,
+    /// Leaf hashes in leaf order
+    pub leaves: Vec<H256>,
+    /// Inclusion proof of every leaf
+    pub inclusion: Vec<LeafVector>,
+    /// Absence proofs, only with ordered hashing
+    #[serde(default)]
+    pub absence: Vec<AbsenceVector>,
+}
+
+fn leaf_vector(proof: &LeafProof) -> Result<LeafVector, Error> {
+    Ok(LeafVector {
+        account: proof.account.address,
+        balance: proof.account.balance,
+        index: proof.index,
+        proof: proof_hashes_from_bytes(&proof.proof)?
+            .into_iter()
+            .map(H256::from)
+            .collect(),
+    })
+}
+
+impl TestVectors {
+    /// Vectors of the tree of `accounts`, with absence proofs of
+    /// `absent_accounts` when the hash mode allows them
+    pub fn new(
+        accounts: &[AccountWithBalance],
+        absent_accounts: &[AccountWithBalance],
+        options: TreeOptions,
+    ) -> Result<Self, Error> {
+        let merkle_tree = AlphabeticMerkleTree::with_options(accounts, options)?;
+
+        let inclusion = merkle_tree
+            .accounts()
+            .iter()
+            .enumerate()
+            .map(|(index, account)| {
+                Ok(LeafVector {
+                    account: account.address,
+                    balance: account.balance,
+                    index,
+                    proof: merkle_tree
+                        .generate_proof_hashes(account)?
+                        .into_iter()
+                        .map(H256::from)
+                        .collect(),
+                })
+            })
+            .collect::<Result<Vec<LeafVector>, Error>>()?;
+
+        let absence = match options.hash_mode {
+            HashMode::Ordered => absent_accounts
+                .iter()
+                .map(|account| {
+                    let proof = merkle_tree.generate_proof_of_absense(account)?;
+                    Ok(AbsenceVector {
+                        account: account.address,
+                        balance: account.balance,
+                        left: proof.left.as_ref().map(leaf_vector).transpose()?,
+                        right: proof.right.as_ref().map(leaf_vector).transpose()?,
+                    })
+                })
+                .collect::<Result<Vec<AbsenceVector>, Error>>()?,
+            HashMode::Commutative => vec![],
+        };
+
+        Ok(Self {
+            hash_mode: options.hash_mode,
+            leaf_encoding: options.leaf_encoding,
+            root: H256::from(merkle_tree.root()),
+            leaf_count: merkle_tree.leaves_len(),
+            leaves: merkle_tree
+                .accounts()
+                .iter()
+                .map(|x| H256::from(x.leaf_hash(options.leaf_encoding)))
+                .collect(),
+            inclusion,
+            absence,
+        })
+    }
+}
+
+pub fn generate_test_vectors(
+    accounts: &[AccountWithBalance],
+    absent_accounts: &[AccountWithBalance],
+    options: TreeOptions,
+) -> Result<String, Error> {
+    let test_vectors = TestVectors::new(accounts, absent_accounts, options)?;
+    Ok(serde_json::to_string_pretty(&test_vectors).expect("test vectors serialize to JSON"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::absence_proof::AbsenceProof;
+    use crate::commutative_merkle_tree::verify_proof;
+    use crate::test_utils::fixed_accounts;
+    use crate::verification::verify_inclusion_with_options;
+
+    const ALPHABETIC_VERIFIER: &str = include_str!("../contracts/src/AlphabeticMerkleVerifier.sol");
+    const STANDARD_VERIFIER: &str = include_str!("../contracts/src/StandardMerkleVerifier.sol");
+    const ALPHABETIC_VECTORS: &str = include_str!("../contracts/test/fixtures/alphabetic.json");
+    const STANDARD_VECTORS: &str = include_str!("../contracts/test/fixtures/standard.json");
+
+    fn absent_accounts() -> Vec<AccountWithBalance> {
+        vec![
+            AccountWithBalance::new("0000000000000000000000000000000000000001", "1"),
+            AccountWithBalance::new("A7A93fd0a276fc1C0197a5B5623eD117786eeD06", "4"),
+            AccountWithBalance::new("FF54284f345afc66a98fbB0a0Afe71e0F007B948", "1"),
+        ]
+    }
+
+    fn leaf_proof(leaf: &LeafVector) -> LeafProof {
+        LeafProof {
+            account: AccountWithBalance {
+                address: leaf.account,
+                balance: leaf.balance,
+            },
+            index: leaf.index,
+            proof: leaf.proof.iter().flat_map(|x| x.to_fixed_bytes()).collect(),
+        }
+    }
+
+    /// The checked in contracts and fixtures are what the generator produces.
+    /// Run with `UPDATE_SOLIDITY_FIXTURES=1` to regenerate them.
+    #[test]
+    fn test_contracts_match_generator() {
+        let outputs = [
+            (
+                "contracts/src/AlphabeticMerkleVerifier.sol",
+                ALPHABETIC_VERIFIER,
+                generate_solidity_verifier("AlphabeticMerkleVerifier", TreeOptions::default()),
+            ),
+            (
+                "contracts/src/StandardMerkleVerifier.sol",
+                STANDARD_VERIFIER,
+                generate_solidity_verifier("StandardMerkleVerifier", TreeOptions::standard()),
+            ),
+            (
+                "contracts/test/fixtures/alphabetic.json",
+                ALPHABETIC_VECTORS,
+                generate_test_vectors(
+                    &fixed_accounts(),
+                    &absent_accounts(),
+                    TreeOptions::default(),
+                )
+                .unwrap()
+                    + "\n",
+            ),
+            (
+                "contracts/test/fixtures/standard.json",
+                STANDARD_VECTORS,
+                generate_test_vectors(
+                    &fixed_accounts(),
+                    &absent_accounts(),
+                    TreeOptions::standard(),
+                )
+                .unwrap()
+                    + "\n",
+            ),
+        ];
+        for (path, checked_in, generated) in outputs {
+            if std::env::var_os("UPDATE_SOLIDITY_FIXTURES").is_some() {
+                std::fs::write(
+                    std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(path),
+                    &generated,
+                )
+                .unwrap();
+            } else {
+                assert_eq!(checked_in, generated, "{} is out of date", path);
+            }
+        }
+    }
+
+    #[test]
+    fn test_alphabetic_vectors_verify() {
+        let test_vectors: TestVectors = serde_json::from_str(ALPHABETIC_VECTORS).unwrap();
+        let root = test_vectors.root.to_fixed_bytes();
+        let options = TreeOptions::default();
+
+        assert_eq!(test_vectors.inclusion.len(), test_vectors.leaf_count);
+        for (leaf, leaf_hash) in test_vectors
+            .inclusion
+            .iter()
+            .zip(test_vectors.leaves.iter())
+        {
+            let proof = leaf_proof(leaf);
+            assert_eq!(
+                proof.account.leaf_hash(options.leaf_encoding),
+                leaf_hash.to_fixed_bytes()
+            );
+            assert!(verify_inclusion_with_options(
+                options,
+                root,
+                &proof.account,
+                &proof.proof,
+                proof.index,
+                test_vectors.leaf_count
+            )
+            .unwrap());
+        }
+
+        assert_eq!(test_vectors.absence.len(), 3);
+        for absence in test_vectors.absence.iter() {
+            let proof = AbsenceProof {
+                left: absence.left.as_ref().map(leaf_proof),
+                right: absence.right.as_ref().map(leaf_proof),
+                leaf_count: test_vectors.leaf_count,
+                hash_mode: test_vectors.hash_mode,
+                leaf_encoding: test_vectors.leaf_encoding,
+            };
+            let account = AccountWithBalance {
+                address: absence.account,
+                balance: absence.balance,
+            };
+            assert!(proof.verify(root, &account).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_standard_vectors_verify() {
+        let test_vectors: TestVectors = serde_json::from_str(STANDARD_VECTORS).unwrap();
+        assert!(test_vectors.absence.is_empty());
+
+        for (leaf, leaf_hash) in test_vectors
+            .inclusion
+            .iter()
+            .zip(test_vectors.leaves.iter())
+        {
+            let proof: Vec<[u8; 32]> = leaf.proof.iter().map(|x| x.to_fixed_bytes()).collect();
+            assert!(verify_proof(
+                test_vectors.root.to_fixed_bytes(),
+                leaf_hash.to_fixed_bytes(),
+                &proof
+            ));
+        }
+    }
+
+    #[test]
+    fn test_verifier_matches_options() {
+        let standard = generate_solidity_verifier("Verifier", TreeOptions::standard());
+        assert!(standard.contains("library Verifier {"));
+        assert!(standard.contains("abi.encode(account, balance)"));
+        assert!(!standard.contains("function verifyAbsence"));
+
+        let packed = generate_solidity_verifier("Verifier", TreeOptions::default());
+        assert!(packed.contains("function verifyAbsence"));
+        assert!(packed.contains("function verifyAddressAbsence"));
+        assert!(!packed.contains("abi.encode(account, balance)"));
+    }
+}
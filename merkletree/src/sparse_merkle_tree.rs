@@ -0,0 +1,467 @@
+use std::collections::{BTreeMap, HashMap};
+use std::marker::PhantomData;
+
+use ethers::types::{Address, U256};
+use serde::{Deserialize, Serialize};
+
+use crate::account_with_balance::{AccountWithBalance, LeafEncoding};
+use crate::duplicates::duplicates_error;
+use crate::hasher::{Keccak256, MerkleHasher};
+use crate::merkle_tree::Error;
+
+/// One level per bit of an address
+pub const SPARSE_TREE_DEPTH: usize = 160;
+
+/// Hash of an empty leaf
+const EMPTY_LEAF: [u8; 32] = [0u8; 32];
+
+/// Roots of empty subtrees of every height, from an empty leaf up to the root
+/// of an empty tree
+fn default_nodes<H: MerkleHasher>() -> Vec<[u8; 32]> {
+    let mut nodes = Vec::with_capacity(SPARSE_TREE_DEPTH + 1);
+    nodes.push(EMPTY_LEAF);
+    for height in 0..SPARSE_TREE_DEPTH {
+        nodes.push(H::hash_pair(&nodes[height], &nodes[height]));
+    }
+    nodes
+}
+
+fn leaf_position(address: Address) -> U256 {
+    U256::from_big_endian(address.as_bytes())
+}
+
+/// Parent of a node at `position`, which is a right child if its lowest bit is set
+fn parent_node<H: MerkleHasher>(position: U256, node: &[u8; 32], sibling: &[u8; 32]) -> [u8; 32] {
+    if position.bit(0) {
+        H::hash_pair(sibling, node)
+    } else {
+        H::hash_pair(node, sibling)
+    }
+}
+
+/// Proof of what the leaf of an address holds in a [`SparseMerkleTree`]: an
+/// account, or nothing when the address is not in the tree
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SparseMerkleProof {
+    pub address: Address,
+    /// Account stored at the address, `None` for an empty leaf
+    pub leaf: Option<AccountWithBalance>,
+    /// Bit `h` is set when the sibling at height `h` is not an empty subtree
+    pub bitmap: [u8; SPARSE_TREE_DEPTH / 8],
+    /// Siblings that are not empty subtrees, from the leaf up
+    pub siblings: Vec<[u8; 32]>,
+}
+
+impl SparseMerkleProof {
+    fn has_sibling(&self, height: usize) -> bool {
+        self.bitmap[height / 8] & (1 << (height % 8)) != 0
+    }
+
+    /// Checks the content of the leaf against the root
+    pub fn verify(&self, root: [u8; 32]) -> Result<bool, Error> {
+        self.verify_with_hasher::<Keccak256>(root)
+    }
+
+    pub fn verify_with_hasher<H: MerkleHasher>(&self, root: [u8; 32]) -> Result<bool, Error> {
+        let expected_siblings: u32 = self.bitmap.iter().map(|x| x.count_ones()).sum();
+        if self.siblings.len() != expected_siblings as usize {
+            return Err(Error::MerkleProofParsingError);
+        }
+        let mut node = match &self.leaf {
+            Some(account) if account.address != self.address => {
+                return Err(Error::MerkleProofParsingError)
+            }
+            Some(account) => account.leaf_hash_with_hasher::<H>(LeafEncoding::Packed),
+            None => EMPTY_LEAF,
+        };
+
+        let mut position = leaf_position(self.address);
+        let mut default_node = EMPTY_LEAF;
+        let mut siblings = self.siblings.iter();
+        for height in 0..SPARSE_TREE_DEPTH {
+            let sibling = if self.has_sibling(height) {
+                *siblings.next().expect("sibling count matches the bitmap")
+            } else {
+                default_node
+            };
+            node = parent_node::<H>(position, &node, &sibling);
+            default_node = H::hash_pair(&default_node, &default_node);
+            position >>= 1;
+        }
+        Ok(node == root)
+    }
+
+    pub fn verify_inclusion(
+        &self,
+        root: [u8; 32],
+        account: &AccountWithBalance,
+    ) -> Result<bool, Error> {
+        self.verify_inclusion_with_hasher::<Keccak256>(root, account)
+    }
+
+    pub fn verify_inclusion_with_hasher<H: MerkleHasher>(
+        &self,
+        root: [u8; 32],
+        account: &AccountWithBalance,
+    ) -> Result<bool, Error> {
+        Ok(self.leaf.as_ref() == Some(account) && self.verify_with_hasher::<H>(root)?)
+    }
+
+    /// Checks that the account is not in the tree: its address has an empty
+    /// leaf or a different balance
+    pub fn verify_absence(
+        &self,
+        root: [u8; 32],
+        account: &AccountWithBalance,
+    ) -> Result<bool, Error> {
+        self.verify_absence_with_hasher::<Keccak256>(root, account)
+    }
+
+    pub fn verify_absence_with_hasher<H: MerkleHasher>(
+        &self,
+        root: [u8; 32],
+        account: &AccountWithBalance,
+    ) -> Result<bool, Error> {
+        Ok(self.address == account.address
+            && self.leaf.as_ref() != Some(account)
+            && self.verify_with_hasher::<H>(root)?)
+    }
+}
+
+/// Merkle tree with one leaf for every possible address, most of them empty.
+///
+/// The leaf of an address is at the position given by its 160 bits, so absence
+/// is proven by the empty leaf itself instead of by sorted neighbours, and the
+/// empty tree is valid. Only nodes that differ from an empty subtree are stored.
+#[derive(Debug, Clone)]
+pub struct SparseMerkleTree<H = Keccak256> {
+    accounts: BTreeMap<Address, U256>,
+    /// Nodes that are not empty subtrees by height and position, leaves first
+    nodes: Vec<HashMap<U256, [u8; 32]>>,
+    default_nodes: Vec<[u8; 32]>,
+    hasher: PhantomData<H>,
+}
+
+impl SparseMerkleTree {
+    pub fn new(accounts: &[AccountWithBalance]) -> Result<Self, Error> {
+        Self::with_hasher(accounts)
+    }
+}
+
+impl<H: MerkleHasher> SparseMerkleTree<H> {
+    pub fn with_hasher(accounts: &[AccountWithBalance]) -> Result<Self, Error> {
+        let mut merkle_tree = Self {
+            accounts: BTreeMap::new(),
+            nodes: vec![HashMap::new(); SPARSE_TREE_DEPTH + 1],
+            default_nodes: default_nodes::<H>(),
+            hasher: PhantomData,
+        };
+        for account in accounts {
+            if merkle_tree.accounts.contains_key(&account.address) {
+                return Err(duplicates_error(accounts));
+            }
+            merkle_tree.set_leaf(account.address, Some(account.balance));
+        }
+        Ok(merkle_tree)
+    }
+
+    fn node(&self, height: usize, position: U256) -> [u8; 32] {
+        self.nodes[height]
+            .get(&position)
+            .copied()
+            .unwrap_or(self.default_nodes[height])
+    }
+
+    /// Stores the leaf of an address and recomputes its path to the root
+    fn set_leaf(&mut self, address: Address, balance: Option<U256>) {
+        let mut node = match balance {
+            Some(balance) => {
+                self.accounts.insert(address, balance);
+                AccountWithBalance { address, balance }
+                    .leaf_hash_with_hasher::<H>(LeafEncoding::Packed)
+            }
+            None => {
+                self.accounts.remove(&address);
+                EMPTY_LEAF
+            }
+        };
+
+        let mut position = leaf_position(address);
+        for height in 0..=SPARSE_TREE_DEPTH {
+            if node == self.default_nodes[height] {
+                self.nodes[height].remove(&position);
+            } else {
+                self.nodes[height].insert(position, node);
+            }
+            if height == SPARSE_TREE_DEPTH {
+                break;
+            }
+            let sibling = self.node(height, position ^ U256::one());
+            node = parent_node::<H>(position, &node, &sibling);
+            position >>= 1;
+        }
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        self.node(SPARSE_TREE_DEPTH, U256::zero())
+    }
+
+    pub fn len(&self) -> usize {
+        self.accounts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+    }
+
+    pub fn balance_of(&self, address: Address) -> Option<U256> {
+        self.accounts.get(&address).copied()
+    }
+
+    /// Accounts ordered by address
+    pub fn accounts(&self) -> impl Iterator<Item = AccountWithBalance> + '_ {
+        self.accounts
+            .iter()
+            .map(|(address, balance)| AccountWithBalance {
+                address: *address,
+                balance: *balance,
+            })
+    }
+
+    /// Proof of the leaf of an address, whether it is empty or not
+    pub fn generate_proof(&self, address: Address) -> SparseMerkleProof {
+        let mut bitmap = [0u8; SPARSE_TREE_DEPTH / 8];
+        let mut siblings = vec![];
+        let mut position = leaf_position(address);
+        for height in 0..SPARSE_TREE_DEPTH {
+            if let Some(sibling) = self.nodes[height].get(&(position ^ U256::one())) {
+                bitmap[height / 8] |= 1 << (height % 8);
+                siblings.push(*sibling);
+            }
+            position >>= 1;
+        }
+
+        SparseMerkleProof {
+            address,
+            leaf: self
+                .balance_of(address)
+                .map(|balance| AccountWithBalance { address, balance }),
+            bitmap,
+            siblings,
+        }
+    }
+
+    pub fn generate_proof_of_inclusion(
+        &self,
+        account: &AccountWithBalance,
+    ) -> Result<SparseMerkleProof, Error> {
+        if self.balance_of(account.address) != Some(account.balance) {
+            return Err(Error::AccountNotFoundError);
+        }
+        Ok(self.generate_proof(account.address))
+    }
+
+    pub fn generate_proof_of_absense(
+        &self,
+        account: &AccountWithBalance,
+    ) -> Result<SparseMerkleProof, Error> {
+        if self.balance_of(account.address) == Some(account.balance) {
+            return Err(Error::AccountAlreadyExistsError);
+        }
+        Ok(self.generate_proof(account.address))
+    }
+
+    pub fn insert(&mut self, account: AccountWithBalance) -> Result<(), Error> {
+        if self.accounts.contains_key(&account.address) {
+            return Err(Error::AccountAlreadyExistsError);
+        }
+        self.set_leaf(account.address, Some(account.balance));
+        Ok(())
+    }
+
+    pub fn update_balance(&mut self, address: Address, balance: U256) -> Result<(), Error> {
+        if !self.accounts.contains_key(&address) {
+            return Err(Error::AccountNotFoundError);
+        }
+        self.set_leaf(address, Some(balance));
+        Ok(())
+    }
+
+    pub fn remove(&mut self, address: Address) -> Result<AccountWithBalance, Error> {
+        let balance = self
+            .balance_of(address)
+            .ok_or(Error::AccountNotFoundError)?;
+        self.set_leaf(address, None);
+        Ok(AccountWithBalance { address, balance })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_utils::{fixed_accounts, random_accounts};
+
+    fn absent_addresses() -> Vec<Address> {
+        [
+            "0000000000000000000000000000000000000000",
+            "A7A93fd0a276fc1C0197a5B5623eD117786eeD07",
+            "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF",
+        ]
+        .iter()
+        .map(|x| x.parse().unwrap())
+        .collect()
+    }
+
+    #[test]
+    fn test_empty_tree() {
+        let tree = SparseMerkleTree::new(&[]).unwrap();
+        assert!(tree.is_empty());
+        assert_eq!(tree.root(), default_nodes::<Keccak256>()[SPARSE_TREE_DEPTH]);
+
+        let account = fixed_accounts()[0];
+        let proof = tree.generate_proof_of_absense(&account).unwrap();
+        assert!(proof.siblings.is_empty());
+        assert!(proof.verify_absence(tree.root(), &account).unwrap());
+    }
+
+    #[test]
+    fn test_single_leaf_root() {
+        let account = fixed_accounts()[0];
+        let tree = SparseMerkleTree::new(&[account]).unwrap();
+
+        let defaults = default_nodes::<Keccak256>();
+        let address_bits = leaf_position(account.address);
+        let mut node = account.generate_hash();
+        for (height, default_node) in defaults.iter().enumerate().take(SPARSE_TREE_DEPTH) {
+            node = if address_bits.bit(height) {
+                Keccak256::hash_pair(default_node, &node)
+            } else {
+                Keccak256::hash_pair(&node, default_node)
+            };
+        }
+        assert_eq!(tree.root(), node);
+    }
+
+    #[test]
+    fn test_proof_of_inclusion() {
+        let accounts = random_accounts(50, 3);
+        let tree = SparseMerkleTree::new(&accounts).unwrap();
+        assert_eq!(tree.accounts().collect::<Vec<_>>(), accounts);
+
+        for account in accounts.iter() {
+            let proof = tree.generate_proof_of_inclusion(account).unwrap();
+            assert!(proof.verify_inclusion(tree.root(), account).unwrap());
+            assert!(!proof.verify_absence(tree.root(), account).unwrap());
+        }
+
+        let other_balance = AccountWithBalance {
+            balance: accounts[0].balance + 1,
+            ..accounts[0]
+        };
+        assert!(matches!(
+            tree.generate_proof_of_inclusion(&other_balance),
+            Err(Error::AccountNotFoundError)
+        ));
+    }
+
+    #[test]
+    fn test_proof_of_absence() {
+        let accounts = fixed_accounts();
+        let tree = SparseMerkleTree::new(&accounts).unwrap();
+
+        for address in absent_addresses() {
+            let account = AccountWithBalance {
+                address,
+                balance: U256::one(),
+            };
+            let proof = tree.generate_proof_of_absense(&account).unwrap();
+            assert_eq!(proof.leaf, None);
+            assert!(proof.verify_absence(tree.root(), &account).unwrap());
+            assert!(!proof.verify_inclusion(tree.root(), &account).unwrap());
+        }
+
+        // An address holding another balance proves the account is absent too
+        let other_balance = AccountWithBalance {
+            balance: U256::from(4),
+            ..accounts[2]
+        };
+        let proof = tree.generate_proof_of_absense(&other_balance).unwrap();
+        assert_eq!(proof.leaf, Some(accounts[2]));
+        assert!(proof.verify_absence(tree.root(), &other_balance).unwrap());
+
+        assert!(matches!(
+            tree.generate_proof_of_absense(&accounts[2]),
+            Err(Error::AccountAlreadyExistsError)
+        ));
+    }
+
+    #[test]
+    fn test_tampered_proof() {
+        let accounts = fixed_accounts();
+        let tree = SparseMerkleTree::new(&accounts).unwrap();
+        let account = accounts[1];
+
+        // Claiming an included address is empty
+        let mut proof = tree.generate_proof_of_inclusion(&account).unwrap();
+        proof.leaf = None;
+        assert!(!proof.verify(tree.root()).unwrap());
+
+        let mut proof = tree.generate_proof_of_inclusion(&account).unwrap();
+        proof.leaf = Some(accounts[0]);
+        assert!(matches!(
+            proof.verify(tree.root()),
+            Err(Error::MerkleProofParsingError)
+        ));
+
+        let mut proof = tree.generate_proof_of_inclusion(&account).unwrap();
+        proof.siblings.pop();
+        assert!(matches!(
+            proof.verify(tree.root()),
+            Err(Error::MerkleProofParsingError)
+        ));
+
+        let proof = tree.generate_proof_of_inclusion(&account).unwrap();
+        let json = serde_json::to_string(&proof).unwrap();
+        let proof: SparseMerkleProof = serde_json::from_str(&json).unwrap();
+        assert!(proof.verify_inclusion(tree.root(), &account).unwrap());
+    }
+
+    #[test]
+    fn test_duplicate_address() {
+        let accounts = fixed_accounts();
+        let duplicate = AccountWithBalance {
+            balance: U256::from(7),
+            ..accounts[0]
+        };
+        let result = SparseMerkleTree::new(&[accounts[0], duplicate]);
+        assert!(matches!(result, Err(Error::DuplicateAddressError(_))));
+    }
+
+    #[test]
+    fn test_mutations_match_rebuild() {
+        let accounts = random_accounts(30, 5);
+        let mut tree = SparseMerkleTree::new(&accounts[..20]).unwrap();
+        for account in accounts[20..].iter() {
+            tree.insert(*account).unwrap();
+        }
+        tree.update_balance(accounts[3].address, U256::from(9))
+            .unwrap();
+        tree.remove(accounts[7].address).unwrap();
+
+        let mut expected = accounts.clone();
+        expected[3].balance = U256::from(9);
+        expected.remove(7);
+        assert_eq!(
+            tree.root(),
+            SparseMerkleTree::new(&expected).unwrap().root()
+        );
+
+        // Removing every leaf gives back the empty tree with no stored nodes
+        for account in expected.iter() {
+            tree.remove(account.address).unwrap();
+        }
+        assert_eq!(tree.root(), SparseMerkleTree::new(&[]).unwrap().root());
+        assert!(tree.nodes.iter().all(|level| level.is_empty()));
+    }
+}
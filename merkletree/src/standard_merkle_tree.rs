@@ -0,0 +1,358 @@
+use ethers::types::{Address, H256, U256};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::account_with_balance::{parse_balance, AccountWithBalance, LeafEncoding};
+use crate::alphabetic_merkle_tree::{AlphabeticMerkleTree, OrderingMode, TreeOptions};
+use crate::merkle_tree::Error;
+
+pub const STANDARD_TREE_FORMAT: &str = "standard-v1";
+pub const STANDARD_LEAF_ENCODING: [&str; 2] = ["address", "uint256"];
+
+/// Tree in the JSON format of `StandardMerkleTree.dump()` from
+/// `@openzeppelin/merkle-tree`
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StandardTreeDump {
+    pub format: String,
+    pub tree: Vec<H256>,
+    pub values: Vec<StandardTreeValue>,
+    pub leaf_encoding: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StandardTreeValue {
+    pub value: (Address, Value),
+    pub tree_index: usize,
+}
+
+/// Balances are dumped as decimal strings, but strings and numbers of any
+/// size are read
+fn parse_value_balance(balance: &Value) -> Result<U256, Error> {
+    match balance {
+        Value::String(balance) => parse_balance(balance),
+        Value::Number(balance) => parse_balance(&balance.to_string()),
+        _ => Err(Error::InvalidTreeDumpError),
+    }
+}
+
+impl StandardTreeDump {
+    pub fn from_tree(merkle_tree: &AlphabeticMerkleTree) -> Result<Self, Error> {
+        if merkle_tree.options().leaf_encoding != LeafEncoding::Standard {
+            return Err(Error::StandardTreeRequiredError);
+        }
+        let nodes = merkle_tree
+            .commutative_nodes()
+            .ok_or(Error::StandardTreeRequiredError)?;
+
+        let values = merkle_tree
+            .accounts()
+            .iter()
+            .enumerate()
+            .map(|(index, account)| StandardTreeValue {
+                value: (account.address, Value::String(account.balance.to_string())),
+                tree_index: nodes.len() - 1 - index,
+            })
+            .collect();
+
+        Ok(Self {
+            format: STANDARD_TREE_FORMAT.to_string(),
+            tree: nodes.iter().map(|node| H256::from(*node)).collect(),
+            values,
+            leaf_encoding: STANDARD_LEAF_ENCODING.map(String::from).to_vec(),
+        })
+    }
+
+    /// Rebuilds the tree in the leaf order of the dump and checks it matches.
+    /// `StandardMerkleTree.of` sorts leaves by hash, which gives a tree with
+    /// [`OrderingMode::LeafHash`]; leaves in alphabetic order, from a JS tree
+    /// built with `{ sortLeaves: false }`, keep the default ordering.
+    pub fn to_tree(&self) -> Result<AlphabeticMerkleTree, Error> {
+        if self.format != STANDARD_TREE_FORMAT || self.leaf_encoding != STANDARD_LEAF_ENCODING {
+            return Err(Error::InvalidTreeDumpError);
+        }
+        // Values may come in any order, their leaves are at the end of the
+        // tree with the first leaf last
+        let leaf_count = self.values.len();
+        if leaf_count == 0 || self.tree.len() != 2 * leaf_count - 1 {
+            return Err(Error::InvalidTreeDumpError);
+        }
+        let mut leaves = vec![None; leaf_count];
+        for value in &self.values {
+            let index = (self.tree.len() - 1)
+                .checked_sub(value.tree_index)
+                .filter(|index| *index < leaf_count)
+                .ok_or(Error::InvalidTreeDumpError)?;
+            let account = AccountWithBalance {
+                address: value.value.0,
+                balance: parse_value_balance(&value.value.1)?,
+            };
+            if leaves[index].replace(account).is_some() {
+                return Err(Error::InvalidTreeDumpError);
+            }
+        }
+        let accounts = leaves
+            .into_iter()
+            .collect::<Option<Vec<AccountWithBalance>>>()
+            .ok_or(Error::InvalidTreeDumpError)?;
+
+        let ordering = if accounts.windows(2).all(|x| x[0] <= x[1]) {
+            OrderingMode::Packed
+        } else {
+            OrderingMode::LeafHash
+        };
+        let options = TreeOptions {
+            ordering,
+            ..TreeOptions::standard()
+        };
+        // Leaves in any other order are rebuilt in a different one
+        let merkle_tree = AlphabeticMerkleTree::with_options(&accounts, options)?;
+        let nodes = merkle_tree
+            .commutative_nodes()
+            .ok_or(Error::StandardTreeRequiredError)?;
+        if merkle_tree.accounts() != accounts
+            || nodes
+                .iter()
+                .zip(self.tree.iter())
+                .any(|(a, b)| a != b.as_fixed_bytes())
+        {
+            return Err(Error::InvalidTreeDumpError);
+        }
+
+        Ok(merkle_tree)
+    }
+}
+
+pub fn dump_standard_tree(merkle_tree: &AlphabeticMerkleTree) -> Result<String, Error> {
+    let dump = StandardTreeDump::from_tree(merkle_tree)?;
+    serde_json::to_string(&dump).map_err(|_| Error::InvalidTreeDumpError)
+}
+
+pub fn load_standard_tree(json: &str) -> Result<AlphabeticMerkleTree, Error> {
+    let dump: StandardTreeDump =
+        serde_json::from_str(json).map_err(|_| Error::InvalidTreeDumpError)?;
+    dump.to_tree()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ethers::utils::hex;
+
+    use crate::commutative_merkle_tree::{verify_proof, CommutativeMerkleTree};
+    use crate::test_utils::{fixed_accounts, random_accounts};
+
+    //Manually calculated StandardMerkleTree nodes for fixed_accounts()
+    const FIXED_TREE: [&str; 9] = [
+        "0x1a967eca92f9ca4b070969e04b13ea5c0d62b9e7a0f94e0bbe974b82026a2660",
+        "0xbffb290ceade564a1c320c99daceba2a4154c84c54bc666f2c1ec43ce7c05ecb",
+        "0x1eff2532a0ca1432729de85a87cf6947a9245dfa69f5f17b3e1e9ffa2b80fb34",
+        "0xfb000e9d089bd4b247272a1feb5911a68e2aa04b85aec45d1a723506b006085d",
+        "0x55ee7ee5f67e2e3f5cc620573aa946334ff7ee5fa8e937953b44c6f9fd028a8a",
+        "0xa618bae305713d30eb4bd3b9a81de072dad117f1ef75e0851d79bdd15c1a635f",
+        "0x7ac26e0f97cc7b96fcdc4df7a3e8257205f1ddd979c76d2d4323844443a94cbe",
+        "0xba2b642cf6aaa11b28122bf1f7211a5db566feea377450f66a67ee53306e08cb",
+        "0xd3f387a0716c7071c5c6c3d9efbbaff3522ca0e0d45ce1a0c3e50f7ab7a26e9e",
+    ];
+
+    fn fixed_dump_json() -> Value {
+        serde_json::json!({
+            "format": "standard-v1",
+            "tree": FIXED_TREE,
+            "values": [
+                { "value": ["0x47ac0fb4f2d84898e4d9e7b4dab3c24507a6d503", "2"], "treeIndex": 8 },
+                { "value": ["0x5754284f345afc66a98fbb0a0afe71e0f007b949", "100"], "treeIndex": 7 },
+                { "value": ["0xa7a93fd0a276fc1c0197a5b5623ed117786eed06", "3"], "treeIndex": 6 },
+                { "value": ["0xcee284f754e854890e311e3280b767f80797180d", "10"], "treeIndex": 5 },
+                { "value": ["0xf977814e90da44bfa03b6295a0616a897441acec", "1"], "treeIndex": 4 },
+            ],
+            "leafEncoding": ["address", "uint256"],
+        })
+    }
+
+    #[test]
+    fn test_standard_leaf_hash() {
+        let account = fixed_accounts()[0];
+        assert_eq!(
+            hex::encode(account.generate_standard_hash()),
+            FIXED_TREE[8].trim_start_matches("0x")
+        );
+    }
+
+    #[test]
+    fn test_dump_standard_tree() {
+        let merkle_tree =
+            AlphabeticMerkleTree::with_options(&fixed_accounts(), TreeOptions::standard()).unwrap();
+
+        let json: Value = serde_json::from_str(&dump_standard_tree(&merkle_tree).unwrap()).unwrap();
+        assert_eq!(json, fixed_dump_json());
+
+        for (index, account) in merkle_tree.accounts().iter().enumerate() {
+            let proof = merkle_tree.generate_proof_hashes(account).unwrap();
+            let leaf = merkle_tree.commutative_nodes().unwrap()[8 - index];
+            assert!(verify_proof(merkle_tree.root(), leaf, &proof));
+        }
+    }
+
+    #[test]
+    fn test_dump_requires_standard_tree() {
+        let merkle_tree = AlphabeticMerkleTree::new(&fixed_accounts()).unwrap();
+        assert!(matches!(
+            dump_standard_tree(&merkle_tree),
+            Err(Error::StandardTreeRequiredError)
+        ));
+    }
+
+    #[test]
+    fn test_load_standard_tree() {
+        let mut json = fixed_dump_json();
+        // Checksummed addresses and numeric amounts are accepted as well
+        json["values"][0]["value"] =
+            serde_json::json!(["0x47ac0Fb4F2D84898e4D9E7b4DaB3C24507a6D503", 2]);
+        json["values"][1]["value"][1] = serde_json::json!("0x64");
+
+        let merkle_tree = load_standard_tree(&json.to_string()).unwrap();
+        assert_eq!(merkle_tree.options(), TreeOptions::standard());
+        assert_eq!(merkle_tree.accounts(), &fixed_accounts()[..]);
+        assert_eq!(
+            format!("0x{}", hex::encode(merkle_tree.root())),
+            FIXED_TREE[0]
+        );
+    }
+
+    #[test]
+    fn test_load_large_numeric_balance() {
+        let mut accounts = fixed_accounts();
+        accounts[2].balance = U256::MAX;
+        let merkle_tree =
+            AlphabeticMerkleTree::with_options(&accounts, TreeOptions::standard()).unwrap();
+        // Balances past u64 written as JSON numbers, like JS tools do with bigints
+        let json = dump_standard_tree(&merkle_tree)
+            .unwrap()
+            .replace(&format!("\"{}\"", U256::MAX), &U256::MAX.to_string());
+
+        let loaded = load_standard_tree(&json).unwrap();
+        assert_eq!(loaded.root(), merkle_tree.root());
+    }
+
+    #[test]
+    fn test_load_tampered_standard_tree() {
+        let mut json = fixed_dump_json();
+        json["tree"][3] = serde_json::json!(FIXED_TREE[4]);
+        let result = load_standard_tree(&json.to_string());
+        assert!(matches!(result, Err(Error::InvalidTreeDumpError)));
+
+        let mut json = fixed_dump_json();
+        json["values"][2]["value"][1] = serde_json::json!("4");
+        let result = load_standard_tree(&json.to_string());
+        assert!(matches!(result, Err(Error::InvalidTreeDumpError)));
+
+        let mut json = fixed_dump_json();
+        json["format"] = serde_json::json!("simple-v1");
+        let result = load_standard_tree(&json.to_string());
+        assert!(matches!(result, Err(Error::InvalidTreeDumpError)));
+    }
+
+    /// Dump of `StandardMerkleTree.of(values, ["address", "uint256"])`, which
+    /// sorts leaves by hash and lists values in input order
+    fn default_js_dump(values: &[AccountWithBalance]) -> StandardTreeDump {
+        let mut leaves: Vec<[u8; 32]> = values.iter().map(|x| x.generate_standard_hash()).collect();
+        leaves.sort();
+        let nodes = CommutativeMerkleTree::from_leaves(&leaves).unwrap();
+        let tree_len = nodes.nodes().len();
+
+        StandardTreeDump {
+            format: STANDARD_TREE_FORMAT.to_string(),
+            tree: nodes.nodes().iter().map(|x| H256::from(*x)).collect(),
+            values: values
+                .iter()
+                .map(|account| {
+                    let leaf = account.generate_standard_hash();
+                    StandardTreeValue {
+                        value: (account.address, Value::String(account.balance.to_string())),
+                        tree_index: tree_len - 1 - leaves.binary_search(&leaf).unwrap(),
+                    }
+                })
+                .collect(),
+            leaf_encoding: STANDARD_LEAF_ENCODING.map(String::from).to_vec(),
+        }
+    }
+
+    /// `getProof` of `StandardMerkleTree`: the siblings up the tree array
+    fn js_proof(tree: &[H256], mut tree_index: usize) -> Vec<[u8; 32]> {
+        let mut proof = vec![];
+        while tree_index > 0 {
+            let sibling = if tree_index.is_multiple_of(2) {
+                tree_index - 1
+            } else {
+                tree_index + 1
+            };
+            proof.push(tree[sibling].to_fixed_bytes());
+            tree_index = (tree_index - 1) / 2;
+        }
+        proof
+    }
+
+    #[test]
+    fn test_load_default_js_dump() {
+        for count in [1, 2, 5, 13] {
+            let values = random_accounts(count, count as u64);
+            let dump = default_js_dump(&values);
+            let json = serde_json::to_string(&dump).unwrap();
+
+            let merkle_tree = load_standard_tree(&json).unwrap();
+            assert_eq!(merkle_tree.root(), dump.tree[0].to_fixed_bytes());
+            if count > 2 {
+                assert_eq!(merkle_tree.options().ordering, OrderingMode::LeafHash);
+            }
+            for value in &dump.values {
+                let account = AccountWithBalance {
+                    address: value.value.0,
+                    balance: parse_value_balance(&value.value.1).unwrap(),
+                };
+                assert_eq!(
+                    merkle_tree.generate_proof_hashes(&account).unwrap(),
+                    js_proof(&dump.tree, value.tree_index)
+                );
+            }
+
+            // Dumping the loaded tree gives the same nodes
+            let dumped = StandardTreeDump::from_tree(&merkle_tree).unwrap();
+            assert_eq!(dumped.tree, dump.tree);
+        }
+
+        // Values swapped between leaves don't match the nodes
+        let mut dump = default_js_dump(&fixed_accounts());
+        let tree_index = dump.values[0].tree_index;
+        dump.values[0].tree_index = dump.values[1].tree_index;
+        dump.values[1].tree_index = tree_index;
+        assert!(matches!(dump.to_tree(), Err(Error::InvalidTreeDumpError)));
+    }
+
+    #[test]
+    fn test_leaves_sorted_by_hash_move_on_update() {
+        let values = random_accounts(13, 7);
+        let mut merkle_tree = default_js_dump(&values).to_tree().unwrap();
+
+        let mut updated = values.clone();
+        updated[3].balance += U256::from(1);
+        merkle_tree
+            .update_balance(updated[3].address, updated[3].balance)
+            .unwrap();
+        updated.push(random_accounts(1, 100)[0]);
+        merkle_tree.insert(updated[13]).unwrap();
+        updated.remove(5);
+        merkle_tree.remove(values[5].address).unwrap();
+
+        let rebuilt = default_js_dump(&updated).to_tree().unwrap();
+        assert_eq!(merkle_tree.accounts(), rebuilt.accounts());
+        assert_eq!(merkle_tree.root(), rebuilt.root());
+
+        assert!(matches!(
+            merkle_tree.generate_proof_of_absense(&random_accounts(1, 101)[0]),
+            Err(Error::AccountOrderingRequiredError)
+        ));
+    }
+}
@@ -0,0 +1,354 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+
+use crate::account_with_balance::{AccountWithBalance, SortKey, ACCOUNT_RECORD_LEN};
+use crate::alphabetic_merkle_tree::{HashMode, OrderingMode, TreeOptions};
+use crate::duplicates::{Duplicate, DuplicatePolicy};
+use crate::hasher::{Keccak256, MerkleHasher};
+use crate::merkle_tree::Error;
+use crate::parallel;
+
+/// Accounts sorted in memory before being written to a temporary file
+pub const DEFAULT_CHUNK_SIZE: usize = 1 << 20;
+
+/// Nodes waiting for their right sibling, one per layer, so hashing a layer
+/// needs memory proportional to the depth of the tree
+struct LayerStack<H> {
+    pending: Vec<Option<[u8; 32]>>,
+    hasher: PhantomData<H>,
+}
+
+impl<H: MerkleHasher> LayerStack<H> {
+    fn new() -> Self {
+        Self {
+            pending: vec![],
+            hasher: PhantomData,
+        }
+    }
+
+    fn push(&mut self, leaf: [u8; 32]) {
+        let mut node = leaf;
+        for pending in self.pending.iter_mut() {
+            match pending.take() {
+                Some(left) => node = H::hash_pair(&left, &node),
+                None => {
+                    *pending = Some(node);
+                    return;
+                }
+            }
+        }
+        self.pending.push(Some(node));
+    }
+
+    /// Root of the leaves pushed so far. Pending nodes are the rightmost of
+    /// their layer, so a lone one is promoted like the last node of an uneven
+    /// layer.
+    fn root(&self) -> Option<[u8; 32]> {
+        self.pending
+            .iter()
+            .flatten()
+            .fold(None, |right, left| match right {
+                Some(right) => Some(H::hash_pair(left, &right)),
+                None => Some(*left),
+            })
+    }
+}
+
+/// Root of a tree built by a [`StreamingTreeBuilder`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamedTree {
+    pub root: [u8; 32],
+    pub leaf_count: usize,
+}
+
+/// Builds the root of a tree over more accounts than fit in memory.
+///
+/// Accounts are sorted in chunks of `chunk_size` that are written to temporary
+/// files, then merged and hashed in a single pass. Besides the chunk being
+/// sorted, memory holds one buffered reader per chunk and one node per layer.
+/// Gives the same root as [`crate::alphabetic_merkle_tree::AlphabeticMerkleTree`]
+/// with ordered hashing.
+pub struct StreamingTreeBuilder<H: MerkleHasher = Keccak256> {
+    options: TreeOptions,
+    chunk_size: usize,
+    chunk: Vec<AccountWithBalance>,
+    runs: Vec<File>,
+    hasher: PhantomData<H>,
+}
+
+impl StreamingTreeBuilder {
+    pub fn new(options: TreeOptions, chunk_size: usize) -> Result<Self, Error> {
+        Self::with_hasher(options, chunk_size)
+    }
+}
+
+impl<H: MerkleHasher> StreamingTreeBuilder<H> {
+    /// Fails with commutative hashing, whose layout needs the leaf count
+    /// before the first node can be hashed, with leaves sorted by hash, and
+    /// with duplicate policies other than `Allow`, which need the input
+    /// positions of the accounts
+    pub fn with_hasher(options: TreeOptions, chunk_size: usize) -> Result<Self, Error> {
+        if options.hash_mode != HashMode::Ordered {
+            return Err(Error::OrderedHashingRequiredError);
+        }
+        if options.ordering == OrderingMode::LeafHash {
+            return Err(Error::AccountOrderingRequiredError);
+        }
+        if options.duplicate_policy != DuplicatePolicy::Allow {
+            return Err(Error::UnsupportedDuplicatePolicyError);
+        }
+        Ok(Self {
+            options,
+            chunk_size: chunk_size.max(1),
+            chunk: Vec::with_capacity(chunk_size.clamp(1, DEFAULT_CHUNK_SIZE)),
+            runs: vec![],
+            hasher: PhantomData,
+        })
+    }
+
+    pub fn push(&mut self, account: AccountWithBalance) -> Result<(), Error> {
+        self.chunk.push(account);
+        if self.chunk.len() >= self.chunk_size {
+            self.write_run()?;
+        }
+        Ok(())
+    }
+
+    pub fn extend(
+        &mut self,
+        accounts: impl IntoIterator<Item = AccountWithBalance>,
+    ) -> Result<(), Error> {
+        for account in accounts {
+            self.push(account)?;
+        }
+        Ok(())
+    }
+
+    /// Reads `address,balance` lines with decimal or `0x` hex balances. A first line
+    /// starting with `address` is taken as a header and empty lines are skipped.
+    pub fn extend_from_csv(&mut self, reader: impl BufRead) -> Result<(), Error> {
+        for (line_index, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || (line_index == 0 && line.to_lowercase().starts_with("address")) {
+                continue;
+            }
+            let account = line
+                .parse::<AccountWithBalance>()
+                .map_err(|error| Error::InvalidAccountRowError(line_index + 1, Box::new(error)))?;
+            self.push(account)?;
+        }
+        Ok(())
+    }
+
+    fn sort_chunk(&mut self) {
+        let chunk = std::mem::take(&mut self.chunk);
+        let mut keyed_accounts = parallel::map(&chunk, |x| (x.sort_key(), *x));
+        drop(chunk);
+        parallel::sort_unstable_by(&mut keyed_accounts, |a, b| a.0.cmp(&b.0));
+        self.chunk = keyed_accounts.into_iter().map(|x| x.1).collect();
+    }
+
+    fn write_run(&mut self) -> Result<(), Error> {
+        self.sort_chunk();
+        let mut writer = BufWriter::new(tempfile::tempfile()?);
+        for account in self.chunk.drain(..) {
+            writer.write_all(&account.to_record())?;
+        }
+        let mut file = writer.into_inner().map_err(|x| x.into_error())?;
+        file.seek(SeekFrom::Start(0))?;
+        self.runs.push(file);
+        Ok(())
+    }
+
+    /// Merges the sorted chunks and hashes the leaves in order
+    pub fn finish(mut self) -> Result<StreamedTree, Error> {
+        self.sort_chunk();
+        let mut sources: Vec<Box<dyn Iterator<Item = Result<AccountWithBalance, Error>>>> = self
+            .runs
+            .drain(..)
+            .map(|file| Box::new(RunReader::new(file)) as Box<dyn Iterator<Item = _>>)
+            .collect();
+        sources.push(Box::new(
+            std::mem::take(&mut self.chunk).into_iter().map(Ok),
+        ));
+
+        let mut heap: BinaryHeap<Reverse<(SortKey, usize, AccountWithBalance)>> =
+            BinaryHeap::with_capacity(sources.len());
+        for (source_index, source) in sources.iter_mut().enumerate() {
+            if let Some(account) = source.next().transpose()? {
+                heap.push(Reverse((account.sort_key(), source_index, account)));
+            }
+        }
+
+        let mut layers = LayerStack::<H>::new();
+        let mut leaf_count = 0;
+        let mut previous: Option<AccountWithBalance> = None;
+        while let Some(Reverse((_, source_index, account))) = heap.pop() {
+            if self.options.ordering == OrderingMode::Address
+                && previous.is_some_and(|x| x.address == account.address)
+            {
+                // The input order is lost in the sorted runs, so positions
+                // are leaf indices
+                return Err(Error::DuplicateAddressError(vec![Duplicate {
+                    key: account.address,
+                    positions: vec![leaf_count - 1, leaf_count],
+                }
+                .boxed()]));
+            }
+            layers.push(account.leaf_hash_with_hasher::<H>(self.options.leaf_encoding));
+            leaf_count += 1;
+            previous = Some(account);
+
+            if let Some(next) = sources[source_index].next().transpose()? {
+                heap.push(Reverse((next.sort_key(), source_index, next)));
+            }
+        }
+
+        Ok(StreamedTree {
+            root: layers.root().ok_or(Error::EmptyAccountsListError)?,
+            leaf_count,
+        })
+    }
+}
+
+/// Accounts of a sorted run, read back one record at a time
+struct RunReader {
+    reader: BufReader<File>,
+}
+
+impl RunReader {
+    fn new(file: File) -> Self {
+        Self {
+            reader: BufReader::new(file),
+        }
+    }
+}
+
+impl Iterator for RunReader {
+    type Item = Result<AccountWithBalance, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut record = [0u8; ACCOUNT_RECORD_LEN];
+        match self.reader.read_exact(&mut record) {
+            Ok(()) => Some(Ok(AccountWithBalance::from_record(&record))),
+            Err(error) if error.kind() == std::io::ErrorKind::UnexpectedEof => None,
+            Err(error) => Some(Err(error.into())),
+        }
+    }
+}
+
+/// Root of the tree of the accounts, sorting them in chunks of
+/// [`DEFAULT_CHUNK_SIZE`] accounts
+pub fn get_streamed_merkle_root(
+    accounts: impl IntoIterator<Item = AccountWithBalance>,
+    options: TreeOptions,
+) -> Result<StreamedTree, Error> {
+    let mut builder = StreamingTreeBuilder::new(options, DEFAULT_CHUNK_SIZE)?;
+    builder.extend(accounts)?;
+    builder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ethers::types::U256;
+
+    use crate::account_with_balance::LeafEncoding;
+    use crate::alphabetic_merkle_tree::AlphabeticMerkleTree;
+    use crate::test_utils::{fixed_accounts, random_accounts, shuffled};
+
+    #[test]
+    fn test_streamed_root_matches_tree() {
+        let address_options = TreeOptions {
+            ordering: OrderingMode::Address,
+            ..Default::default()
+        };
+        let standard_leaves = TreeOptions {
+            leaf_encoding: LeafEncoding::Standard,
+            ..Default::default()
+        };
+        for count in [1, 2, 3, 5, 8, 13, 64, 100] {
+            let accounts = shuffled(&random_accounts(count, count as u64), 0);
+            for options in [TreeOptions::default(), address_options, standard_leaves] {
+                let tree = AlphabeticMerkleTree::with_options(&accounts, options).unwrap();
+                for chunk_size in [1, 7, 1000] {
+                    let mut builder = StreamingTreeBuilder::new(options, chunk_size).unwrap();
+                    builder.extend(accounts.iter().copied()).unwrap();
+                    let streamed = builder.finish().unwrap();
+                    assert_eq!(streamed.root, tree.root());
+                    assert_eq!(streamed.leaf_count, count);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_streamed_root_from_csv() {
+        let csv = "address,balance\n\
+                   0xF977814e90dA44bFA03b6295A0616a897441aceC,1\n\
+                   0x47ac0Fb4F2D84898e4D9E7b4DaB3C24507a6D503, 2\n\
+                   \n\
+                   A7A93fd0a276fc1C0197a5B5623eD117786eeD06,3\n\
+                   0xcEe284F754E854890e311e3280b767F80797180d,10\n\
+                   0x5754284f345afc66a98fbB0a0Afe71e0F007B949,100\n";
+        let mut builder = StreamingTreeBuilder::new(TreeOptions::default(), 2).unwrap();
+        builder.extend_from_csv(csv.as_bytes()).unwrap();
+        let streamed = builder.finish().unwrap();
+
+        let tree = AlphabeticMerkleTree::new(&fixed_accounts()).unwrap();
+        assert_eq!(streamed.root, tree.root());
+        assert_eq!(streamed.leaf_count, 5);
+
+        let mut builder = StreamingTreeBuilder::new(TreeOptions::default(), 2).unwrap();
+        let result = builder.extend_from_csv(
+            "address,balance\n\
+             0x47ac0Fb4F2D84898e4D9E7b4DaB3C24507a6D503,1\n\
+             0x5754284f345afc66a98fbB0a0Afe71e0F007B949,x\n"
+                .as_bytes(),
+        );
+        assert!(matches!(
+            result,
+            Err(Error::InvalidAccountRowError(3, error))
+                if matches!(*error, Error::InvalidBalanceError(_))
+        ));
+    }
+
+    #[test]
+    fn test_streaming_errors() {
+        let result = StreamingTreeBuilder::new(TreeOptions::standard(), 10);
+        assert!(matches!(result, Err(Error::OrderedHashingRequiredError)));
+        let options = TreeOptions {
+            duplicate_policy: DuplicatePolicy::Sum,
+            ..Default::default()
+        };
+        let result = StreamingTreeBuilder::new(options, 10);
+        assert!(matches!(
+            result,
+            Err(Error::UnsupportedDuplicatePolicyError)
+        ));
+
+        let result = get_streamed_merkle_root(vec![], TreeOptions::default());
+        assert!(matches!(result, Err(Error::EmptyAccountsListError)));
+
+        let options = TreeOptions {
+            ordering: OrderingMode::Address,
+            ..Default::default()
+        };
+        let mut accounts = fixed_accounts();
+        accounts.push(AccountWithBalance {
+            balance: U256::from(7),
+            ..accounts[2]
+        });
+        let mut builder = StreamingTreeBuilder::new(options, 2).unwrap();
+        builder.extend(accounts).unwrap();
+        assert!(matches!(
+            builder.finish(),
+            Err(Error::DuplicateAddressError(_))
+        ));
+    }
+}
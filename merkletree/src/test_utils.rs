@@ -0,0 +1,62 @@
+use ethers::types::{Address, U256};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use crate::account_with_balance::AccountWithBalance;
+use crate::leaf::DelegatedAccount;
+
+pub fn fixed_accounts() -> Vec<AccountWithBalance> {
+    let mut accounts = [
+        AccountWithBalance::new("F977814e90dA44bFA03b6295A0616a897441aceC", "1"),
+        AccountWithBalance::new("47ac0Fb4F2D84898e4D9E7b4DaB3C24507a6D503", "2"),
+        AccountWithBalance::new("A7A93fd0a276fc1C0197a5B5623eD117786eeD06", "3"),
+        AccountWithBalance::new("cEe284F754E854890e311e3280b767F80797180d", "10"),
+        AccountWithBalance::new("5754284f345afc66a98fbB0a0Afe71e0F007B949", "100"),
+    ]
+    .to_vec();
+    accounts.sort();
+    accounts
+}
+
+/// Sorted accounts with random addresses and balances, reproducible by seed
+pub fn random_accounts(count: usize, seed: u64) -> Vec<AccountWithBalance> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut accounts: Vec<AccountWithBalance> = (0..count)
+        .map(|_| AccountWithBalance {
+            address: Address::from(rng.gen::<[u8; 20]>()),
+            balance: U256::from(rng.gen::<u64>()),
+        })
+        .collect();
+    accounts.sort();
+    accounts
+}
+
+pub fn shuffled(accounts: &[AccountWithBalance], seed: u64) -> Vec<AccountWithBalance> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut accounts = accounts.to_vec();
+    accounts.shuffle(&mut rng);
+    accounts
+}
+
+/// Random accounts delegating to random addresses
+pub fn random_delegations(count: usize, seed: u64) -> Vec<DelegatedAccount> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    random_accounts(count, seed)
+        .iter()
+        .map(|x| DelegatedAccount {
+            address: x.address,
+            balance: x.balance,
+            delegatee: Address::from(rng.gen::<[u8; 20]>()),
+        })
+        .collect()
+}
+
+/// The two children of an internal node read as a delegation with a 24-byte
+/// balance, which packed to the same 64 bytes before balances took 32 bytes
+pub fn node_as_delegation(left: &[u8; 32], right: &[u8; 32]) -> DelegatedAccount {
+    let preimage = [*left, *right].concat();
+    DelegatedAccount {
+        address: Address::from_slice(&preimage[..20]),
+        balance: U256::from_big_endian(&preimage[20..44]),
+        delegatee: Address::from_slice(&preimage[44..]),
+    }
+}
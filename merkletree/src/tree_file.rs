@@ -0,0 +1,642 @@
+//! Versioned binary file holding a built tree, so proofs can be served from a
+//! memory map without rebuilding the tree.
+//!
+//! All integers are little-endian:
+//!
+//! | bytes | content |
+//! |-------|---------|
+//! | 8 | magic `ALPHMRKL` |
+//! | 4 | format version |
+//! | 1 | hasher: [`MerkleHasher::ID`], 0 keccak256, 1 sha256, 2 blake3, 3 poseidon |
+//! | 1 | hash mode: 0 ordered, 1 commutative |
+//! | 1 | leaf encoding: 0 packed, 1 standard |
+//! | 1 | ordering: 0 packed, 1 address, 2 leaf hash |
+//! | 1 | duplicate policy: 0 allow, 1 reject, 2 sum, 3 keep max, 4 keep last |
+//! | 8 | leaf count |
+//! | 52 × leaf count | accounts in leaf order, address then 32-byte big-endian balance |
+//! | 32 × node count | every layer from the leaves up with ordered hashing, the array layout with commutative hashing |
+//! | 8 × leaf count | with leaf hash ordering only, leaf indices sorted by address |
+//! | 32 | keccak256 of everything before it |
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+
+use ethers::types::Address;
+use memmap2::Mmap;
+use tiny_keccak::{Hasher, Keccak};
+
+use crate::absence_proof::{AbsenceProof, LeafProof};
+use crate::account_with_balance::{AccountWithBalance, LeafEncoding, ACCOUNT_RECORD_LEN};
+use crate::address_proof::AddressProof;
+use crate::alphabetic_merkle_tree::{AlphabeticMerkleTree, HashMode, OrderingMode, TreeOptions};
+use crate::duplicates::DuplicatePolicy;
+use crate::hasher::{Keccak256, MerkleHasher};
+use crate::merkle_tree::Error;
+
+pub const TREE_FILE_MAGIC: [u8; 8] = *b"ALPHMRKL";
+pub const TREE_FILE_VERSION: u32 = 2;
+
+const HEADER_LEN: usize = 25;
+const CHECKSUM_LEN: usize = 32;
+
+fn encode_options(options: TreeOptions) -> [u8; 4] {
+    [
+        match options.hash_mode {
+            HashMode::Ordered => 0,
+            HashMode::Commutative => 1,
+        },
+        match options.leaf_encoding {
+            LeafEncoding::Packed => 0,
+            LeafEncoding::Standard => 1,
+        },
+        match options.ordering {
+            OrderingMode::Packed => 0,
+            OrderingMode::Address => 1,
+            OrderingMode::LeafHash => 2,
+        },
+        match options.duplicate_policy {
+            DuplicatePolicy::Allow => 0,
+            DuplicatePolicy::Reject => 1,
+            DuplicatePolicy::Sum => 2,
+            DuplicatePolicy::KeepMax => 3,
+            DuplicatePolicy::KeepLast => 4,
+        },
+    ]
+}
+
+fn decode_options(bytes: &[u8]) -> Result<TreeOptions, Error> {
+    let hash_mode = match bytes[0] {
+        0 => HashMode::Ordered,
+        1 => HashMode::Commutative,
+        _ => return Err(Error::InvalidTreeFileError),
+    };
+    let leaf_encoding = match bytes[1] {
+        0 => LeafEncoding::Packed,
+        1 => LeafEncoding::Standard,
+        _ => return Err(Error::InvalidTreeFileError),
+    };
+    let ordering = match bytes[2] {
+        0 => OrderingMode::Packed,
+        1 => OrderingMode::Address,
+        2 => OrderingMode::LeafHash,
+        _ => return Err(Error::InvalidTreeFileError),
+    };
+    let duplicate_policy = match bytes[3] {
+        0 => DuplicatePolicy::Allow,
+        1 => DuplicatePolicy::Reject,
+        2 => DuplicatePolicy::Sum,
+        3 => DuplicatePolicy::KeepMax,
+        4 => DuplicatePolicy::KeepLast,
+        _ => return Err(Error::InvalidTreeFileError),
+    };
+    Ok(TreeOptions {
+        ordering,
+        hash_mode,
+        leaf_encoding,
+        duplicate_policy,
+    })
+}
+
+/// Start and length of every layer of an ordered tree within the nodes, from
+/// the leaves up to the root
+fn ordered_layers(leaf_count: usize) -> Vec<(usize, usize)> {
+    let mut layers = vec![];
+    let mut start = 0;
+    let mut layer_len = leaf_count;
+    loop {
+        layers.push((start, layer_len));
+        if layer_len <= 1 {
+            return layers;
+        }
+        start += layer_len;
+        layer_len = layer_len.div_ceil(2);
+    }
+}
+
+/// First value of `0..len` for which `pred` is false, the range being
+/// partitioned by it
+fn partition_point(len: usize, pred: impl Fn(usize) -> bool) -> usize {
+    let (mut low, mut high) = (0, len);
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if pred(middle) {
+            low = middle + 1;
+        } else {
+            high = middle;
+        }
+    }
+    low
+}
+
+fn node_count(hash_mode: HashMode, leaf_count: usize) -> usize {
+    match hash_mode {
+        HashMode::Ordered => ordered_layers(leaf_count)
+            .last()
+            .map_or(0, |(start, len)| start + len),
+        HashMode::Commutative => 2 * leaf_count - 1,
+    }
+}
+
+/// Writes trees in the tree file format, computing the checksum as it goes
+pub struct TreeFileWriter<W: Write> {
+    writer: W,
+    checksum: Keccak,
+}
+
+impl<W: Write> TreeFileWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            checksum: Keccak::v256(),
+        }
+    }
+
+    fn write_all(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.checksum.update(bytes);
+        Ok(self.writer.write_all(bytes)?)
+    }
+
+    pub fn write_tree<H: MerkleHasher>(
+        mut self,
+        merkle_tree: &AlphabeticMerkleTree<H>,
+    ) -> Result<W, Error> {
+        self.write_all(&TREE_FILE_MAGIC)?;
+        self.write_all(&TREE_FILE_VERSION.to_le_bytes())?;
+        self.write_all(&[H::ID])?;
+        self.write_all(&encode_options(merkle_tree.options()))?;
+        self.write_all(&(merkle_tree.leaves_len() as u64).to_le_bytes())?;
+        for account in merkle_tree.accounts() {
+            self.write_all(&account.to_record())?;
+        }
+        for layer in merkle_tree.node_layers() {
+            for node in layer {
+                self.write_all(node)?;
+            }
+        }
+        // Leaves sorted by hash can't be searched by address without an index
+        if merkle_tree.options().ordering == OrderingMode::LeafHash {
+            let accounts = merkle_tree.accounts();
+            let mut indices: Vec<usize> = (0..accounts.len()).collect();
+            indices.sort_by_key(|index| (accounts[*index].address, *index));
+            for index in indices {
+                self.write_all(&(index as u64).to_le_bytes())?;
+            }
+        }
+
+        let mut checksum = [0u8; CHECKSUM_LEN];
+        self.checksum.finalize(&mut checksum);
+        self.writer.write_all(&checksum)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+pub fn write_tree_file<H: MerkleHasher>(
+    merkle_tree: &AlphabeticMerkleTree<H>,
+    path: impl AsRef<Path>,
+) -> Result<(), Error> {
+    let file = File::create(path)?;
+    TreeFileWriter::new(BufWriter::new(file)).write_tree(merkle_tree)?;
+    Ok(())
+}
+
+/// Memory-mapped tree file serving roots and proofs straight from the file,
+/// written with the hasher `H`.
+///
+/// The checksum is checked once when the file is opened, so the file must not
+/// be modified while it is mapped.
+pub struct MappedTreeFile<H: MerkleHasher = Keccak256> {
+    mmap: Mmap,
+    options: TreeOptions,
+    leaf_count: usize,
+    node_count: usize,
+    /// Start and length of the layers within the nodes, with ordered hashing
+    layers: Vec<(usize, usize)>,
+    hasher: PhantomData<H>,
+}
+
+impl MappedTreeFile {
+    /// Maps the file, rejecting it if the header, size or checksum are wrong
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::open_with_hasher(path)
+    }
+}
+
+impl<H: MerkleHasher> MappedTreeFile<H> {
+    /// Same as [`MappedTreeFile::open`] for a file written with the hasher `H`
+    pub fn open_with_hasher(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let file = File::open(path)?;
+        // SAFETY: the map is read only and the file is expected to stay
+        // unchanged while it is open, as documented on the type
+        let mmap = unsafe { Mmap::map(&file)? };
+        Self::from_mmap(mmap)
+    }
+
+    fn from_mmap(mmap: Mmap) -> Result<Self, Error> {
+        if mmap.len() < HEADER_LEN + CHECKSUM_LEN || mmap[..8] != TREE_FILE_MAGIC {
+            return Err(Error::InvalidTreeFileError);
+        }
+        let version = u32::from_le_bytes(mmap[8..12].try_into().expect("4 bytes"));
+        if version != TREE_FILE_VERSION {
+            return Err(Error::InvalidTreeFileError);
+        }
+        let options = decode_options(&mmap[13..17])?;
+        let leaf_count = usize::try_from(u64::from_le_bytes(
+            mmap[17..25].try_into().expect("8 bytes"),
+        ))
+        .map_err(|_| Error::InvalidTreeFileError)?;
+        // Checked before computing sizes, so a corrupted count can't overflow them
+        if leaf_count == 0 || leaf_count > mmap.len() / ACCOUNT_RECORD_LEN {
+            return Err(Error::InvalidTreeFileError);
+        }
+
+        let node_count = node_count(options.hash_mode, leaf_count);
+        let address_index_len = match options.ordering {
+            OrderingMode::LeafHash => leaf_count * 8,
+            _ => 0,
+        };
+        let expected_len = HEADER_LEN
+            + leaf_count * ACCOUNT_RECORD_LEN
+            + node_count * 32
+            + address_index_len
+            + CHECKSUM_LEN;
+        if expected_len != mmap.len() {
+            return Err(Error::InvalidTreeFileError);
+        }
+
+        let (content, checksum) = mmap.split_at(mmap.len() - CHECKSUM_LEN);
+        let mut keccak = Keccak::v256();
+        keccak.update(content);
+        let mut expected_checksum = [0u8; CHECKSUM_LEN];
+        keccak.finalize(&mut expected_checksum);
+        if checksum != expected_checksum {
+            return Err(Error::InvalidTreeFileError);
+        }
+
+        if mmap[12] != H::ID {
+            return Err(Error::TreeFileHasherMismatchError(mmap[12], H::ID));
+        }
+
+        let layers = match options.hash_mode {
+            HashMode::Ordered => ordered_layers(leaf_count),
+            HashMode::Commutative => vec![],
+        };
+        let mapped = Self {
+            mmap,
+            options,
+            leaf_count,
+            node_count,
+            layers,
+            hasher: PhantomData,
+        };
+        if address_index_len > 0
+            && (0..leaf_count).any(|position| mapped.address_index(position) >= leaf_count)
+        {
+            return Err(Error::InvalidTreeFileError);
+        }
+        Ok(mapped)
+    }
+
+    pub fn options(&self) -> TreeOptions {
+        self.options
+    }
+
+    pub fn leaves_len(&self) -> usize {
+        self.leaf_count
+    }
+
+    /// Account of the leaf at `index`
+    pub fn account(&self, index: usize) -> Option<AccountWithBalance> {
+        if index >= self.leaf_count {
+            return None;
+        }
+        let start = HEADER_LEN + index * ACCOUNT_RECORD_LEN;
+        let record = self.mmap[start..start + ACCOUNT_RECORD_LEN]
+            .try_into()
+            .expect("records are ACCOUNT_RECORD_LEN bytes long");
+        Some(AccountWithBalance::from_record(record))
+    }
+
+    fn node(&self, index: usize) -> [u8; 32] {
+        let start = HEADER_LEN + self.leaf_count * ACCOUNT_RECORD_LEN + index * 32;
+        self.mmap[start..start + 32]
+            .try_into()
+            .expect("nodes are 32 bytes long")
+    }
+
+    /// Hash of the leaf at `index`
+    fn leaf_node(&self, index: usize) -> [u8; 32] {
+        match self.options.hash_mode {
+            HashMode::Ordered => self.node(index),
+            HashMode::Commutative => self.node(2 * self.leaf_count - 2 - index),
+        }
+    }
+
+    /// Leaf index at `position` among the leaves sorted by address, with leaf
+    /// hash ordering
+    fn address_index(&self, position: usize) -> usize {
+        let start =
+            HEADER_LEN + self.leaf_count * ACCOUNT_RECORD_LEN + self.node_count * 32 + position * 8;
+        let index = u64::from_le_bytes(
+            self.mmap[start..start + 8]
+                .try_into()
+                .expect("indices are 8 bytes long"),
+        );
+        usize::try_from(index).unwrap_or(usize::MAX)
+    }
+
+    pub fn root(&self) -> [u8; 32] {
+        match self.options.hash_mode {
+            HashMode::Ordered => self.node(self.layers[self.layers.len() - 1].0),
+            HashMode::Commutative => self.node(0),
+        }
+    }
+
+    /// First leaf index for which `pred` is false, leaves being partitioned by it
+    fn partition_point(&self, pred: impl Fn(&AccountWithBalance) -> bool) -> usize {
+        partition_point(self.leaf_count, |index| {
+            pred(&self.account(index).expect("index is within the leaves"))
+        })
+    }
+
+    /// Leaves sorted by hash are searched by the hash of the account
+    pub fn index_of(&self, account: &AccountWithBalance) -> Option<usize> {
+        let index = match self.options.ordering {
+            OrderingMode::LeafHash => {
+                let leaf_hash = account.leaf_hash_with_hasher::<H>(self.options.leaf_encoding);
+                partition_point(self.leaf_count, |index| self.leaf_node(index) < leaf_hash)
+            }
+            _ => self.partition_point(|x| x < account),
+        };
+        (self.account(index)? == *account).then_some(index)
+    }
+
+    /// First leaf of the address. Leaves sorted by hash are searched through
+    /// the address index of the file.
+    pub fn index_of_address(&self, address: Address) -> Option<usize> {
+        if self.options.ordering == OrderingMode::LeafHash {
+            let position = partition_point(self.leaf_count, |position| {
+                self.account(self.address_index(position))
+                    .is_some_and(|x| x.address < address)
+            });
+            let index = (position < self.leaf_count).then(|| self.address_index(position))?;
+            return (self.account(index)?.address == address).then_some(index);
+        }
+        let index = self.partition_point(|x| x.address < address);
+        (self.account(index)?.address == address).then_some(index)
+    }
+
+    fn proof_hashes(&self, index: usize) -> Vec<[u8; 32]> {
+        let mut proof = vec![];
+        match self.options.hash_mode {
+            HashMode::Ordered => {
+                let mut index = index;
+                for (start, layer_len) in &self.layers[..self.layers.len() - 1] {
+                    if index ^ 1 < *layer_len {
+                        proof.push(self.node(start + (index ^ 1)));
+                    }
+                    index /= 2;
+                }
+            }
+            HashMode::Commutative => {
+                let mut tree_index = 2 * self.leaf_count - 2 - index;
+                while tree_index > 0 {
+                    let sibling = if tree_index.is_multiple_of(2) {
+                        tree_index - 1
+                    } else {
+                        tree_index + 1
+                    };
+                    proof.push(self.node(sibling));
+                    tree_index = (tree_index - 1) / 2;
+                }
+            }
+        }
+        proof
+    }
+
+    pub fn generate_proof_hashes(
+        &self,
+        account: &AccountWithBalance,
+    ) -> Result<Vec<[u8; 32]>, Error> {
+        let index = self.index_of(account).ok_or(Error::AccountNotFoundError)?;
+        Ok(self.proof_hashes(index))
+    }
+
+    pub fn generate_proof_of_inclusion(
+        &self,
+        account: &AccountWithBalance,
+    ) -> Result<Vec<u8>, Error> {
+        Ok(self.generate_proof_hashes(account)?.concat())
+    }
+
+    pub fn generate_proof_of_absense(
+        &self,
+        account: &AccountWithBalance,
+    ) -> Result<AbsenceProof, Error> {
+        if self.options.ordering == OrderingMode::LeafHash {
+            return Err(Error::AccountOrderingRequiredError);
+        }
+        let next_index = self.partition_point(|x| x < account);
+        if self.account(next_index) == Some(*account) {
+            return Err(Error::AccountAlreadyExistsError);
+        }
+        self.absence_proof(next_index)
+    }
+
+    /// Proves the balance of an address, or that the address has no leaf.
+    /// Requires the tree to be ordered by address.
+    pub fn generate_address_proof(&self, address: Address) -> Result<AddressProof, Error> {
+        if self.options.ordering != OrderingMode::Address {
+            return Err(Error::AddressOrderingRequiredError);
+        }
+        if let Some(index) = self.index_of_address(address) {
+            return Ok(AddressProof::Included {
+                leaf: self.leaf_proof(index),
+                leaf_count: self.leaf_count,
+                hash_mode: self.options.hash_mode,
+                leaf_encoding: self.options.leaf_encoding,
+            });
+        }
+        let next_index = self.partition_point(|x| x.address < address);
+        Ok(AddressProof::Absent(self.absence_proof(next_index)?))
+    }
+
+    /// Proof with the leaves on both sides of `next_index`
+    fn absence_proof(&self, next_index: usize) -> Result<AbsenceProof, Error> {
+        if self.options.hash_mode == HashMode::Commutative {
+            return Err(Error::OrderedHashingRequiredError);
+        }
+        Ok(AbsenceProof {
+            left: next_index
+                .checked_sub(1)
+                .map(|index| self.leaf_proof(index)),
+            right: (next_index < self.leaf_count).then(|| self.leaf_proof(next_index)),
+            leaf_count: self.leaf_count,
+            hash_mode: self.options.hash_mode,
+            leaf_encoding: self.options.leaf_encoding,
+        })
+    }
+
+    fn leaf_proof(&self, index: usize) -> LeafProof {
+        LeafProof {
+            account: self.account(index).expect("index is within the leaves"),
+            index,
+            proof: self.proof_hashes(index).concat(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::NamedTempFile;
+
+    use crate::test_utils::random_accounts;
+
+    fn write_temp_file(merkle_tree: &AlphabeticMerkleTree) -> NamedTempFile {
+        let file = NamedTempFile::new().unwrap();
+        write_tree_file(merkle_tree, file.path()).unwrap();
+        file
+    }
+
+    #[test]
+    fn test_mapped_tree_matches_tree() {
+        let address_options = TreeOptions {
+            ordering: OrderingMode::Address,
+            duplicate_policy: DuplicatePolicy::KeepLast,
+            ..Default::default()
+        };
+        let absent = random_accounts(8, 100);
+        for count in [1, 2, 5, 13, 64] {
+            let accounts = random_accounts(count, count as u64);
+            for options in [
+                TreeOptions::default(),
+                address_options,
+                TreeOptions::standard(),
+                TreeOptions {
+                    ordering: OrderingMode::LeafHash,
+                    ..TreeOptions::standard()
+                },
+                TreeOptions {
+                    ordering: OrderingMode::LeafHash,
+                    ..Default::default()
+                },
+            ] {
+                let tree = AlphabeticMerkleTree::with_options(&accounts, options).unwrap();
+                let file = write_temp_file(&tree);
+                let mapped = MappedTreeFile::open(file.path()).unwrap();
+
+                assert_eq!(mapped.options(), options);
+                assert_eq!(mapped.leaves_len(), count);
+                assert_eq!(mapped.root(), tree.root());
+                for (index, account) in tree.accounts().iter().enumerate() {
+                    assert_eq!(mapped.index_of(account), Some(index));
+                    assert_eq!(
+                        mapped.index_of_address(account.address),
+                        tree.index_of_key(account.address)
+                    );
+                    assert_eq!(
+                        mapped.generate_proof_of_inclusion(account).unwrap(),
+                        tree.generate_proof_of_inclusion(account).unwrap()
+                    );
+                }
+                assert_eq!(mapped.index_of(&absent[0]), None);
+                assert_eq!(mapped.index_of_address(absent[0].address), None);
+                if options.hash_mode == HashMode::Ordered
+                    && options.ordering != OrderingMode::LeafHash
+                {
+                    for account in absent.iter() {
+                        assert_eq!(
+                            mapped.generate_proof_of_absense(account).unwrap(),
+                            tree.generate_proof_of_absense(account).unwrap()
+                        );
+                    }
+                }
+                if options.ordering == OrderingMode::Address {
+                    for address in [accounts[0].address, absent[0].address] {
+                        assert_eq!(
+                            mapped.generate_address_proof(address).unwrap(),
+                            tree.generate_address_proof(address).unwrap()
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_mapped_tree_errors() {
+        let accounts = random_accounts(5, 0);
+        let tree = AlphabeticMerkleTree::with_options(&accounts, TreeOptions::standard()).unwrap();
+        let mapped = MappedTreeFile::open(write_temp_file(&tree).path()).unwrap();
+
+        assert!(matches!(
+            mapped.generate_proof_of_absense(&random_accounts(1, 1)[0]),
+            Err(Error::OrderedHashingRequiredError)
+        ));
+        assert!(matches!(
+            mapped.generate_proof_of_inclusion(&random_accounts(1, 1)[0]),
+            Err(Error::AccountNotFoundError)
+        ));
+        assert!(matches!(
+            mapped.generate_address_proof(accounts[0].address),
+            Err(Error::AddressOrderingRequiredError)
+        ));
+    }
+
+    #[test]
+    fn test_corrupted_tree_file() {
+        let tree = AlphabeticMerkleTree::new(&random_accounts(5, 0)).unwrap();
+        let bytes = TreeFileWriter::new(vec![]).write_tree(&tree).unwrap();
+
+        let mut corrupted = vec![];
+        // Flipped bits anywhere in the file are rejected
+        for position in [
+            0,
+            8,
+            12,
+            13,
+            17,
+            HEADER_LEN + 3,
+            bytes.len() - 40,
+            bytes.len() - 1,
+        ] {
+            let mut file_bytes = bytes.clone();
+            file_bytes[position] ^= 1;
+            corrupted.push(file_bytes);
+        }
+        corrupted.push(bytes[..bytes.len() - 32].to_vec());
+        corrupted.push([bytes.as_slice(), &[0u8; 32]].concat());
+
+        for file_bytes in corrupted {
+            let file = NamedTempFile::new().unwrap();
+            std::fs::write(file.path(), &file_bytes).unwrap();
+            assert!(matches!(
+                MappedTreeFile::open(file.path()),
+                Err(Error::InvalidTreeFileError)
+            ));
+        }
+    }
+
+    #[cfg(feature = "sha256")]
+    #[test]
+    fn test_tree_file_hasher() {
+        use crate::hasher::Sha256;
+
+        let accounts = random_accounts(5, 0);
+        let tree =
+            AlphabeticMerkleTree::<Sha256>::with_hasher(&accounts, TreeOptions::default()).unwrap();
+        let file = NamedTempFile::new().unwrap();
+        write_tree_file(&tree, file.path()).unwrap();
+
+        let mapped = MappedTreeFile::<Sha256>::open_with_hasher(file.path()).unwrap();
+        assert_eq!(mapped.root(), tree.root());
+        assert!(matches!(
+            MappedTreeFile::open(file.path()),
+            Err(Error::TreeFileHasherMismatchError(1, 0))
+        ));
+    }
+}
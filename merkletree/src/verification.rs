@@ -0,0 +1,264 @@
+//! Single leaf proofs are checked by `merkletree-verifier`, with the hash
+//! function of the tree.
+
+use merkletree_verifier::verification as verifier;
+
+use crate::account_with_balance::LeafEncoding;
+use crate::alphabetic_merkle_tree::{HashMode, TreeOptions};
+use crate::hasher::{Keccak256, MerkleHasher};
+use crate::leaf::Leaf;
+use crate::merkle_tree::Error;
+
+pub use merkletree_verifier::verification::expected_proof_len;
+
+impl From<verifier::Error> for Error {
+    fn from(error: verifier::Error) -> Self {
+        match error {
+            verifier::Error::EmptyAccountsListError => Error::EmptyAccountsListError,
+            verifier::Error::LeafIndexOutOfRangeError => Error::LeafIndexOutOfRangeError,
+            verifier::Error::MerkleProofParsingError => Error::MerkleProofParsingError,
+            verifier::Error::OrderedHashingRequiredError => Error::OrderedHashingRequiredError,
+        }
+    }
+}
+
+impl From<HashMode> for verifier::HashMode {
+    fn from(hash_mode: HashMode) -> Self {
+        match hash_mode {
+            HashMode::Ordered => verifier::HashMode::Ordered,
+            HashMode::Commutative => verifier::HashMode::Commutative,
+        }
+    }
+}
+
+/// Number of sibling hashes a multiproof has for sorted, deduplicated leaf
+/// indices. Siblings that are themselves proven are not part of the proof.
+pub fn expected_multi_proof_len(indices: &[usize], leaf_count: usize) -> usize {
+    let mut indices = indices.to_vec();
+    let mut layer_len = leaf_count;
+    let mut proof_len = 0;
+    while layer_len > 1 {
+        for (position, index) in indices.iter().enumerate() {
+            let sibling = index ^ 1;
+            let proven = if sibling < *index {
+                position > 0 && indices[position - 1] == sibling
+            } else {
+                indices.get(position + 1) == Some(&sibling)
+            };
+            if sibling < layer_len && !proven {
+                proof_len += 1;
+            }
+        }
+        indices = indices.iter().map(|index| index / 2).collect();
+        indices.dedup();
+        layer_len = layer_len.div_ceil(2);
+    }
+    proof_len
+}
+
+/// Splits a serialized proof into its sibling hashes
+pub fn proof_hashes_from_bytes(proof_bytes: &[u8]) -> Result<Vec<[u8; 32]>, Error> {
+    Ok(verifier::proof_hashes_from_bytes(proof_bytes)?)
+}
+
+/// Root of the tree in which `leaf_hash` is the leaf at `index`
+fn proof_root<H: MerkleHasher>(
+    hash_mode: HashMode,
+    leaf_hash: [u8; 32],
+    proof_bytes: &[u8],
+    index: usize,
+    leaf_count: usize,
+) -> Result<[u8; 32], Error> {
+    Ok(verifier::proof_root_with_hash_pair(
+        hash_mode.into(),
+        leaf_hash,
+        proof_bytes,
+        index,
+        leaf_count,
+        H::hash_pair,
+    )?)
+}
+
+/// Root of the ordered tree in which `leaf_hash` is the leaf at `index`
+pub(crate) fn ordered_proof_root<H: MerkleHasher>(
+    leaf_hash: [u8; 32],
+    proof_bytes: &[u8],
+    index: usize,
+    leaf_count: usize,
+) -> Result<[u8; 32], Error> {
+    proof_root::<H>(HashMode::Ordered, leaf_hash, proof_bytes, index, leaf_count)
+}
+
+/// Checks that `leaf_hash` is the leaf at `index` of the tree with the given root
+pub fn verify_leaf_inclusion(
+    root: [u8; 32],
+    leaf_hash: [u8; 32],
+    proof_bytes: &[u8],
+    index: usize,
+    leaf_count: usize,
+) -> Result<bool, Error> {
+    verify_leaf_inclusion_with_mode(
+        HashMode::Ordered,
+        root,
+        leaf_hash,
+        proof_bytes,
+        index,
+        leaf_count,
+    )
+}
+
+/// Same as [`verify_leaf_inclusion`] for a tree built with the given hash mode
+pub fn verify_leaf_inclusion_with_mode(
+    hash_mode: HashMode,
+    root: [u8; 32],
+    leaf_hash: [u8; 32],
+    proof_bytes: &[u8],
+    index: usize,
+    leaf_count: usize,
+) -> Result<bool, Error> {
+    verify_leaf_inclusion_with_hasher::<Keccak256>(
+        hash_mode,
+        root,
+        leaf_hash,
+        proof_bytes,
+        index,
+        leaf_count,
+    )
+}
+
+/// Same as [`verify_leaf_inclusion_with_mode`] for a tree hashed with `H`
+pub fn verify_leaf_inclusion_with_hasher<H: MerkleHasher>(
+    hash_mode: HashMode,
+    root: [u8; 32],
+    leaf_hash: [u8; 32],
+    proof_bytes: &[u8],
+    index: usize,
+    leaf_count: usize,
+) -> Result<bool, Error> {
+    let proof_root = proof_root::<H>(hash_mode, leaf_hash, proof_bytes, index, leaf_count)?;
+    Ok(proof_root == root)
+}
+
+/// Checks that `account` is the leaf at `index` of the tree with the given root
+pub fn verify_inclusion(
+    root: [u8; 32],
+    account: &impl Leaf,
+    proof_bytes: &[u8],
+    index: usize,
+    leaf_count: usize,
+) -> Result<bool, Error> {
+    verify_leaf_inclusion(
+        root,
+        account.leaf_hash(LeafEncoding::Packed),
+        proof_bytes,
+        index,
+        leaf_count,
+    )
+}
+
+/// Same as [`verify_inclusion`] for a tree built with the given hash mode and
+/// leaf encoding
+pub fn verify_inclusion_with_options(
+    options: TreeOptions,
+    root: [u8; 32],
+    account: &impl Leaf,
+    proof_bytes: &[u8],
+    index: usize,
+    leaf_count: usize,
+) -> Result<bool, Error> {
+    verify_inclusion_with_hasher::<Keccak256>(
+        options,
+        root,
+        account,
+        proof_bytes,
+        index,
+        leaf_count,
+    )
+}
+
+/// Same as [`verify_inclusion_with_options`] for a tree hashed with `H`
+pub fn verify_inclusion_with_hasher<H: MerkleHasher>(
+    options: TreeOptions,
+    root: [u8; 32],
+    account: &impl Leaf,
+    proof_bytes: &[u8],
+    index: usize,
+    leaf_count: usize,
+) -> Result<bool, Error> {
+    verify_leaf_inclusion_with_hasher::<H>(
+        options.hash_mode,
+        root,
+        account.leaf_hash_with_hasher::<H>(options.leaf_encoding),
+        proof_bytes,
+        index,
+        leaf_count,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::account_with_balance::AccountWithBalance;
+    use crate::alphabetic_merkle_tree::AlphabeticMerkleTree;
+    use crate::test_utils::fixed_accounts;
+
+    #[test]
+    fn test_expected_proof_len() {
+        assert_eq!(expected_proof_len(0, 1), 0);
+        assert_eq!(expected_proof_len(0, 5), 3);
+        assert_eq!(expected_proof_len(3, 5), 3);
+        // The fifth leaf is promoted on the first two layers
+        assert_eq!(expected_proof_len(4, 5), 1);
+    }
+
+    #[test]
+    fn test_expected_multi_proof_len() {
+        assert_eq!(expected_multi_proof_len(&[1], 5), expected_proof_len(1, 5));
+        assert_eq!(expected_multi_proof_len(&[0, 1], 5), 2);
+        assert_eq!(expected_multi_proof_len(&[0, 4], 5), 2);
+        assert_eq!(expected_multi_proof_len(&[0, 1, 2, 3, 4], 5), 0);
+    }
+
+    #[test]
+    fn test_verify_inclusion() {
+        let accounts = fixed_accounts();
+        let tree = AlphabeticMerkleTree::new(&accounts).unwrap();
+
+        for (index, account) in accounts.iter().enumerate() {
+            let proof = tree.generate_proof_of_inclusion(account).unwrap();
+            assert!(verify_inclusion(tree.root(), account, &proof, index, accounts.len()).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_verify_inclusion_wrong_account() {
+        let accounts = fixed_accounts();
+        let tree = AlphabeticMerkleTree::new(&accounts).unwrap();
+        let proof = tree.generate_proof_of_inclusion(&accounts[1]).unwrap();
+
+        let wrong_balance =
+            AccountWithBalance::new("5754284f345afc66a98fbB0a0Afe71e0F007B949", "1");
+        assert!(!verify_inclusion(tree.root(), &wrong_balance, &proof, 1, accounts.len()).unwrap());
+        assert!(!verify_inclusion(tree.root(), &accounts[0], &proof, 1, accounts.len()).unwrap());
+    }
+
+    #[test]
+    fn test_verify_inclusion_malformed_proof() {
+        let accounts = fixed_accounts();
+        let tree = AlphabeticMerkleTree::new(&accounts).unwrap();
+        let proof = tree.generate_proof_of_inclusion(&accounts[1]).unwrap();
+
+        let truncated = verify_inclusion(tree.root(), &accounts[1], &proof[..40], 1, 5);
+        assert!(matches!(truncated, Err(Error::MerkleProofParsingError)));
+
+        let missing_hash = verify_inclusion(tree.root(), &accounts[1], &proof[32..], 1, 5);
+        assert!(matches!(missing_hash, Err(Error::MerkleProofParsingError)));
+
+        let out_of_range = verify_inclusion(tree.root(), &accounts[1], &proof, 5, 5);
+        assert!(matches!(out_of_range, Err(Error::LeafIndexOutOfRangeError)));
+
+        let empty_tree = verify_inclusion(tree.root(), &accounts[1], &proof, 0, 0);
+        assert!(matches!(empty_tree, Err(Error::EmptyAccountsListError)));
+    }
+}
@@ -5,6 +5,7 @@ mod m20230828_013249_create_address_domain;
 mod m20230828_060306_create_proposal_table;
 mod m20230828_071737_create_account_table;
 mod m20230828_090601_link_account_proposal_tables;
+mod m20261017_000000_add_proposal_backend;
 
 pub struct Migrator;
 
@@ -17,6 +18,7 @@ impl MigratorTrait for Migrator {
             Box::new(m20230828_060306_create_proposal_table::Migration),
             Box::new(m20230828_071737_create_account_table::Migration),
             Box::new(m20230828_090601_link_account_proposal_tables::Migration),
+            Box::new(m20261017_000000_add_proposal_backend::Migration),
         ]
     }
 }
@@ -4,11 +4,13 @@ use sea_orm::{
     RuntimeErr, Set, TransactionTrait,
 };
 
+use crate::utils::errors::ServiceError;
 use crate::utils::pagination::Pagination;
 use entity::prelude::*;
 use merkletree::{
     account_with_balance::AccountWithBalance,
-    merkle_tree::{get_merkle_root, Error},
+    backend::{AnyMerkleTree, BackendKind},
+    merkle_tree::Error,
 };
 
 async fn list_proposals_paginated(
@@ -69,21 +71,45 @@ pub async fn get_proposal_with_accounts(
         })
 }
 
-fn get_merkletree_root(accounts: Vec<AccountsActiveModel>) -> Result<String, Error> {
-    let accounts: Vec<AccountWithBalance> = accounts
+/// Tree of the proposal accounts, built with the backend of the proposal
+pub async fn get_proposal_tree(
+    db: &DatabaseConnection,
+    id: i32,
+) -> Result<AnyMerkleTree, ServiceError> {
+    let proposal = get_proposal_with_accounts(db, id).await?;
+    Ok(build_proposal_tree(&proposal)?)
+}
+
+fn build_proposal_tree(proposal: &ProposalsModel) -> Result<AnyMerkleTree, Error> {
+    let backend: BackendKind = proposal.backend.parse()?;
+    let accounts = proposal
+        .accounts
+        .iter()
+        .map(|account| {
+            AccountWithBalance::try_from((account.address.as_str(), account.balance.as_str()))
+        })
+        .collect::<Result<Vec<AccountWithBalance>, Error>>()?;
+    AnyMerkleTree::new(backend, &accounts)
+}
+
+fn get_merkletree_root(
+    accounts: Vec<AccountsActiveModel>,
+    backend: BackendKind,
+) -> Result<String, Error> {
+    let accounts = accounts
         .iter()
         .map(|account| {
             let address = account.address.clone().take();
             let balance = account.balance.clone().take();
             match (address, balance) {
                 (Some(address), Some(balance)) => {
-                    AccountWithBalance::new(address.as_str(), balance.as_str())
+                    AccountWithBalance::try_from((address.as_str(), balance.as_str()))
                 }
                 _ => panic!("Error getting accounts"), //TODO: handle error
             }
         })
-        .collect();
-    let merkle_root = get_merkle_root(&accounts)?;
+        .collect::<Result<Vec<AccountWithBalance>, Error>>()?;
+    let merkle_root = AnyMerkleTree::new(backend, &accounts)?.root();
     Ok(hex::encode(merkle_root))
 }
 
@@ -97,9 +123,16 @@ pub async fn insert_proposal(
             Box::pin(async move {
                 let mut accounts = account_models.clone();
 
-                let merkle_root = get_merkletree_root(accounts.clone())
+                let backend = match proposal_data.backend.clone().take() {
+                    Some(backend) => backend
+                        .parse()
+                        .map_err(|e| DbErr::Custom(format!("{}", e)))?,
+                    None => BackendKind::default(),
+                };
+                let merkle_root = get_merkletree_root(accounts.clone(), backend)
                     .map_err(|e| DbErr::Custom(format!("Error creating merkle tree: {}", e)))?;
                 proposal_data.root_hash = Set(merkle_root);
+                proposal_data.backend = Set(backend.to_string());
 
                 let proposal: ProposalsActiveModel = proposal_data.save(txn).await?;
 
@@ -1,4 +1,15 @@
+use merkletree::merkle_tree::Error as MerkleTreeError;
 use sea_orm::{error::DbErr, RuntimeErr};
+use thiserror::Error;
+
+/// Error of the queries that build the merkle tree of a proposal
+#[derive(Error, Debug)]
+pub enum ServiceError {
+    #[error(transparent)]
+    Database(#[from] DbErr),
+    #[error("Error creating merkle tree: {0}")]
+    MerkleTree(#[from] MerkleTreeError),
+}
 
 pub fn get_sql_error(error: DbErr) -> sqlx::error::ErrorKind {
     match error {